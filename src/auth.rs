@@ -0,0 +1,102 @@
+//! Credentials for authenticating requests in their `Authorization` header.
+
+use std::fmt::{self, Debug};
+
+use http_types::auth::BasicAuth;
+use http_types::headers::HeaderValue;
+use http_types::{Error, StatusCode};
+
+/// Credentials to authenticate a request with, in its `Authorization` header.
+///
+/// Set them on a request with `RequestBuilder::basic_auth` or `RequestBuilder::bearer_auth`, or
+/// for each request a client sends with `ClientBuilder::credentials`. Passwords and tokens are
+/// left out of their `Debug` output, the `Logger` middleware redacts the header, and the
+/// `H1Client` drops it when following a redirect to another origin.
+///
+/// ```
+/// use http_client::Credentials;
+///
+/// let credentials = Credentials::basic("aladdin", "opensesame");
+/// assert_eq!(format!("{:?}", credentials), r#"Credentials { scheme: "Basic", username: "aladdin", .. }"#);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials(Scheme);
+
+#[derive(Clone, PartialEq, Eq)]
+enum Scheme {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    /// A `username` and `password`, sent base64 encoded in the `Basic` scheme of RFC 7617.
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self(Scheme::Basic {
+            username: username.into(),
+            password: password.into(),
+        })
+    }
+
+    /// A `token`, such as an OAuth 2.0 access token, sent as it is in the `Bearer` scheme of
+    /// RFC 6750.
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Self(Scheme::Bearer(token.into()))
+    }
+
+    /// The value of the `Authorization` header, failing if a bearer token is empty or has
+    /// characters other than visible ASCII, which could end the header early.
+    pub(crate) fn header_value(&self) -> Result<HeaderValue, Error> {
+        match &self.0 {
+            Scheme::Basic { username, password } => Ok(BasicAuth::new(username, password).value()),
+            Scheme::Bearer(token)
+                if !token.is_empty() && token.bytes().all(|b| b.is_ascii_graphic()) =>
+            {
+                format!("Bearer {}", token).parse()
+            }
+            Scheme::Bearer(_) => Err(Error::from_str(
+                StatusCode::BadRequest,
+                "invalid bearer token",
+            )),
+        }
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Scheme::Basic { username, .. } => f
+                .debug_struct("Credentials")
+                .field("scheme", &"Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Scheme::Bearer(_) => f
+                .debug_struct("Credentials")
+                .field("scheme", &"Bearer")
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_header_values() -> Result<(), Error> {
+        let value = Credentials::basic("aladdin", "open sesame: é").header_value()?;
+        assert_eq!(value, "Basic YWxhZGRpbjpvcGVuIHNlc2FtZTogw6k=");
+        let value = Credentials::bearer("mF_9.B5f-4.1JqM").header_value()?;
+        assert_eq!(value, "Bearer mF_9.B5f-4.1JqM");
+        for token in ["", "token\r\nX-Injected: 1", "two words"] {
+            assert!(
+                Credentials::bearer(token).header_value().is_err(),
+                "{:?}",
+                token
+            );
+        }
+
+        let debug = format!("{:?}", Credentials::bearer("secret"));
+        assert!(!debug.contains("secret"), "{}", debug);
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! A synchronous client, for programs that don't run an async runtime of their own, like CLI tools
+//! and build scripts.
+//!
+//! ```no_run
+//! # fn main() -> http_types::Result<()> {
+//! use http_client::blocking::Client;
+//! use http_client::Request;
+//!
+//! let client = Client::new();
+//! let mut res = client.send(Request::get("https://example.com/"))?;
+//! assert!(res.status().is_success());
+//! let body = res.body_string()?;
+//! # Ok(()) }
+//! ```
+
+use std::io::{self, Read};
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use futures_lite::future::block_on;
+use futures_lite::AsyncReadExt;
+use http_types::StatusCode;
+
+use crate::{Error, HttpClient, Request};
+
+/// A request handed to the runtime thread, with where to send its response.
+type Job = (Request, Sender<Result<crate::Response, Error>>);
+
+/// Sends requests with an async `Client`, blocking until their response arrives.
+///
+/// Requests are handed to a thread of its own, which sends each of them on async-std's executor,
+/// so requests sent from several threads at once share the client's connection pools and run
+/// concurrently. The thread stops once the client and all its clones are dropped.
+#[derive(Clone, Debug)]
+pub struct Client {
+    jobs: Arc<Mutex<Sender<Job>>>,
+}
+
+impl Client {
+    /// Create a client with the default configuration.
+    pub fn new() -> Self {
+        Self::with_client(crate::Client::new())
+    }
+
+    /// Send requests with `client`, as configured by its `ClientBuilder`.
+    pub fn with_client(client: crate::Client) -> Self {
+        let (jobs, requests) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("http-client-blocking".into())
+            .spawn(move || {
+                for (req, reply) in requests {
+                    let client = client.clone();
+                    async_std::task::spawn(async move {
+                        let _ = reply.send(client.send(req).await);
+                    });
+                }
+            })
+            .expect("failed to spawn the client's runtime thread");
+        Self {
+            jobs: Arc::new(Mutex::new(jobs)),
+        }
+    }
+
+    /// Send `req`, blocking until the head of its response arrives. Its body is read as it's
+    /// asked for.
+    pub fn send(&self, req: impl Into<Request>) -> Result<Response, Error> {
+        let (reply, response) = mpsc::channel();
+        let sent = self.jobs.lock().unwrap().send((req.into(), reply));
+        match sent.ok().and_then(|()| response.recv().ok()) {
+            Some(res) => Ok(Response(res?)),
+            None => Err(Error::from_str(
+                StatusCode::InternalServerError,
+                "the client's runtime thread stopped",
+            )),
+        }
+    }
+
+    /// Send a `GET` request to `url`.
+    pub fn get(&self, url: impl AsRef<str>) -> Result<Response, Error> {
+        let url = http_types::Url::parse(url.as_ref())
+            .map_err(|e| Error::new(StatusCode::BadRequest, e))?;
+        self.send(Request::get(url))
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<crate::Client> for Client {
+    fn from(client: crate::Client) -> Self {
+        Self::with_client(client)
+    }
+}
+
+/// A response, whose body is read blocking with `Read`, or at once with `body_string` and
+/// `body_bytes`. Its head is accessed through the async `Response` it derefs to.
+#[derive(Debug)]
+pub struct Response(crate::Response);
+
+impl Response {
+    /// Read the whole body as a UTF-8 string.
+    pub fn body_string(&mut self) -> Result<String, Error> {
+        block_on(self.0.body_string())
+    }
+
+    /// Read the whole body.
+    pub fn body_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        block_on(self.0.body_bytes())
+    }
+
+    /// Take the async response, to read its body asynchronously.
+    pub fn into_inner(self) -> crate::Response {
+        self.0
+    }
+}
+
+impl Deref for Response {
+    type Target = crate::Response;
+
+    fn deref(&self) -> &crate::Response {
+        &self.0
+    }
+}
+
+impl DerefMut for Response {
+    fn deref_mut(&mut self) -> &mut crate::Response {
+        &mut self.0
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        block_on(self.0.read(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_trait, Response};
+
+    /// Answers every request with its URL.
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl HttpClient for Echo {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(req.url().as_str());
+            Ok(res)
+        }
+    }
+
+    #[test]
+    fn sends_blocking() -> Result<(), Error> {
+        let client = Client::with_client(crate::Client::with_http_client(Echo));
+        let threads = (0..4)
+            .map(|i| {
+                let client = client.clone();
+                thread::spawn(move || -> Result<(), Error> {
+                    let url = format!("http://example.test/{}", i);
+                    let mut res = client.get(&url)?;
+                    assert_eq!(res.status(), StatusCode::Ok);
+                    let mut body = String::new();
+                    res.read_to_string(&mut body)?;
+                    assert_eq!(body, url);
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+
+        let err = client.get("not a url").unwrap_err();
+        assert_eq!(err.status(), StatusCode::BadRequest);
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+//! Telling the outcomes of conditional requests apart.
+
+use http_types::conditional::{ETag, LastModified};
+use http_types::StatusCode;
+
+use crate::Response;
+
+/// The response to a conditional request, built with e.g. `RequestBuilder::if_none_match`,
+/// sorted by whether its preconditions held.
+///
+/// ```
+/// use http_client::{Conditional, Response};
+/// use http_types::StatusCode;
+///
+/// let res = Response::new(StatusCode::NotModified);
+/// assert!(matches!(Conditional::from(res), Conditional::NotModified(_)));
+/// ```
+#[derive(Debug)]
+pub enum Conditional {
+    /// `304 Not Modified`: the representation the request's validators identify, e.g. one the
+    /// caller cached, is still current.
+    NotModified(Response),
+    /// `412 Precondition Failed`: the resource changed since the validators were taken, e.g. so
+    /// an `If-Match` update wasn't applied.
+    PreconditionFailed(Response),
+    /// Any other response, such as the full, changed representation.
+    Modified(Response),
+}
+
+impl Conditional {
+    /// Whether the representation the request's validators identify is still current.
+    pub fn is_not_modified(&self) -> bool {
+        matches!(self, Conditional::NotModified(_))
+    }
+
+    /// The response, whatever the outcome.
+    pub fn into_response(self) -> Response {
+        match self {
+            Conditional::NotModified(res)
+            | Conditional::PreconditionFailed(res)
+            | Conditional::Modified(res) => res,
+        }
+    }
+
+    /// The `ETag` of the response, if it has a valid one.
+    pub fn etag(&self) -> Option<ETag> {
+        ETag::from_headers(self.response()).ok()?
+    }
+
+    /// The `Last-Modified` date of the response, if it has a valid one.
+    pub fn last_modified(&self) -> Option<LastModified> {
+        LastModified::from_headers(self.response()).ok()?
+    }
+
+    fn response(&self) -> &Response {
+        match self {
+            Conditional::NotModified(res)
+            | Conditional::PreconditionFailed(res)
+            | Conditional::Modified(res) => res,
+        }
+    }
+}
+
+impl From<Response> for Conditional {
+    fn from(res: Response) -> Self {
+        match res.status() {
+            StatusCode::NotModified => Conditional::NotModified(res),
+            StatusCode::PreconditionFailed => Conditional::PreconditionFailed(res),
+            _ => Conditional::Modified(res),
+        }
+    }
+}
@@ -0,0 +1,1144 @@
+//! Configuration for `HttpClient`s.
+
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+use std::collections::HashMap;
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+use std::net::{IpAddr, SocketAddr};
+#[cfg(all(unix, any(feature = "h1_client", feature = "h1_client_rustls")))]
+use std::path::PathBuf;
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+use std::sync::Arc;
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+use std::time::Duration;
+
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+use http_types::Url;
+
+/// Configuration for `HttpClient`s.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Accept any TLS certificate presented by the server, without verifying it.
+    ///
+    /// Default: `false`.
+    ///
+    /// __This makes every HTTPS request vulnerable to man-in-the-middle attacks.__ It should only
+    /// be enabled for testing against servers using self-signed certificates.
+    ///
+    /// Note: Only supported on `h1_client`.
+    pub danger_accept_invalid_certs: bool,
+    /// Additional root certificates to trust, on top of the default trust store.
+    ///
+    /// Default: empty.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub root_certificates: Vec<crate::h1::Certificate>,
+    /// Client certificate and key presented to servers requesting client authentication.
+    ///
+    /// Default: `None`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub identity: Option<crate::h1::Identity>,
+    /// SHA-256 hashes of the `SubjectPublicKeyInfo` the server's leaf certificate must match, by
+    /// lowercase host name. Hosts without pins only rely on regular certificate verification.
+    ///
+    /// Default: empty.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub certificate_pins: HashMap<String, Vec<[u8; 32]>>,
+    /// Application protocols to offer over ALPN during the TLS handshake, in order of preference.
+    ///
+    /// Default: `["h2", "http/1.1"]` with `h2_client`, otherwise empty (ALPN is not used).
+    ///
+    /// Note: Only supported on `h1_client_rustls`, as `async-native-tls` doesn't report which
+    /// protocol was negotiated.
+    pub alpn_protocols: Vec<String>,
+    /// Speak HTTP/2 directly on plain TCP connections for `http` URLs ("h2c" with prior
+    /// knowledge), without an `Upgrade` from HTTP/1.1. Only use this for servers known to
+    /// support it, e.g. internal gRPC services.
+    ///
+    /// Default: `false`.
+    #[cfg(feature = "h2_client")]
+    pub http2_prior_knowledge: bool,
+    /// Resolves host names each time a new connection is established.
+    ///
+    /// Default: [`SystemResolver`](crate::h1::SystemResolver).
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub resolver: Arc<dyn crate::h1::Resolver>,
+    /// Establishes connections in place of TCP connections to servers and their proxies. `None`
+    /// connects over TCP.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub connector: Option<Arc<dyn crate::h1::Connect>>,
+    /// Addresses to connect to for the given hosts, instead of resolving them. The `Host` header
+    /// and TLS server name still use the host from the URL.
+    ///
+    /// Default: empty.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub resolve_overrides: HashMap<String, Vec<SocketAddr>>,
+    /// Cache the addresses a host resolves to for up to this long, instead of resolving it for
+    /// every new connection. Addresses are cached for their records' TTL, when the resolver
+    /// reports it (`SystemResolver` doesn't), otherwise for this long. Once they expire, idle
+    /// connections to addresses the host no longer resolves to are closed. `None` disables the
+    /// cache.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub dns_cache_max_ttl: Option<Duration>,
+    /// Cache resolved addresses for at least this long, even if their records' TTL is shorter.
+    ///
+    /// Default: `0s`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub dns_cache_min_ttl: Duration,
+    /// Spreads new connections to a host across the addresses it resolves to, skipping those
+    /// which recently failed to connect. `None` connects to the first address which accepts the
+    /// connection, in the order the resolver returned them.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub load_balancer: Option<crate::h1::LoadBalancer>,
+    /// How long a request may take overall: from checking out a connection, through resolving,
+    /// connecting and the TLS handshake of a new one, to reading the end of the response body.
+    /// Override it for a single request by inserting a `RequestTimeout` into its extensions.
+    /// `None` never times requests out.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub timeout: Option<Duration>,
+    /// Which redirects are followed, rather than returned as the response.
+    ///
+    /// Default: `RedirectPolicy::none()`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub redirect_policy: crate::h1::RedirectPolicy,
+    /// Which failed requests are sent again, and how long to back off before each retry.
+    ///
+    /// Default: `RetryPolicy::none()`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub retry_policy: crate::h1::RetryPolicy,
+    /// How long to wait for the response head to a request with an idempotent method before
+    /// sending it again, over another connection, and taking whichever response comes first.
+    /// `None` sends requests once.
+    ///
+    /// Set it to about the 95th percentile latency of the host, to cut the slowest responses
+    /// short at the cost of a few more requests. Requests with a body too large to buffer
+    /// aren't hedged.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub hedge_delay: Option<Duration>,
+    /// Equivalent origins, such as `https://api1.example.com` and `https://api2.example.com`, to
+    /// send a request to one of them to the others in turn, in this order, if it fails before any
+    /// of it was sent, as `ErrorExt::is_unsent` tells, e.g. because connecting to its origin
+    /// failed. Requests with a body too large to buffer aren't failed over.
+    ///
+    /// Default: empty.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub failover_origins: Vec<Url>,
+    /// Fails requests immediately, for a while, to hosts which too many requests failed to.
+    /// `None` always sends requests.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub circuit_breaker: Option<crate::h1::CircuitBreaker>,
+    /// Limits the rate requests are sent at, to all hosts together. `None` sends requests as
+    /// they come.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub rate_limit: Option<crate::h1::RateLimit>,
+    /// Limits the rate requests are sent at to each host, unless `host_rate_limits` has a limit
+    /// for it. `None` sends requests as they come.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub per_host_rate_limit: Option<crate::h1::RateLimit>,
+    /// Limits the rate requests are sent at to particular hosts, by lowercase hostname,
+    /// overriding `per_host_rate_limit`.
+    ///
+    /// Default: Empty.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub host_rate_limits: HashMap<String, crate::h1::RateLimit>,
+    /// Limits the bandwidth request bodies are sent with, in bytes per second, shared by all
+    /// requests. `None` sends them as fast as possible.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub upload_bandwidth: Option<u64>,
+    /// Limits the bandwidth response bodies are received with, in bytes per second, shared by
+    /// all requests. `None` receives them as fast as possible.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub download_bandwidth: Option<u64>,
+    /// Caches responses and serves them while they're fresh, per RFC 7234. `None` caches
+    /// nothing.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub cache_store: Option<Arc<dyn crate::h1::CacheStore>>,
+    /// Stores the cookies set by responses, to send them with later requests. `None` sends no
+    /// cookies beyond the request's own `Cookie` header.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub cookie_store: Option<Arc<dyn crate::h1::CookieStore>>,
+    /// The longest response head read, in bytes. A longer one fails the request with
+    /// `ClientError::ResponseTooLarge`.
+    ///
+    /// Default: 8 KiB.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_response_head_size: usize,
+    /// The most headers read in a response head, and trailers after a chunked body. More fail
+    /// the request with `ClientError::ResponseTooLarge`, or the body.
+    ///
+    /// Default: 128.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_response_headers: usize,
+    /// The most of a response body read, in bytes as received, before it's decompressed. A
+    /// response with a longer `Content-Length` fails the request, and reading past it fails the
+    /// body, with `ClientError::ResponseTooLarge`, so buffering a response can't exhaust memory.
+    /// `None` places no limit.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_response_body_size: Option<u64>,
+    /// Whether to ask for compressed responses with `Accept-Encoding`, and decompress their
+    /// bodies as they're read. Requests setting their own `Accept-Encoding` header get their
+    /// response as it's sent.
+    ///
+    /// Default: `true`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub decompress: bool,
+    /// The most a response body may decompress to, in bytes. Reading more of it fails with
+    /// `ClientError::DecompressionLimitExceeded`, so a small compressed response can't expand to
+    /// exhaust memory. `None` places no limit.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_decompressed_size: Option<u64>,
+    /// The most a response body may decompress to, as a multiple of its compressed size. It's
+    /// only checked once a body has decompressed to 1 MiB. Like `max_decompressed_size`,
+    /// exceeding it fails with `ClientError::DecompressionLimitExceeded`. `None` places no
+    /// limit.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_decompression_ratio: Option<u64>,
+    /// Send `Expect: 100-continue` with request bodies of at least this many bytes, or of
+    /// unknown length, and only send the body once the server answers with `100 Continue`. A
+    /// final response arriving instead, such as a `401` or `413`, is returned without the body
+    /// having been sent, and its connection is closed. `None` sends bodies straight away, unless
+    /// the request sets its own `Expect: 100-continue` header.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub expect_continue: Option<u64>,
+    /// How long to wait for a `100 Continue` before sending the body regardless, as not every
+    /// server sends one.
+    ///
+    /// Default: 1 second.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub expect_continue_timeout: Duration,
+    /// Receives the `103 Early Hints` responses sent ahead of final responses. They're skipped,
+    /// like other informational responses, if there's no handler.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub early_hints_handler: Option<Arc<dyn crate::h1::EarlyHintsHandler>>,
+    /// How long a read from a connection may wait for data before it fails, whether for the
+    /// response headers or between chunks of the body. Unlike `timeout`, a response streaming
+    /// slowly but steadily is never timed out. `None` waits indefinitely.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub read_timeout: Option<Duration>,
+    /// How long establishing a new connection may take, including the TLS handshake, before it's
+    /// abandoned and the request fails. `None` waits for the operating system to give up.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub connect_timeout: Option<Duration>,
+    /// How long the TLS handshake of a new connection may take, once the server has accepted
+    /// the TCP connection. It fails with `ClientError::TlsHandshakeTimeout`, telling apart
+    /// servers which never complete the handshake from connect failures. `None` only bounds
+    /// the handshake by `connect_timeout`.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub tls_handshake_timeout: Option<Duration>,
+    /// Set `TCP_NODELAY` on new connections, sending small writes right away rather than
+    /// waiting to coalesce them, as Nagle's algorithm does, which delays requests.
+    ///
+    /// Default: `true`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub tcp_nodelay: bool,
+    /// Send TCP keepalive probes on idle connections, with `SO_KEEPALIVE`. `None` leaves it to
+    /// the operating system, which usually doesn't.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub tcp_keepalive: Option<crate::h1::TcpKeepalive>,
+    /// The size of the send buffer of new connections, `SO_SNDBUF`. `None` leaves it to the
+    /// operating system.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub send_buffer_size: Option<usize>,
+    /// The size of the receive buffer of new connections, `SO_RCVBUF`. `None` leaves it to the
+    /// operating system.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub recv_buffer_size: Option<usize>,
+    /// The local address new connections are bound to, for multi-homed hosts to choose which
+    /// address requests egress from. Only addresses of its family are connected to. `None`
+    /// leaves it to the operating system.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub local_address: Option<IpAddr>,
+    /// The name of the network interface new connections are bound to, with
+    /// `SO_BINDTODEVICE`, e.g. `eth1`.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`, on Linux, Android and Fuchsia.
+    #[cfg(all(
+        any(feature = "h1_client", feature = "h1_client_rustls"),
+        any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+    ))]
+    pub interface: Option<String>,
+    /// The proxies requests are sent through, by the scheme of their URL. Servers without one
+    /// are connected to directly.
+    ///
+    /// Default: no proxies.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub proxies: crate::h1::Proxies,
+    /// Unix domain sockets to connect to instead of servers, by the lowercase authority of the
+    /// requests sent over them: a host, for any port, or a `host:port`. Requests are sent over
+    /// them in plain HTTP/1.1, and `https` URLs can't be.
+    ///
+    /// Default: empty.
+    ///
+    /// Note: Only supported on `h1_client`, on Unix.
+    #[cfg(all(unix, any(feature = "h1_client", feature = "h1_client_rustls")))]
+    pub unix_sockets: HashMap<String, PathBuf>,
+    /// The maximum number of connections kept open to each host (by resolved address).
+    ///
+    /// Default: `50`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_connections_per_host: usize,
+    /// The maximum number of connections kept open across all hosts. Once reached, requests
+    /// needing a new connection close an idle connection to another host if there is one, and
+    /// otherwise queue for a connection to close, in the order they arrived. Queueing is bounded
+    /// by `pool_checkout_timeout` and `pool_fail_fast`, as for a single host's pool. `None`
+    /// places no limit beyond `max_connections_per_host`.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_connections: Option<usize>,
+    /// How long to wait for a connection to become available when the pool for a host is
+    /// exhausted, before failing the request with a `Checkout` timeout. `None` waits
+    /// indefinitely.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub pool_checkout_timeout: Option<Duration>,
+    /// Fail requests immediately when the pool for a host is exhausted, instead of queueing for
+    /// a connection. Takes precedence over `pool_checkout_timeout`.
+    ///
+    /// Default: `false`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub pool_fail_fast: bool,
+    /// Close pooled connections which have been idle for longer than this, instead of reusing
+    /// them. Set this below the server's keep-alive timeout to avoid reusing connections the
+    /// server is about to close. `None` keeps idle connections indefinitely.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub idle_timeout: Option<Duration>,
+    /// Close pooled connections which were established longer ago than this, instead of
+    /// reusing them. `None` places no limit on a connection's lifetime.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub max_lifetime: Option<Duration>,
+    /// Walk the pools this often in a background task, closing idle connections which exceeded
+    /// `idle_timeout`, `max_lifetime`, or the server's keep-alive limits. `None` only discovers
+    /// expired connections when they're next checked out.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub reap_interval: Option<Duration>,
+    /// Leave the path and query of URLs out of the spans of requests, with the `tracing` feature,
+    /// for URLs carrying user IDs or tokens. Credentials in URLs are always left out.
+    ///
+    /// Default: `false`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(feature = "tracing")]
+    pub tracing_redact_paths: bool,
+    /// Receives the metrics of requests sent and connections established, to export them.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub metrics_observer: Option<Arc<dyn crate::h1::MetricsObserver>>,
+    /// Is told what becomes of pooled connections: when they're established, reused, returned
+    /// idle, evicted and closed.
+    ///
+    /// Default: `None`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub connection_observer: Option<Arc<dyn crate::h1::ConnectionObserver>>,
+}
+
+impl Config {
+    /// Construct new empty config.
+    pub fn new() -> Self {
+        Self {
+            danger_accept_invalid_certs: false,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            root_certificates: Vec::new(),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            identity: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            certificate_pins: HashMap::new(),
+            #[cfg(feature = "h2_client")]
+            alpn_protocols: vec!["h2".to_string(), "http/1.1".to_string()],
+            #[cfg(not(feature = "h2_client"))]
+            alpn_protocols: Vec::new(),
+            #[cfg(feature = "h2_client")]
+            http2_prior_knowledge: false,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            resolver: Arc::new(crate::h1::SystemResolver),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            connector: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            resolve_overrides: HashMap::new(),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            dns_cache_max_ttl: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            dns_cache_min_ttl: Duration::from_secs(0),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            load_balancer: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            timeout: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            redirect_policy: crate::h1::RedirectPolicy::none(),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            retry_policy: crate::h1::RetryPolicy::none(),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            hedge_delay: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            failover_origins: Vec::new(),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            circuit_breaker: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            rate_limit: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            per_host_rate_limit: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            host_rate_limits: HashMap::new(),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            upload_bandwidth: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            download_bandwidth: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            cache_store: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            cookie_store: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_response_head_size: 8 * 1024,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_response_headers: 128,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_response_body_size: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            decompress: true,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_decompressed_size: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_decompression_ratio: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            expect_continue: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            expect_continue_timeout: Duration::from_secs(1),
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            early_hints_handler: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            read_timeout: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            connect_timeout: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            tls_handshake_timeout: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            tcp_nodelay: true,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            tcp_keepalive: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            send_buffer_size: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            recv_buffer_size: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            local_address: None,
+            #[cfg(all(
+                any(feature = "h1_client", feature = "h1_client_rustls"),
+                any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+            ))]
+            interface: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            proxies: crate::h1::Proxies::new(),
+            #[cfg(all(unix, any(feature = "h1_client", feature = "h1_client_rustls")))]
+            unix_sockets: HashMap::new(),
+            // This number is based on a few random benchmarks and see whatever gave decent perf vs resource use.
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_connections_per_host: 50,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_connections: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            pool_checkout_timeout: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            pool_fail_fast: false,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            idle_timeout: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            max_lifetime: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            reap_interval: None,
+            #[cfg(feature = "tracing")]
+            tracing_redact_paths: false,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            metrics_observer: None,
+            #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+            connection_observer: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    /// Accept any TLS certificate presented by the server, without verifying it.
+    ///
+    /// __This makes every HTTPS request vulnerable to man-in-the-middle attacks.__
+    pub fn set_danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.danger_accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. a private CA used for internal services.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn add_root_certificate(mut self, cert: crate::h1::Certificate) -> Self {
+        self.root_certificates.push(cert);
+        self
+    }
+
+    /// Set the client identity used for TLS client authentication (mTLS).
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_identity(mut self, identity: Option<crate::h1::Identity>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Pin the public key of `host`'s certificate to the given SHA-256 hash of its DER encoded
+    /// `SubjectPublicKeyInfo` (the same value used by HPKP's `pin-sha256`).
+    ///
+    /// Connections to `host` whose leaf certificate doesn't match any of its pins are rejected,
+    /// even if the certificate is otherwise valid. Call this more than once for the same host to
+    /// allow backup keys.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn pin_certificate(mut self, host: impl Into<String>, sha256_spki: [u8; 32]) -> Self {
+        self.certificate_pins
+            .entry(host.into().to_ascii_lowercase())
+            .or_default()
+            .push(sha256_spki);
+        self
+    }
+
+    /// Set the application protocols to offer over ALPN, e.g. `&["http/1.1"]`.
+    ///
+    /// Note: Only supported on `h1_client_rustls`.
+    pub fn set_alpn_protocols(mut self, protocols: &[&str]) -> Self {
+        self.alpn_protocols = protocols.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Speak HTTP/2 directly on plain TCP connections for `http` URLs, without an upgrade.
+    #[cfg(feature = "h2_client")]
+    pub fn set_http2_prior_knowledge(mut self, prior_knowledge: bool) -> Self {
+        self.http2_prior_knowledge = prior_knowledge;
+        self
+    }
+
+    /// Resolve host names with `resolver`, instead of the system's resolver.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_resolver(mut self, resolver: impl crate::h1::Resolver) -> Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+
+    /// Establish connections with `connector`, instead of over TCP.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_connector(mut self, connector: impl crate::h1::Connect) -> Self {
+        self.connector = Some(Arc::new(connector));
+        self
+    }
+
+    /// Connect to `addr` for requests to `host`, instead of resolving it, e.g. to test against a
+    /// particular server while keeping the server name used for TLS. Call this more than once for
+    /// the same host to give it several addresses.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn resolve(mut self, host: impl Into<String>, addr: SocketAddr) -> Self {
+        self.resolve_overrides
+            .entry(host.into().to_ascii_lowercase())
+            .or_default()
+            .push(addr);
+        self
+    }
+
+    /// Set the longest time resolved addresses are cached for, `None` disabling the cache.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_dns_cache_max_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.dns_cache_max_ttl = ttl;
+        self
+    }
+
+    /// Set the shortest time resolved addresses are cached for.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_dns_cache_min_ttl(mut self, ttl: Duration) -> Self {
+        self.dns_cache_min_ttl = ttl;
+        self
+    }
+
+    /// Set how new connections to a host are spread across the addresses it resolves to.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_load_balancer(mut self, balancer: crate::h1::LoadBalancer) -> Self {
+        self.load_balancer = Some(balancer);
+        self
+    }
+
+    /// Set how long a request may take overall, including reading the response body.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set which redirects are followed.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_redirect_policy(mut self, policy: crate::h1::RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Set which failed requests are retried.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_retry_policy(mut self, policy: crate::h1::RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set how long to wait for a response before sending an idempotent request again.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_hedge_delay(mut self, delay: Option<Duration>) -> Self {
+        self.hedge_delay = delay;
+        self
+    }
+
+    /// Set the equivalent origins requests to each of them fail over to.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_failover_origins(mut self, origins: Vec<Url>) -> Self {
+        self.failover_origins = origins;
+        self
+    }
+
+    /// Set when to fail requests immediately to hosts which keep failing.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_circuit_breaker(mut self, breaker: crate::h1::CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Set the rate limit for requests to all hosts together.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_rate_limit(mut self, limit: crate::h1::RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Set the rate limit for requests to each host.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_per_host_rate_limit(mut self, limit: crate::h1::RateLimit) -> Self {
+        self.per_host_rate_limit = Some(limit);
+        self
+    }
+
+    /// Set the rate limit for requests to `host`, instead of `per_host_rate_limit`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_host_rate_limit(
+        mut self,
+        host: impl Into<String>,
+        limit: crate::h1::RateLimit,
+    ) -> Self {
+        self.host_rate_limits
+            .insert(host.into().to_ascii_lowercase(), limit);
+        self
+    }
+
+    /// Set the bandwidth request bodies are sent with, in bytes per second, by all requests
+    /// together.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_upload_bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.upload_bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// Set the bandwidth response bodies are received with, in bytes per second, by all requests
+    /// together.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_download_bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.download_bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// Set the store of cached responses, e.g. a `MemoryCache`. The `Arc` may be kept to clear
+    /// the cache, or to share it with another client.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_cache_store<S: crate::h1::CacheStore>(mut self, store: Arc<S>) -> Self {
+        self.cache_store = Some(store);
+        self
+    }
+
+    /// Set the store of the cookies set by responses, e.g. a `CookieJar`. The `Arc` may be kept
+    /// to inspect the cookies, or to share them with another client.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_cookie_store<S: crate::h1::CookieStore>(mut self, store: Arc<S>) -> Self {
+        self.cookie_store = Some(store);
+        self
+    }
+
+    /// Set the longest response head read, in bytes.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_response_head_size(mut self, max: usize) -> Self {
+        self.max_response_head_size = max;
+        self
+    }
+
+    /// Set the most headers read in a response head, and trailers after a chunked body.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_response_headers(mut self, max: usize) -> Self {
+        self.max_response_headers = max;
+        self
+    }
+
+    /// Set the most of a response body read, in bytes as received.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_response_body_size(mut self, max: Option<u64>) -> Self {
+        self.max_response_body_size = max;
+        self
+    }
+
+    /// Set whether to ask for compressed responses, and decompress them.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Set the most a response body may decompress to, in bytes.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_decompressed_size(mut self, max: Option<u64>) -> Self {
+        self.max_decompressed_size = max;
+        self
+    }
+
+    /// Set the most a response body may decompress to, as a multiple of its compressed size.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_decompression_ratio(mut self, ratio: Option<u64>) -> Self {
+        self.max_decompression_ratio = ratio;
+        self
+    }
+
+    /// Set the smallest request body sent with `Expect: 100-continue`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_expect_continue(mut self, min_body_size: Option<u64>) -> Self {
+        self.expect_continue = min_body_size;
+        self
+    }
+
+    /// Set how long to wait for a `100 Continue` before sending the body regardless.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_expect_continue_timeout(mut self, timeout: Duration) -> Self {
+        self.expect_continue_timeout = timeout;
+        self
+    }
+
+    /// Set the handler receiving `103 Early Hints` responses.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_early_hints_handler(mut self, handler: impl crate::h1::EarlyHintsHandler) -> Self {
+        self.early_hints_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Set how long a read from a connection may wait for data.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set how long establishing a new connection may take, including the TLS handshake.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set how long the TLS handshake of a new connection may take.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_tls_handshake_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.tls_handshake_timeout = timeout;
+        self
+    }
+
+    /// Set whether to set `TCP_NODELAY` on new connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Set when TCP keepalive probes are sent on idle connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_tcp_keepalive(mut self, keepalive: Option<crate::h1::TcpKeepalive>) -> Self {
+        self.tcp_keepalive = keepalive;
+        self
+    }
+
+    /// Set the size of the send buffer of new connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_send_buffer_size(mut self, size: Option<usize>) -> Self {
+        self.send_buffer_size = size;
+        self
+    }
+
+    /// Set the size of the receive buffer of new connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_recv_buffer_size(mut self, size: Option<usize>) -> Self {
+        self.recv_buffer_size = size;
+        self
+    }
+
+    /// Set the local address new connections are bound to.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_local_address(mut self, address: Option<IpAddr>) -> Self {
+        self.local_address = address;
+        self
+    }
+
+    /// Set the name of the network interface new connections are bound to.
+    ///
+    /// Note: Only supported on `h1_client`, on Linux, Android and Fuchsia.
+    #[cfg(all(
+        any(feature = "h1_client", feature = "h1_client_rustls"),
+        any(target_os = "android", target_os = "fuchsia", target_os = "linux")
+    ))]
+    pub fn set_interface(mut self, interface: Option<String>) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Set the proxy every request is sent through, replacing any `proxies`.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_proxy(mut self, proxy: Option<crate::h1::Proxy>) -> Self {
+        self.proxies = proxy.map(crate::h1::Proxies::all).unwrap_or_default();
+        self
+    }
+
+    /// Set the proxies requests are sent through.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_proxies(mut self, proxies: crate::h1::Proxies) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// Send requests to `authority`, a host or a `host:port`, over the Unix domain socket at
+    /// `path` instead, such as `/var/run/docker.sock`.
+    ///
+    /// Note: Only supported on `h1_client`, on Unix.
+    #[cfg(all(unix, any(feature = "h1_client", feature = "h1_client_rustls")))]
+    pub fn add_unix_socket(
+        mut self,
+        authority: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        self.unix_sockets
+            .insert(authority.into().to_ascii_lowercase(), path.into());
+        self
+    }
+
+    /// Set the maximum number of connections kept open to each host.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_connections_per_host(mut self, max_connections_per_host: usize) -> Self {
+        self.max_connections_per_host = max_connections_per_host;
+        self
+    }
+
+    /// Set the maximum number of connections kept open across all hosts.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_connections(mut self, max_connections: Option<usize>) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Set how long to wait for a pooled connection when the pool for a host is exhausted.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_pool_checkout_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_checkout_timeout = timeout;
+        self
+    }
+
+    /// Fail requests immediately when the pool for a host is exhausted, instead of queueing.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_pool_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.pool_fail_fast = fail_fast;
+        self
+    }
+
+    /// Set how long a pooled connection may sit idle before it's closed instead of reused.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Set how long after being established a pooled connection is closed instead of reused.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_max_lifetime(mut self, lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = lifetime;
+        self
+    }
+
+    /// Set how often a background task closes expired idle connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_reap_interval(mut self, interval: Option<Duration>) -> Self {
+        self.reap_interval = interval;
+        self
+    }
+
+    /// Leave the path and query of URLs out of the spans of requests.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(feature = "tracing")]
+    pub fn set_tracing_redact_paths(mut self, redact: bool) -> Self {
+        self.tracing_redact_paths = redact;
+        self
+    }
+
+    /// Set the observer receiving the metrics of requests and connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_metrics_observer(mut self, observer: impl crate::h1::MetricsObserver) -> Self {
+        self.metrics_observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Set the observer told about the lifecycle of pooled connections.
+    ///
+    /// Note: Only supported on `h1_client`.
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    pub fn set_connection_observer(mut self, observer: impl crate::h1::ConnectionObserver) -> Self {
+        self.connection_observer = Some(Arc::new(observer));
+        self
+    }
+}
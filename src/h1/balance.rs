@@ -0,0 +1,199 @@
+//! Spreading new connections across the addresses a host resolves to, as configured by
+//! `Config::load_balancer`.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How new connections to a host which resolves to several addresses are spread across them,
+/// rather than each made to the first address which accepts it.
+///
+/// An address which fails to connect is marked unhealthy, and skipped for the `cooldown` after,
+/// unless all of the host's addresses are. Addresses are still raced the Happy Eyeballs way, so
+/// if the address picked doesn't connect soon, the next one is tried alongside it.
+#[derive(Clone, Debug)]
+pub struct LoadBalancer {
+    strategy: Strategy,
+    cooldown: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Strategy {
+    RoundRobin,
+    LeastOutstanding,
+}
+
+impl LoadBalancer {
+    /// Connect to each of the host's addresses in turn, skipping unhealthy ones for 30 seconds.
+    pub fn round_robin() -> Self {
+        Self {
+            strategy: Strategy::RoundRobin,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    /// Connect to the address with the fewest open connections, counting those still being
+    /// established, skipping unhealthy ones for 30 seconds. Ties go to the address resolved
+    /// first.
+    pub fn least_outstanding() -> Self {
+        Self {
+            strategy: Strategy::LeastOutstanding,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    /// Set how long an address which failed to connect is skipped for.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+/// The addresses of one host, and the connections to each, balanced as a `LoadBalancer` has it.
+#[derive(Debug)]
+pub(crate) struct Balance {
+    balancer: LoadBalancer,
+    next: AtomicUsize,
+    addrs: Mutex<HashMap<SocketAddr, Address>>,
+}
+
+#[derive(Debug, Default)]
+struct Address {
+    // Shared by each outstanding connection.
+    leases: Arc<()>,
+    unhealthy_until: Option<Instant>,
+}
+
+impl Address {
+    fn outstanding(&self) -> usize {
+        Arc::strong_count(&self.leases) - 1
+    }
+
+    fn healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Counts a connection as outstanding to its address until dropped.
+#[derive(Debug)]
+pub(crate) struct Lease {
+    _address: Arc<()>,
+}
+
+impl Balance {
+    pub(crate) fn new(balancer: LoadBalancer) -> Self {
+        Self {
+            balancer,
+            next: AtomicUsize::new(0),
+            addrs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Order `addrs` by which to connect to first, leaving out unhealthy ones unless they all
+    /// are.
+    pub(crate) fn order(&self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let now = Instant::now();
+        let mut state = self.addrs.lock().unwrap();
+        // Forget addresses the host no longer resolves to, once nothing is known of them.
+        state.retain(|addr, address| {
+            addrs.contains(addr) || address.outstanding() > 0 || !address.healthy(now)
+        });
+        let healthy = |addr: &SocketAddr| state.get(addr).is_none_or(|a| a.healthy(now));
+        if addrs.iter().any(healthy) {
+            addrs.retain(healthy);
+        }
+        match self.balancer.strategy {
+            Strategy::RoundRobin if !addrs.is_empty() => {
+                let next = self.next.fetch_add(1, Ordering::Relaxed) % addrs.len();
+                addrs.rotate_left(next);
+            }
+            Strategy::RoundRobin => {}
+            Strategy::LeastOutstanding => {
+                addrs.sort_by_key(|addr| state.get(addr).map_or(0, Address::outstanding));
+            }
+        }
+        addrs
+    }
+
+    /// Count a connection to `addr` as outstanding, while it's established or open.
+    pub(crate) fn lease(&self, addr: SocketAddr) -> Lease {
+        let mut state = self.addrs.lock().unwrap();
+        Lease {
+            _address: state.entry(addr).or_default().leases.clone(),
+        }
+    }
+
+    /// Mark `addr` unhealthy, having failed to connect.
+    pub(crate) fn failed(&self, addr: SocketAddr) {
+        log::debug!(
+            "{} failed to connect, skipping it for {:?}",
+            addr,
+            self.balancer.cooldown
+        );
+        let mut state = self.addrs.lock().unwrap();
+        state.entry(addr).or_default().unhealthy_until =
+            Some(Instant::now() + self.balancer.cooldown);
+    }
+
+    /// Mark `addr` healthy again, having connected, and count the connection as outstanding.
+    pub(crate) fn connected(&self, addr: SocketAddr) -> Lease {
+        let mut state = self.addrs.lock().unwrap();
+        let address = state.entry(addr).or_default();
+        address.unhealthy_until = None;
+        Lease {
+            _address: address.leases.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> Vec<SocketAddr> {
+        ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn round_robin() {
+        let balance = Balance::new(LoadBalancer::round_robin());
+        let first = (0..4)
+            .map(|_| balance.order(addrs())[0])
+            .collect::<Vec<_>>();
+        let addrs = addrs();
+        assert_eq!(first, [addrs[0], addrs[1], addrs[2], addrs[0]]);
+
+        // Unhealthy addresses are skipped, unless they all are.
+        balance.failed(addrs[1]);
+        assert_eq!(balance.order(addrs.clone()), [addrs[0], addrs[2]]);
+        balance.failed(addrs[0]);
+        balance.failed(addrs[2]);
+        assert_eq!(balance.order(addrs.clone()).len(), 3);
+        let _lease = balance.connected(addrs[1]);
+        assert_eq!(balance.order(addrs.clone()), [addrs[1]]);
+
+        let balance = Balance::new(LoadBalancer::round_robin().cooldown(Duration::ZERO));
+        balance.failed(addrs[0]);
+        assert_eq!(balance.order(addrs.clone()), addrs);
+    }
+
+    #[test]
+    fn least_outstanding() {
+        let balance = Balance::new(LoadBalancer::least_outstanding());
+        let addrs = addrs();
+        assert_eq!(balance.order(addrs.clone()), addrs);
+
+        let first = balance.lease(addrs[0]);
+        let second = balance.lease(addrs[0]);
+        let third = balance.connected(addrs[1]);
+        assert_eq!(balance.order(addrs.clone()), [addrs[2], addrs[1], addrs[0]]);
+
+        drop((first, second, third));
+        assert_eq!(balance.order(addrs.clone()), addrs);
+    }
+}
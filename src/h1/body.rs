@@ -0,0 +1,400 @@
+//! Bodies streamed to and from the connection as they're sent and received.
+
+use std::fmt::{self, Debug};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use async_std::io::{self, BufRead, BufReader, Read};
+use async_std::stream::Stream;
+use bytes1::{Buf, Bytes, BytesMut};
+use futures_lite::AsyncBufReadExt;
+use http_types::headers::CONTENT_TYPE;
+use http_types::{Method, StatusCode};
+
+use super::error::Target;
+use super::pool::Exchange;
+use super::ClientError;
+use crate::{Body, Error, Request, Response};
+
+/// A request body read from `reader` as the request is written to the connection, rather than
+/// buffered in memory first, so uploads of any size use a constant amount of memory.
+///
+/// With a known `len` the body is sent with a `Content-Length`, and `reader` must produce
+/// exactly that many bytes. Otherwise it's sent with `Transfer-Encoding: chunked`.
+///
+/// Unlike `Body::from_reader`, `reader` needn't be `Sync` nor buffered.
+pub fn streaming_body(reader: impl Read + Send + Unpin + 'static, len: Option<usize>) -> Body {
+    let reader: Box<dyn Read + Send + Unpin> = Box::new(reader);
+    Body::from_reader(BufReader::new(SyncReader(Mutex::new(reader))), len)
+}
+
+/// A body sent straight from `bytes`, rather than copied into a `Vec<u8>` first. `Bytes` clone
+/// cheaply, so the same body can be sent with several requests, or a part of a larger buffer
+/// sent as one with `Bytes::slice`, without copying it.
+pub fn bytes_body(bytes: Bytes) -> Body {
+    let len = bytes.len();
+    Body::from_reader(BytesReader(bytes), Some(len))
+}
+
+/// Take the body of `req`, without the `Content-Type` `Request::take_body` adds if it has none.
+pub(crate) fn take_request_body(req: &mut Request) -> Body {
+    set_request_body(req, Body::empty())
+}
+
+/// Set `body` on `req`, returning the one it had, without the `Content-Type` `Request::set_body`
+/// adds if it has none.
+pub(crate) fn set_request_body(req: &mut Request, body: Body) -> Body {
+    let typed = req.header(CONTENT_TYPE).is_some();
+    let old = req.replace_body(body);
+    if !typed {
+        req.remove_header(CONTENT_TYPE);
+    }
+    old
+}
+
+/// Read `body` to its end into `Bytes`, sized up front if its length is known, copying what's
+/// read of it once rather than into a buffer first.
+pub(crate) async fn read_bytes(mut body: Body) -> io::Result<Bytes> {
+    let mut bytes = BytesMut::with_capacity(body.len().unwrap_or_default());
+    loop {
+        let buf = body.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(bytes.freeze());
+        }
+        let n = buf.len();
+        bytes.extend_from_slice(buf);
+        body.consume(n);
+    }
+}
+
+/// Reads `Bytes`, lending out their data as the reader's buffer rather than copying it into one.
+struct BytesReader(Bytes);
+
+impl Read for BytesReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = buf.len().min(self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0.advance(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl BufRead for BytesReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Poll::Ready(Ok(&self.get_mut().0))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.0.advance(amt);
+    }
+}
+
+/// Makes a reader `Sync`, as bodies have to be, by only ever reading it through `&mut`.
+struct SyncReader(Mutex<Box<dyn Read + Send + Unpin>>);
+
+impl Read for SyncReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let reader = self
+            .get_mut()
+            .0
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Pin::new(reader).poll_read(cx, buf)
+    }
+}
+
+/// The most of a response body read into each chunk a `BodyStream` yields.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// A response body as a `Stream` of the chunks of data read off the connection, yielded as they
+/// arrive rather than once the whole body has.
+///
+/// Response bodies can also be read as they arrive through `AsyncRead`, which `Body` implements.
+/// Either way, a pooled connection is only returned to the pool once its response body has been
+/// read to its end; if the body is dropped before then, the connection is closed.
+pub struct BodyStream {
+    body: Body,
+    // Chunks are split off it, so its allocation is reused once they're dropped.
+    buf: BytesMut,
+}
+
+impl BodyStream {
+    /// Stream the chunks of `body`.
+    pub fn new(body: Body) -> Self {
+        Self {
+            body,
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyStream")
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<Body> for BodyStream {
+    fn from(body: Body) -> Self {
+        Self::new(body)
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.buf.resize(CHUNK_SIZE, 0);
+        match ready!(Pin::new(&mut this.body).poll_read(cx, &mut this.buf)) {
+            Ok(0) => Poll::Ready(None),
+            Ok(n) => {
+                this.buf.truncate(n);
+                Poll::Ready(Some(Ok(this.buf.split().freeze())))
+            }
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+/// Finish `exchange` once the body of `res`, the response to a `method` request, has been read
+/// to its end, and raise errors reading it as `Body` failures of `target`.
+///
+/// Responses to `HEAD` requests, and `204` and `304` responses, have no body whatever their
+/// headers say, so they're finished straight away, as are empty bodies.
+pub(crate) fn track(
+    method: Method,
+    mut res: Response,
+    exchange: Exchange,
+    target: Target,
+) -> Response {
+    let bodiless = method == Method::Head
+        || matches!(
+            res.status(),
+            StatusCode::NoContent | StatusCode::NotModified
+        );
+    if bodiless || res.len() == Some(0) {
+        exchange.finish();
+        // Dropping the body returns the connection to the pool now, rather than once the
+        // response is dropped.
+        drop(res.take_body());
+        return res;
+    }
+
+    let body = res.take_body();
+    let mime = body.mime().clone();
+    let len = body.len();
+    let tracked = Tracked {
+        body,
+        remaining: len,
+        exchange,
+        target,
+    };
+    let mut body = Body::from_reader(tracked, len);
+    body.set_mime(mime);
+    res.replace_body(body);
+    res
+}
+
+/// Bound the body of `res` to `max` bytes, failing at once if its `Content-Length` is longer,
+/// or else once more of it is read, with `ClientError::ResponseTooLarge`.
+pub(crate) fn limit(mut res: Response, max: Option<u64>) -> Result<Response, Error> {
+    let max = match max {
+        Some(max) => max,
+        None => return Ok(res),
+    };
+    match res.len() {
+        Some(len) if len as u64 > max => return Err(ClientError::ResponseTooLarge.into_error()),
+        // `Body` stops reading at its length, so it can't exceed it.
+        Some(_) => return Ok(res),
+        None => {}
+    }
+    let body = res.take_body();
+    let mime = body.mime().clone();
+    let capped = Capped { body, read: 0, max };
+    let mut body = Body::from_reader(capped, None);
+    body.set_mime(mime);
+    res.replace_body(body);
+    Ok(res)
+}
+
+/// A response body, which fails to be read past `max` bytes.
+struct Capped {
+    body: Body,
+    read: u64,
+    max: u64,
+}
+
+/// Fail if `more` bytes past the `read` ones exceed `max`.
+fn check_capped(read: u64, more: usize, max: u64) -> io::Result<()> {
+    if read + more as u64 > max {
+        let error = ClientError::ResponseTooLarge;
+        return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+    }
+    Ok(())
+}
+
+impl Read for Capped {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.body).poll_read(cx, buf))?;
+        check_capped(self.read, n, self.max)?;
+        self.read += n as u64;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl BufRead for Capped {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        let buf = ready!(Pin::new(&mut this.body).poll_fill_buf(cx))?;
+        // The body is longer than the limit if what's buffered of it already is.
+        check_capped(this.read, buf.len(), this.max)?;
+        Poll::Ready(Ok(buf))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.body).consume(amt);
+        self.read += amt as u64;
+    }
+}
+
+/// A response body, which finishes its exchange once read to its end.
+struct Tracked {
+    body: Body,
+    // The length of the rest of the body, if known. `Body` stops reading at its length, without
+    // waiting to see the end of the body it wraps.
+    remaining: Option<usize>,
+    exchange: Exchange,
+    target: Target,
+}
+
+/// The error for a response body which ends before its `Content-Length`, leaving its exchange
+/// unfinished, so the connection isn't reused.
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "connection closed before the end of the body",
+    )
+}
+
+impl Tracked {
+    fn advance(&mut self, n: usize) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(n);
+            if *remaining == 0 {
+                self.exchange.finish();
+            }
+        }
+    }
+}
+
+impl Read for Tracked {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.body).poll_read(cx, buf);
+        match poll {
+            Poll::Ready(Ok(0)) if !buf.is_empty() && self.remaining.is_some_and(|n| n > 0) => {
+                return Poll::Ready(Err(self.target.fail_body(truncated())));
+            }
+            Poll::Ready(Ok(0)) if !buf.is_empty() => self.exchange.finish(),
+            Poll::Ready(Ok(n)) => self.advance(n),
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(self.target.fail_body(e))),
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl BufRead for Tracked {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        match ready!(Pin::new(&mut this.body).poll_fill_buf(cx)) {
+            Ok([]) if this.remaining.is_some_and(|n| n > 0) => {
+                Poll::Ready(Err(this.target.fail_body(truncated())))
+            }
+            Ok([]) => {
+                this.exchange.finish();
+                Poll::Ready(Ok(&[]))
+            }
+            Ok(buf) => Poll::Ready(Ok(buf)),
+            Err(e) => Poll::Ready(Err(this.target.fail_body(e))),
+        }
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.body).consume(amt);
+        self.advance(amt);
+    }
+}
+
+/// Count the request `res` answers as in flight, holding `in_flight` until its body has been
+/// read to its end, or dropped.
+pub(crate) fn count_in_flight(mut res: Response, in_flight: Arc<()>) -> Response {
+    if res.len() == Some(0) {
+        return res;
+    }
+    let body = res.take_body();
+    let mime = body.mime().clone();
+    let len = body.len();
+    let counted = Counted {
+        body,
+        in_flight: Some(in_flight),
+    };
+    let mut body = Body::from_reader(counted, len);
+    body.set_mime(mime);
+    res.replace_body(body);
+    res
+}
+
+/// A response body, which counts its request as in flight until read to its end.
+struct Counted {
+    body: Body,
+    in_flight: Option<Arc<()>>,
+}
+
+impl Read for Counted {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.body).poll_read(cx, buf))?;
+        if n == 0 && !buf.is_empty() {
+            self.in_flight = None;
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl BufRead for Counted {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        let buf = ready!(Pin::new(&mut this.body).poll_fill_buf(cx))?;
+        if buf.is_empty() {
+            this.in_flight = None;
+        }
+        Poll::Ready(Ok(buf))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.body).consume(amt);
+    }
+}
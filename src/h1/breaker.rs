@@ -0,0 +1,266 @@
+//! Failing fast for hosts which are down, as configured by `Config::circuit_breaker`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use super::pool::Origin;
+use super::ClientError;
+use crate::{Error, Response};
+
+/// When to stop sending requests to a host which keeps failing, and for how long.
+///
+/// Each host's circuit starts out closed, letting requests through. Once at least
+/// `min_requests` requests to it within a `window` have completed, and `failure_ratio` of them
+/// or more failed, the circuit opens: for the `cooldown` after, requests to the host fail
+/// immediately with `ClientError::CircuitOpen`, instead of taking up a pooled connection and
+/// waiting for a timeout. Once the cooldown has passed the circuit is half open, letting a
+/// single request through as a probe. If it succeeds the circuit closes again, and if it fails
+/// the circuit opens for another cooldown.
+///
+/// A request fails if it returns an error, or a `5xx` response.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    failure_ratio: f64,
+    min_requests: usize,
+    window: Duration,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    /// Open a host's circuit once half of at least 10 requests to it within 10 seconds failed,
+    /// for a cooldown of 30 seconds.
+    pub fn new() -> Self {
+        Self {
+            failure_ratio: 0.5,
+            min_requests: 10,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the ratio of failed requests, between 0 and 1, which opens the circuit.
+    pub fn failure_ratio(mut self, ratio: f64) -> Self {
+        self.failure_ratio = ratio;
+        self
+    }
+
+    /// Set how many requests within a window must have completed before the circuit can open.
+    pub fn min_requests(mut self, min_requests: usize) -> Self {
+        self.min_requests = min_requests;
+        self
+    }
+
+    /// Set how long requests are counted for, before counting starts over.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set how long requests fail immediately once the circuit has opened, before a probe is
+    /// let through.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The state of a host's circuit.
+#[derive(Debug)]
+enum State {
+    Closed {
+        since: Instant,
+        requests: usize,
+        failures: usize,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        probing: bool,
+    },
+}
+
+impl State {
+    fn closed() -> Self {
+        State::Closed {
+            since: Instant::now(),
+            requests: 0,
+            failures: 0,
+        }
+    }
+}
+
+/// The circuit of each host a client has sent requests to.
+#[derive(Debug, Default)]
+pub(crate) struct Circuits(DashMap<Origin, Mutex<State>>);
+
+impl Circuits {
+    /// Let a request to `origin` through, unless its circuit is open or another request is
+    /// already probing it.
+    pub(crate) fn enter<'a>(
+        &'a self,
+        breaker: &'a CircuitBreaker,
+        origin: Origin,
+    ) -> Result<Permit<'a>, Error> {
+        let circuit = self
+            .0
+            .entry(origin.clone())
+            .or_insert_with(|| Mutex::new(State::closed()));
+        let mut state = circuit.lock().unwrap();
+        let probe = match *state {
+            State::Closed { .. } => false,
+            State::Open { until } if Instant::now() >= until => {
+                log::debug!("probing {}:{} after cooldown", origin.host, origin.port);
+                *state = State::HalfOpen { probing: true };
+                true
+            }
+            State::HalfOpen { probing: false } => {
+                *state = State::HalfOpen { probing: true };
+                true
+            }
+            State::Open { .. } | State::HalfOpen { probing: true } => {
+                return Err(ClientError::CircuitOpen.into_error())
+            }
+        };
+        drop(state);
+        drop(circuit);
+        Ok(Permit {
+            circuits: self,
+            breaker,
+            origin,
+            probe,
+            recorded: false,
+        })
+    }
+}
+
+/// A request let through a circuit, which records whether it failed.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    circuits: &'a Circuits,
+    breaker: &'a CircuitBreaker,
+    origin: Origin,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    /// Record the `result` of the request.
+    pub(crate) fn record(mut self, result: &Result<Response, Error>) {
+        let failed = match result {
+            Ok(res) => res.status().is_server_error(),
+            Err(_) => true,
+        };
+        self.recorded = true;
+        let circuit = match self.circuits.0.get(&self.origin) {
+            Some(circuit) => circuit,
+            None => return,
+        };
+        let mut state = circuit.lock().unwrap();
+        let breaker = self.breaker;
+        if self.probe {
+            *state = if failed {
+                State::Open {
+                    until: Instant::now() + breaker.cooldown,
+                }
+            } else {
+                State::closed()
+            };
+            return;
+        }
+        if let State::Closed {
+            since,
+            requests,
+            failures,
+        } = &mut *state
+        {
+            if since.elapsed() >= breaker.window {
+                *since = Instant::now();
+                *requests = 0;
+                *failures = 0;
+            }
+            *requests += 1;
+            *failures += failed as usize;
+            let ratio = *failures as f64 / *requests as f64;
+            if *requests >= breaker.min_requests && ratio >= breaker.failure_ratio {
+                log::debug!(
+                    "opening the circuit to {}:{} after {} of {} requests failed",
+                    self.origin.host,
+                    self.origin.port,
+                    failures,
+                    requests
+                );
+                *state = State::Open {
+                    until: Instant::now() + breaker.cooldown,
+                };
+            }
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        // A probe which was cancelled lets the next request probe instead.
+        if self.probe && !self.recorded {
+            if let Some(circuit) = self.circuits.0.get(&self.origin) {
+                *circuit.lock().unwrap() = State::HalfOpen { probing: false };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_types::StatusCode;
+
+    #[test]
+    fn opens_and_recovers() -> Result<(), Error> {
+        let breaker = CircuitBreaker::new()
+            .min_requests(4)
+            .cooldown(Duration::from_millis(50));
+        let circuits = Circuits::default();
+        let origin = Origin::from_url(&"http://example.test".parse()?)?;
+        let ok = Ok(Response::new(StatusCode::Ok));
+        let failed = Ok(Response::new(StatusCode::ServiceUnavailable));
+
+        for result in [&ok, &failed, &ok, &failed] {
+            circuits.enter(&breaker, origin.clone())?.record(result);
+        }
+        let err = circuits.enter(&breaker, origin.clone()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ClientError>(),
+            Some(&ClientError::CircuitOpen)
+        );
+
+        // Once the cooldown has passed a single probe is let through, and a failed one opens
+        // the circuit again.
+        std::thread::sleep(Duration::from_millis(60));
+        let probe = circuits.enter(&breaker, origin.clone())?;
+        assert!(circuits.enter(&breaker, origin.clone()).is_err());
+        probe.record(&failed);
+        assert!(circuits.enter(&breaker, origin.clone()).is_err());
+
+        // A cancelled probe doesn't count.
+        std::thread::sleep(Duration::from_millis(60));
+        drop(circuits.enter(&breaker, origin.clone())?);
+        let probe = circuits.enter(&breaker, origin.clone())?;
+        probe.record(&ok);
+
+        // A successful probe closes the circuit, counting requests afresh.
+        for _ in 0..3 {
+            circuits.enter(&breaker, origin.clone())?.record(&failed);
+        }
+        circuits.enter(&breaker, origin.clone())?.record(&failed);
+        assert!(circuits.enter(&breaker, origin).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Buffers for reading response heads and bodies and writing request bodies, reused across
+//! requests rather than allocated afresh for each, to cut allocator pressure in clients sending
+//! many requests.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// The most buffers kept for reuse, by all clients together.
+const MAX_POOLED: usize = 64;
+
+/// The largest buffer kept for reuse. Larger ones, grown for unusually long response heads or
+/// chunks, are freed rather than left holding on to their memory.
+const MAX_CAPACITY: usize = 64 * 1024;
+
+/// The pool buffers are taken from.
+static POOL: Pool = Pool::new();
+
+/// Buffers given back by the requests done with them.
+#[derive(Debug)]
+struct Pool(Mutex<Vec<Vec<u8>>>);
+
+impl Pool {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn take(&'static self, capacity: usize) -> Buffer {
+        let mut buf = self.0.lock().unwrap().pop().unwrap_or_default();
+        buf.reserve(capacity);
+        Buffer { buf, pool: self }
+    }
+
+    fn give_back(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut pooled = self.0.lock().unwrap();
+        if pooled.len() < MAX_POOLED {
+            pooled.push(buf);
+        }
+    }
+}
+
+/// A buffer taken from the pool, going back to it once dropped.
+#[derive(Debug)]
+pub(crate) struct Buffer {
+    buf: Vec<u8>,
+    pool: &'static Pool,
+}
+
+impl Buffer {
+    /// An empty buffer, with room for at least `capacity` bytes.
+    pub(crate) fn new(capacity: usize) -> Self {
+        POOL.take(capacity)
+    }
+
+    /// A buffer of `len` zeroes, to read into.
+    pub(crate) fn zeroed(len: usize) -> Self {
+        let mut buf = Self::new(len);
+        buf.resize(len, 0);
+        buf
+    }
+}
+
+impl Deref for Buffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for Buffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for Buffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_buffers() {
+        static POOL: Pool = Pool::new();
+
+        let mut buf = POOL.take(1024);
+        buf.extend_from_slice(b"abc");
+        let ptr = buf.as_ptr();
+        drop(buf);
+        let buf = POOL.take(16);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 1024);
+
+        // Large buffers aren't kept, nor more than `MAX_POOLED`.
+        drop(buf);
+        let mut large = POOL.take(MAX_CAPACITY + 1);
+        large.resize(MAX_CAPACITY + 1, 0);
+        drop(large);
+        assert!(POOL.0.lock().unwrap().is_empty());
+        let bufs = (0..MAX_POOLED + 1)
+            .map(|_| POOL.take(16))
+            .collect::<Vec<_>>();
+        drop(bufs);
+        assert_eq!(POOL.0.lock().unwrap().len(), MAX_POOLED);
+    }
+}
@@ -0,0 +1,888 @@
+//! Caching responses per RFC 7234, as configured by `Config::cache_store`.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use async_std::io::{Cursor, ReadExt};
+use bytes1::Bytes;
+use http_types::{Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use super::bytes_body;
+use crate::{Body, Error, Request, Response};
+
+/// The largest body which is cached. Larger responses are passed through uncached.
+const MAX_CACHED_BODY: usize = 8 * 1024 * 1024;
+
+/// The first line of the files `DiskCache` stores entries in, changed along with their format.
+const DISK_FORMAT: &str = "http-client-cache 1";
+
+/// Stores cached responses, as configured by `Config::cache_store`.
+///
+/// `GET` responses are stored if their `Cache-Control`, `Expires` and status allow, and served
+/// without a request for as long as they're fresh. Once stale, a request for them is sent with
+/// `If-None-Match` or `If-Modified-Since` to revalidate them, which a `304 Not Modified`
+/// response does. Responses to unsafe requests, like `POST`, remove the entry for their URL.
+///
+/// The cache is private, so responses marked `private` are stored too. Each response, whether
+/// from the cache or not, has a `CacheStatus` extension. Implement this to store responses
+/// somewhere other than in memory or on disk.
+pub trait CacheStore: Debug + Send + Sync + 'static {
+    /// The entry stored for `key`, if any.
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    /// Store `entry` for `key`, replacing any entry already stored for it.
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    /// Remove the entry stored for `key`, if any.
+    fn remove(&self, key: &str);
+}
+
+/// A cached response, and what's needed to decide whether it may still be served.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// The status code of the response.
+    pub status: u16,
+    /// The response's headers, by lowercase name.
+    pub headers: Vec<(String, String)>,
+    /// The response's body, which clones cheaply, as entries are cloned each time they're
+    /// served.
+    pub body: Bytes,
+    /// The values of the request headers its `Vary` header names, which a request must match to
+    /// be served the entry.
+    pub vary: Vec<(String, Option<String>)>,
+    /// When the request was sent.
+    pub request_time: SystemTime,
+    /// When the response was received.
+    pub response_time: SystemTime,
+}
+
+impl CacheEntry {
+    /// The value of the `name` header, with multiple values joined by commas.
+    fn header(&self, name: &str) -> Option<String> {
+        let values = self
+            .headers
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>();
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+
+    fn date(&self, name: &str) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.header(name)?.trim()).ok()
+    }
+
+    /// How long the response is fresh for after it was generated, per RFC 7234 section 4.2.1.
+    fn freshness_lifetime(&self) -> Duration {
+        let directives = directives(self.header("cache-control").as_deref());
+        if let Some(max_age) = duration(&directives, "max-age") {
+            return max_age;
+        }
+        let date = self.date("date").unwrap_or(self.response_time);
+        if self.header("expires").is_some() {
+            // Invalid dates, like `0`, mean the response has already expired.
+            let expires = self.date("expires").unwrap_or(SystemTime::UNIX_EPOCH);
+            return expires.duration_since(date).unwrap_or_default();
+        }
+        // Heuristically, a tenth of the time since it was last modified.
+        match self.date("last-modified") {
+            Some(modified) if cacheable_by_default(self.status) => {
+                date.duration_since(modified).unwrap_or_default() / 10
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// How old the response is now, per RFC 7234 section 4.2.3.
+    fn age(&self, now: SystemTime) -> Duration {
+        let apparent_age = self
+            .date("date")
+            .and_then(|date| self.response_time.duration_since(date).ok())
+            .unwrap_or_default();
+        let age = self
+            .header("age")
+            .and_then(|age| age.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let delay = self
+            .response_time
+            .duration_since(self.request_time)
+            .unwrap_or_default();
+        let resident = now.duration_since(self.response_time).unwrap_or_default();
+        apparent_age.max(age + delay) + resident
+    }
+
+    /// Whether the entry may be served to `req` without revalidating it.
+    fn is_fresh(&self, req: &Request, now: SystemTime) -> bool {
+        let response_directives = directives(self.header("cache-control").as_deref());
+        if has(&response_directives, "no-cache") {
+            return false;
+        }
+        let request_directives = directives(cache_control(req).as_deref());
+        if has(&request_directives, "no-cache") {
+            return false;
+        }
+        let mut lifetime = self.freshness_lifetime();
+        if let Some(max_age) = duration(&request_directives, "max-age") {
+            lifetime = lifetime.min(max_age);
+        }
+        let min_fresh = duration(&request_directives, "min-fresh").unwrap_or_default();
+        self.age(now) + min_fresh < lifetime
+    }
+
+    /// Whether `req` sends the same values of the headers the response varies on.
+    fn matches(&self, req: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| header_value(req, name) == *value)
+    }
+
+    fn to_response(&self, status: CacheStatus) -> Result<Response, Error> {
+        let mut res = Response::new(StatusCode::try_from(self.status)?);
+        for (name, value) in &self.headers {
+            res.append_header(name.as_str(), value.as_str());
+        }
+        let age = self.age(SystemTime::now()).as_secs();
+        res.insert_header("age", age.to_string());
+        res.set_body(bytes_body(self.body.clone()));
+        res.ext_mut().insert(status);
+        Ok(res)
+    }
+}
+
+/// Whether a response came from the cache, as an extension on each response passing through
+/// `Config::cache_store`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The response was served from the cache, without a request.
+    Hit,
+    /// The response was served from the cache, after the server said it's unchanged.
+    Revalidated,
+    /// The response came from the server.
+    Miss,
+}
+
+/// An in-memory `CacheStore`, holding up to a maximum number of entries.
+///
+/// Once it's full, storing another entry evicts the one stored longest ago.
+#[derive(Debug)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    max_entries: usize,
+}
+
+impl MemoryCache {
+    /// Create an empty cache holding up to 1024 entries.
+    pub fn new() -> Self {
+        Self::with_max_entries(1024)
+    }
+
+    /// Create an empty cache holding up to `max_entries` entries.
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+        }
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.response_time)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        if self.max_entries > 0 {
+            entries.insert(key.to_string(), entry);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// A `CacheStore` persisting entries as files in a directory, so they outlive the process.
+///
+/// Each entry is written to a temporary file first and then renamed into place, so a crash never
+/// leaves a truncated entry behind. Once the entries take up more than the byte budget, those
+/// used longest ago are removed. When the cache is opened again, entries are ordered by when
+/// their files were last modified, which is updated as they're used.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<DiskIndex>,
+}
+
+/// The size and last use of each entry file, by file name.
+#[derive(Debug, Default)]
+struct DiskIndex {
+    files: HashMap<String, (u64, u64)>,
+    bytes: u64,
+    clock: u64,
+}
+
+impl DiskIndex {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn insert(&mut self, file: String, size: u64) {
+        let used = self.tick();
+        if let Some((old_size, _)) = self.files.insert(file, (size, used)) {
+            self.bytes -= old_size;
+        }
+        self.bytes += size;
+    }
+
+    fn remove(&mut self, file: &str) -> bool {
+        match self.files.remove(file) {
+            Some((size, _)) => {
+                self.bytes -= size;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The file used longest ago.
+    fn oldest(&self) -> Option<String> {
+        let (file, _) = self.files.iter().min_by_key(|(_, (_, used))| *used)?;
+        Some(file.clone())
+    }
+}
+
+impl DiskCache {
+    /// Open the cache in `dir`, creating the directory if it doesn't exist yet, and holding up
+    /// to `max_bytes` bytes of entries.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut files = Vec::new();
+        for file in fs::read_dir(&dir)? {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".tmp") {
+                // Left behind by a write which never completed.
+                let _ = fs::remove_file(file.path());
+                continue;
+            }
+            if !name.ends_with(".entry") {
+                continue;
+            }
+            let metadata = file.metadata()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, name, metadata.len()));
+        }
+        files.sort();
+
+        let cache = Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(DiskIndex::default()),
+        };
+        let mut index = cache.index.lock().unwrap();
+        for (_, name, size) in files {
+            index.insert(name, size);
+        }
+        cache.evict(&mut index);
+        drop(index);
+        Ok(cache)
+    }
+
+    /// The directory the entries are persisted to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How many bytes the entries take up.
+    pub fn size(&self) -> u64 {
+        self.index.lock().unwrap().bytes
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) -> io::Result<()> {
+        let mut index = self.index.lock().unwrap();
+        for (file, _) in index.files.drain() {
+            remove_file(&self.dir.join(file))?;
+        }
+        index.bytes = 0;
+        Ok(())
+    }
+
+    /// Remove the entries used longest ago until they fit in the byte budget.
+    fn evict(&self, index: &mut DiskIndex) {
+        while index.bytes > self.max_bytes {
+            let oldest = match index.oldest() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            index.remove(&oldest);
+            if let Err(e) = remove_file(&self.dir.join(&oldest)) {
+                log::debug!("failed to evict cached {}: {}", oldest, e);
+            }
+        }
+    }
+
+    fn file_name(key: &str) -> String {
+        let digest = Sha256::digest(key.as_bytes());
+        let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}.entry", hex)
+    }
+
+    fn read(&self, key: &str, file: &str) -> io::Result<Option<CacheEntry>> {
+        let path = self.dir.join(file);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // Record the use, so it's known once the cache is opened again.
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now())?;
+        match decode_entry(contents.into()) {
+            Some((stored_key, entry)) if stored_key == key => Ok(Some(entry)),
+            Some(_) => Ok(None),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid entry")),
+        }
+    }
+
+    fn write(&self, file: &str, entry: &CacheEntry, key: &str) -> io::Result<u64> {
+        let contents = encode_entry(key, entry);
+        let random = RandomState::new().build_hasher().finish();
+        let tmp = self.dir.join(format!("{}.{:016x}.tmp", file, random));
+        fs::write(&tmp, &contents)?;
+        fs::rename(&tmp, self.dir.join(file))?;
+        Ok(contents.len() as u64)
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let file = Self::file_name(key);
+        {
+            let mut index = self.index.lock().unwrap();
+            let used = index.tick();
+            match index.files.get_mut(&file) {
+                Some((_, last_used)) => *last_used = used,
+                None => return None,
+            }
+        }
+        match self.read(key, &file) {
+            Ok(Some(entry)) => Some(entry),
+            Ok(None) => {
+                self.index.lock().unwrap().remove(&file);
+                None
+            }
+            Err(e) => {
+                log::debug!("failed to read cached {}: {}", key, e);
+                self.remove(key);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let file = Self::file_name(key);
+        match self.write(&file, &entry, key) {
+            Ok(size) => {
+                let mut index = self.index.lock().unwrap();
+                index.insert(file, size);
+                self.evict(&mut index);
+            }
+            Err(e) => log::debug!("failed to cache {}: {}", key, e),
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let file = Self::file_name(key);
+        if self.index.lock().unwrap().remove(&file) {
+            if let Err(e) = remove_file(&self.dir.join(&file)) {
+                log::debug!("failed to remove cached {}: {}", key, e);
+            }
+        }
+    }
+}
+
+/// Remove the file at `path`, if it still exists.
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Encode an entry stored for `key` as lines describing it, a blank line, then its body.
+fn encode_entry(key: &str, entry: &CacheEntry) -> Vec<u8> {
+    let nanos = |time: SystemTime| {
+        let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH);
+        since_epoch.unwrap_or_default().as_nanos()
+    };
+    let mut head = format!(
+        "{}\nkey {}\nstatus {}\nrequest-time {}\nresponse-time {}\n",
+        DISK_FORMAT,
+        key,
+        entry.status,
+        nanos(entry.request_time),
+        nanos(entry.response_time)
+    );
+    for (name, value) in &entry.vary {
+        match value {
+            Some(value) => head.push_str(&format!("vary {}: {}\n", name, value)),
+            None => head.push_str(&format!("vary {}\n", name)),
+        }
+    }
+    for (name, value) in &entry.headers {
+        head.push_str(&format!("header {}: {}\n", name, value));
+    }
+    head.push('\n');
+    let mut contents = head.into_bytes();
+    contents.extend_from_slice(&entry.body);
+    contents
+}
+
+/// Decode an entry encoded by `encode_entry`, and the key it was stored for.
+fn decode_entry(contents: Bytes) -> Option<(String, CacheEntry)> {
+    let end = contents.windows(2).position(|window| window == b"\n\n")?;
+    let head = std::str::from_utf8(&contents[..end]).ok()?;
+    let mut lines = head.lines();
+    if lines.next()? != DISK_FORMAT {
+        return None;
+    }
+    let time = |nanos: &str| {
+        let nanos: u64 = nanos.parse().ok()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos))
+    };
+    let header = |line: &str| {
+        let (name, value) = line.split_once(": ")?;
+        Some((name.to_string(), value.to_string()))
+    };
+    let mut key = None;
+    let mut entry = CacheEntry {
+        status: 0,
+        headers: Vec::new(),
+        body: contents.slice(end + 2..),
+        vary: Vec::new(),
+        request_time: SystemTime::UNIX_EPOCH,
+        response_time: SystemTime::UNIX_EPOCH,
+    };
+    for line in lines {
+        let (field, value) = line.split_once(' ')?;
+        match field {
+            "key" => key = Some(value.to_string()),
+            "status" => entry.status = value.parse().ok()?,
+            "request-time" => entry.request_time = time(value)?,
+            "response-time" => entry.response_time = time(value)?,
+            "vary" => entry.vary.push(match header(value) {
+                Some((name, value)) => (name, Some(value)),
+                None => (value.to_string(), None),
+            }),
+            "header" => entry.headers.push(header(value)?),
+            _ => return None,
+        }
+    }
+    Some((key?, entry))
+}
+
+/// What the cache has for a request.
+#[derive(Debug)]
+pub(crate) enum Lookup {
+    /// The request can't be served from the cache, nor its response stored.
+    Bypass,
+    /// Nothing is stored for the request.
+    Miss,
+    /// A fresh response.
+    Fresh(Box<Response>),
+    /// A stale entry, which the request has been made conditional on.
+    Stale(CacheEntry),
+}
+
+/// The key the response to `url` is stored under.
+pub(crate) fn key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.into()
+}
+
+/// Look up the response to `req` in `store`, making `req` conditional on a stale entry.
+pub(crate) fn lookup(
+    store: &dyn CacheStore,
+    key: &str,
+    req: &mut Request,
+) -> Result<Lookup, Error> {
+    let conditional = ["if-none-match", "if-modified-since", "if-match", "range"];
+    if req.method() != Method::Get
+        || conditional.iter().any(|name| req.header(*name).is_some())
+        || has(&directives(cache_control(req).as_deref()), "no-store")
+    {
+        return Ok(Lookup::Bypass);
+    }
+    let entry = match store.get(key) {
+        Some(entry) if entry.matches(req) => entry,
+        _ => return Ok(Lookup::Miss),
+    };
+    if entry.is_fresh(req, SystemTime::now()) {
+        let res = entry.to_response(CacheStatus::Hit)?;
+        return Ok(Lookup::Fresh(Box::new(res)));
+    }
+    if let Some(etag) = entry.header("etag") {
+        req.insert_header("if-none-match", etag);
+    } else if let Some(modified) = entry.header("last-modified") {
+        req.insert_header("if-modified-since", modified);
+    }
+    Ok(Lookup::Stale(entry))
+}
+
+/// Store `res`, the response to `req` sent at `request_time`, if it may be, and pass it on.
+pub(crate) async fn store(
+    store: &dyn CacheStore,
+    key: &str,
+    req: &Request,
+    mut res: Response,
+    request_time: SystemTime,
+) -> Result<Response, Error> {
+    res.ext_mut().insert(CacheStatus::Miss);
+    let headers = stored_headers(&res);
+    let vary = match vary(req, &res) {
+        Some(vary) => vary,
+        None => return Ok(res),
+    };
+    let mut entry = CacheEntry {
+        status: res.status().into(),
+        headers,
+        body: Bytes::new(),
+        vary,
+        request_time,
+        response_time: SystemTime::now(),
+    };
+    if !is_storable(req, &entry) || res.len().is_some_and(|len| len > MAX_CACHED_BODY) {
+        return Ok(res);
+    }
+
+    let len = res.len();
+    let mut body = res.take_body().take(MAX_CACHED_BODY as u64 + 1);
+    let mut bytes = Vec::new();
+    body.read_to_end(&mut bytes).await?;
+    if bytes.len() > MAX_CACHED_BODY {
+        let reader = Cursor::new(bytes).chain(body.into_inner());
+        res.set_body(Body::from_reader(reader, len));
+        return Ok(res);
+    }
+    let bytes = Bytes::from(bytes);
+    res.set_body(bytes_body(bytes.clone()));
+    entry.body = bytes;
+    store.put(key, entry);
+    Ok(res)
+}
+
+/// Update the stale `entry` with the headers of the `304 Not Modified` response `res` to the
+/// request sent to revalidate it at `request_time`, and serve it.
+pub(crate) fn revalidated(
+    store: &dyn CacheStore,
+    key: &str,
+    mut entry: CacheEntry,
+    res: &Response,
+    request_time: SystemTime,
+) -> Result<Response, Error> {
+    let headers = stored_headers(res);
+    let updated =
+        |name: &String| headers.iter().any(|(n, _)| n == name) && name != "content-length";
+    entry.headers.retain(|(name, _)| !updated(name));
+    entry.headers.extend(
+        headers
+            .into_iter()
+            .filter(|(name, _)| name != "content-length"),
+    );
+    entry.request_time = request_time;
+    entry.response_time = SystemTime::now();
+    let revalidated = entry.to_response(CacheStatus::Revalidated)?;
+    store.put(key, entry);
+    Ok(revalidated)
+}
+
+/// Remove the entry for the URL of an unsafe `method` request, if it succeeded, per RFC 7234
+/// section 4.4.
+pub(crate) fn invalidate(store: &dyn CacheStore, key: &str, method: Method, res: &Response) {
+    let safe = matches!(
+        method,
+        Method::Get | Method::Head | Method::Options | Method::Trace
+    );
+    if !safe && !res.status().is_client_error() && !res.status().is_server_error() {
+        store.remove(key);
+    }
+}
+
+/// Whether `entry`, the response to `req`, may be stored, and is worth storing.
+fn is_storable(req: &Request, entry: &CacheEntry) -> bool {
+    let response_directives = directives(entry.header("cache-control").as_deref());
+    if has(&response_directives, "no-store")
+        || has(&directives(cache_control(req).as_deref()), "no-store")
+    {
+        return false;
+    }
+    let explicit = has(&response_directives, "max-age") || entry.header("expires").is_some();
+    if !explicit && !cacheable_by_default(entry.status) {
+        return false;
+    }
+    let validator = entry.header("etag").is_some() || entry.header("last-modified").is_some();
+    validator || entry.freshness_lifetime() > Duration::ZERO
+}
+
+/// Whether responses with `status` may be cached without explicit freshness, per RFC 7231
+/// section 6.1.
+fn cacheable_by_default(status: u16) -> bool {
+    matches!(
+        status,
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// The values of the request headers the response `res` varies on, or `None` if it varies on
+/// something other than headers.
+fn vary(req: &Request, res: &Response) -> Option<Vec<(String, Option<String>)>> {
+    let mut vary = Vec::new();
+    for values in res.header("vary").into_iter() {
+        for name in values.iter().flat_map(|value| value.as_str().split(',')) {
+            let name = name.trim().to_ascii_lowercase();
+            match name.as_str() {
+                "" => {}
+                "*" => return None,
+                _ => {
+                    let value = header_value(req, &name);
+                    vary.push((name, value));
+                }
+            }
+        }
+    }
+    Some(vary)
+}
+
+/// The headers of `res` worth storing, leaving out those about the connection it arrived on.
+fn stored_headers(res: &Response) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    for (name, values) in res.iter() {
+        let name = name.as_str().to_ascii_lowercase();
+        if matches!(
+            name.as_str(),
+            "connection" | "keep-alive" | "transfer-encoding"
+        ) {
+            continue;
+        }
+        for value in values.iter() {
+            headers.push((name.clone(), value.as_str().to_string()));
+        }
+    }
+    headers
+}
+
+fn header_value(req: &Request, name: &str) -> Option<String> {
+    let values = req.header(name)?;
+    let values = values
+        .iter()
+        .map(|value| value.as_str())
+        .collect::<Vec<_>>();
+    Some(values.join(", "))
+}
+
+fn cache_control(req: &Request) -> Option<String> {
+    header_value(req, "cache-control")
+}
+
+/// The directives of a `Cache-Control` header, by lowercase name.
+fn directives(cache_control: Option<&str>) -> Vec<(String, Option<String>)> {
+    let cache_control = match cache_control {
+        Some(cache_control) => cache_control,
+        None => return Vec::new(),
+    };
+    cache_control
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_ascii_lowercase(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (directive.to_ascii_lowercase(), None),
+        })
+        .collect()
+}
+
+fn has(directives: &[(String, Option<String>)], name: &str) -> bool {
+    directives.iter().any(|(n, _)| n == name)
+}
+
+fn duration(directives: &[(String, Option<String>)], name: &str) -> Option<Duration> {
+    directives
+        .iter()
+        .find(|(n, _)| n == name)
+        .and_then(|(_, value)| value.as_ref()?.parse().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(headers: &[(&str, &str)]) -> CacheEntry {
+        let now = SystemTime::now();
+        CacheEntry {
+            status: 200,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Bytes::from_static(b"cached"),
+            vary: Vec::new(),
+            request_time: now,
+            response_time: now,
+        }
+    }
+
+    fn get() -> Request {
+        Request::get("http://example.test/")
+    }
+
+    #[test]
+    fn computes_freshness() {
+        let now = SystemTime::now();
+        let date = httpdate::fmt_http_date(now);
+        let hour_ago = httpdate::fmt_http_date(now - Duration::from_secs(3600));
+        let in_a_minute = httpdate::fmt_http_date(now + Duration::from_secs(60));
+
+        let fresh = entry(&[("cache-control", "public, max-age=60")]);
+        assert!(fresh.is_fresh(&get(), now));
+        assert!(!fresh.is_fresh(&get(), now + Duration::from_secs(61)));
+        // `Age` counts towards the age, as does the request's `max-age`.
+        let aged = entry(&[("cache-control", "max-age=60"), ("age", "60")]);
+        assert!(!aged.is_fresh(&get(), now));
+        let mut req = get();
+        req.insert_header("Cache-Control", "max-age=0");
+        assert!(!fresh.is_fresh(&req, now));
+
+        let expires = entry(&[("date", &date), ("expires", &in_a_minute)]);
+        assert_eq!(expires.freshness_lifetime().as_secs(), 60);
+        assert_eq!(
+            entry(&[("expires", "0")]).freshness_lifetime(),
+            Duration::ZERO
+        );
+        let heuristic = entry(&[("date", &date), ("last-modified", &hour_ago)]);
+        assert_eq!(heuristic.freshness_lifetime().as_secs(), 360);
+        assert!(!entry(&[("cache-control", "max-age=60, no-cache")]).is_fresh(&get(), now));
+    }
+
+    #[test]
+    fn decides_what_is_storable() {
+        let req = get();
+        assert!(is_storable(
+            &req,
+            &entry(&[("cache-control", "max-age=60")])
+        ));
+        assert!(is_storable(&req, &entry(&[("etag", "\"v1\"")])));
+        assert!(!is_storable(&req, &entry(&[])));
+        assert!(!is_storable(
+            &req,
+            &entry(&[("cache-control", "no-store, max-age=60")])
+        ));
+        let mut created = entry(&[("etag", "\"v1\"")]);
+        created.status = 201;
+        assert!(!is_storable(&req, &created));
+
+        let mut res = Response::new(StatusCode::Ok);
+        res.insert_header("Vary", "Accept-Language, accept");
+        let mut req = get();
+        req.insert_header("Accept-Language", "en");
+        let vary = vary(&req, &res).unwrap();
+        assert_eq!(
+            vary,
+            [
+                ("accept-language".to_string(), Some("en".to_string())),
+                ("accept".to_string(), None)
+            ]
+        );
+        res.insert_header("Vary", "*");
+        assert_eq!(super::vary(&req, &res), None);
+    }
+
+    #[test]
+    fn disk_cache_persists_entries() -> io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("http-client-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut stored = entry(&[("etag", "\"v1\""), ("x-empty", "")]);
+        stored.vary = vec![
+            ("accept".to_string(), Some("text/html".to_string())),
+            ("accept-language".to_string(), None),
+        ];
+        stored.body = Bytes::from_static(b"line\n\nbreaks");
+
+        let cache = DiskCache::open(&dir, 1400)?;
+        cache.put("http://example.test/a", stored.clone());
+        cache.put("http://example.test/b", entry(&[]));
+        assert!(cache.get("http://example.test/a").is_some());
+        drop(cache);
+
+        // Entries survive reopening, and once over budget, the one used longest ago goes.
+        let cache = DiskCache::open(&dir, 1400)?;
+        assert_eq!(cache.get("http://example.test/a"), Some(stored));
+        let mut large = entry(&[]);
+        large.body = vec![0; 1000].into();
+        cache.put("http://example.test/c", large);
+        assert!(cache.get("http://example.test/b").is_none());
+        assert!(cache.get("http://example.test/a").is_some());
+        assert!(cache.size() <= 1400);
+
+        cache.remove("http://example.test/a");
+        assert!(cache.get("http://example.test/a").is_none());
+        cache.clear()?;
+        assert_eq!(fs::read_dir(&dir)?.count(), 0);
+        fs::remove_dir_all(&dir)
+    }
+
+    #[test]
+    fn memory_cache_evicts_oldest() {
+        let cache = MemoryCache::with_max_entries(2);
+        let mut old = entry(&[]);
+        old.response_time -= Duration::from_secs(10);
+        cache.put("a", old);
+        cache.put("b", entry(&[]));
+        cache.put("c", entry(&[]));
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some() && cache.get("c").is_some());
+        cache.remove("b");
+        assert!(cache.get("b").is_none());
+    }
+}
@@ -0,0 +1,236 @@
+//! Decoding response bodies sent with chunked transfer encoding, RFC 9112 section 7.1.
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use async_std::io::{self, BufRead, Read};
+use http_types::headers::{HeaderName, HeaderValue};
+use http_types::trailers::{Sender, Trailers};
+
+/// The longest chunk size line read, with any chunk extensions.
+const MAX_SIZE_LINE_LEN: usize = 1024;
+
+/// The longest trailer section read.
+const MAX_TRAILERS_LEN: usize = 8 * 1024;
+
+/// A chunked body read from `inner`, passing its trailers on to the response's `Sender`.
+pub(crate) struct ChunkedDecoder<R> {
+    inner: R,
+    state: State,
+    /// What's been read of the chunk size line or trailer section being read.
+    line: Vec<u8>,
+    trailers: Option<Sender>,
+    max_trailers: usize,
+}
+
+enum State {
+    Size,
+    Data(u64),
+    DataEnd,
+    Trailers,
+    Sending(Pin<Box<dyn Future<Output = ()> + Send + Sync>>),
+    Done,
+}
+
+impl<R> Debug for ChunkedDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkedDecoder").finish_non_exhaustive()
+    }
+}
+
+impl<R: BufRead + Unpin> ChunkedDecoder<R> {
+    /// Decode the body read from `inner`, with at most `max_trailers` trailers.
+    pub(crate) fn new(inner: R, trailers: Sender, max_trailers: usize) -> Self {
+        Self {
+            inner,
+            state: State::Size,
+            line: Vec::new(),
+            trailers: Some(trailers),
+            max_trailers,
+        }
+    }
+
+    /// Read up to and including the next LF onto `line`, failing once it would be longer than
+    /// `max`.
+    fn poll_line(&mut self, cx: &mut Context<'_>, max: usize) -> Poll<io::Result<()>> {
+        loop {
+            let available = ready!(Pin::new(&mut self.inner).poll_fill_buf(cx))?;
+            if available.is_empty() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed during a chunked body",
+                )));
+            }
+            let (n, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(idx) => (idx + 1, true),
+                None => (available.len(), false),
+            };
+            if self.line.len() + n > max {
+                return Poll::Ready(Err(invalid("chunk size line or trailers too long")));
+            }
+            self.line.extend_from_slice(&available[..n]);
+            Pin::new(&mut self.inner).consume(n);
+            if done {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// Parse the trailer section read into `line`, which ends with a blank line.
+    fn trailers(&self) -> io::Result<Trailers> {
+        let mut headers = vec![httparse::EMPTY_HEADER; self.max_trailers];
+        let parsed = match httparse::parse_headers(&self.line, &mut headers) {
+            Ok(httparse::Status::Complete((_, parsed))) => parsed,
+            Ok(httparse::Status::Partial) => return Err(invalid("incomplete trailers")),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let mut trailers = Trailers::new();
+        for header in parsed {
+            let name = HeaderName::from_bytes(header.name.as_bytes().to_vec());
+            let value = HeaderValue::from_bytes(header.value.to_vec());
+            match (name, value) {
+                (Ok(name), Ok(value)) => trailers.append(name, value),
+                _ => return Err(invalid("invalid trailer")),
+            }
+        }
+        Ok(trailers)
+    }
+}
+
+impl<R: BufRead + Unpin> Read for ChunkedDecoder<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            match &mut this.state {
+                State::Size => {
+                    ready!(this.poll_line(cx, MAX_SIZE_LINE_LEN))?;
+                    let size = parse_size(&this.line)?;
+                    this.line.clear();
+                    this.state = match size {
+                        0 => State::Trailers,
+                        size => State::Data(size),
+                    };
+                }
+                State::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Poll::Ready(Ok(0));
+                    }
+                    let available = ready!(Pin::new(&mut this.inner).poll_fill_buf(cx))?;
+                    if available.is_empty() {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "connection closed during a chunked body",
+                        )));
+                    }
+                    let n = available.len().min(buf.len());
+                    let n = (*remaining).min(n as u64) as usize;
+                    buf[..n].copy_from_slice(&available[..n]);
+                    Pin::new(&mut this.inner).consume(n);
+                    *remaining -= n as u64;
+                    if *remaining == 0 {
+                        this.state = State::DataEnd;
+                    }
+                    return Poll::Ready(Ok(n));
+                }
+                State::DataEnd => {
+                    ready!(this.poll_line(cx, 2))?;
+                    if !matches!(&this.line[..], b"\r\n" | b"\n") {
+                        return Poll::Ready(Err(invalid("chunk not followed by CRLF")));
+                    }
+                    this.line.clear();
+                    this.state = State::Size;
+                }
+                State::Trailers => {
+                    // The section ends with a blank line, which is all of it without trailers.
+                    while !(matches!(&this.line[..], b"\r\n" | b"\n")
+                        || this.line.ends_with(b"\n\r\n")
+                        || this.line.ends_with(b"\n\n"))
+                    {
+                        ready!(this.poll_line(cx, MAX_TRAILERS_LEN))?;
+                    }
+                    let trailers = this.trailers()?;
+                    this.line = Vec::new();
+                    this.state = match this.trailers.take() {
+                        Some(sender) => State::Sending(Box::pin(sender.send(trailers))),
+                        None => State::Done,
+                    };
+                }
+                State::Sending(sending) => {
+                    ready!(sending.as_mut().poll(cx));
+                    this.state = State::Done;
+                }
+                State::Done => return Poll::Ready(Ok(0)),
+            }
+        }
+    }
+}
+
+/// Parse a chunk size line, ignoring any chunk extensions.
+fn parse_size(line: &[u8]) -> io::Result<u64> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let size = match line.iter().position(|&b| b == b';') {
+        Some(idx) => &line[..idx],
+        None => line,
+    };
+    let size = std::str::from_utf8(size)
+        .map(|size| size.trim_matches(|c| c == ' ' || c == '\t'))
+        .map_err(|_| invalid("invalid chunk size"))?;
+    if size.is_empty() || size.len() > 16 || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid("invalid chunk size"));
+    }
+    u64::from_str_radix(size, 16).map_err(|_| invalid("invalid chunk size"))
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::io::{BufReader, Cursor, ReadExt};
+    use http_types::Response;
+
+    async fn decode(body: &[u8]) -> io::Result<(Vec<u8>, Option<Trailers>)> {
+        let mut res = Response::new(200);
+        let reader = BufReader::with_capacity(3, Cursor::new(body.to_vec()));
+        let mut decoder = ChunkedDecoder::new(reader, res.send_trailers(), 4);
+        let mut decoded = Vec::new();
+        decoder.read_to_end(&mut decoded).await?;
+        Ok((decoded, res.recv_trailers().await))
+    }
+
+    #[async_std::test]
+    async fn decodes_chunks() -> io::Result<()> {
+        let body = b"3\r\nabc\r\n5;name=value\r\ndefgh\r\n0\r\nDigest: sha-256=x\r\n\r\n";
+        let (decoded, trailers) = decode(body).await?;
+        assert_eq!(decoded, b"abcdefgh");
+        let trailers = trailers.unwrap();
+        assert_eq!(trailers["digest"], "sha-256=x");
+
+        let (decoded, trailers) = decode(b"A\r\n0123456789\r\n0\r\n\r\n").await?;
+        assert_eq!(decoded, b"0123456789");
+        assert!(trailers.unwrap().iter().next().is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rejects_malformed_chunks() {
+        for body in [
+            &b"x\r\nabc\r\n0\r\n\r\n"[..],
+            b"3\r\nabcd\r\n0\r\n\r\n",
+            b"11111111111111111\r\n",
+            b"3\r\nab",
+            b"0\r\na: 1\r\nb: 2\r\nc: 3\r\nd: 4\r\ne: 5\r\n\r\n",
+        ] {
+            assert!(decode(body).await.is_err(), "{:?}", body);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Establishing connections with a custom `Connect`, instead of over TCP.
+
+use std::fmt::Debug;
+
+use async_std::io::{self, Read, Write};
+
+use crate::async_trait;
+
+/// A byte stream requests can be sent over, as established by a `Connect`.
+///
+/// Implemented for every stream which can be read from and written to across tasks, such as
+/// `async_std::os::unix::net::UnixStream` or an in-memory duplex.
+pub trait Connection: Read + Write + Send + Sync + Unpin + 'static {}
+
+impl<T: Read + Write + Send + Sync + Unpin + 'static> Connection for T {}
+
+/// Establishes the connections requests are sent over, in place of TCP connections to servers
+/// and their proxies, e.g. to tunnel through SSH, reach VSOCK services, or test without a
+/// network.
+///
+/// Set one with `Config::set_connector`. The connections it returns are pooled just the same,
+/// by the scheme, host and port they're for, and HTTPS requests are sent in TLS over them. No
+/// proxy is used alongside it, though Unix sockets set for an authority still take precedence.
+#[async_trait]
+pub trait Connect: Debug + Send + Sync + 'static {
+    /// Open a connection to `host` on `port`.
+    async fn connect(&self, host: &str, port: u16) -> io::Result<Box<dyn Connection>>;
+}
@@ -0,0 +1,430 @@
+//! Storing the cookies set by responses, and sending them with later requests.
+
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Write as _};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http_types::cookies::Cookie;
+use http_types::Url;
+
+/// Stores the cookies set by responses, and provides those to send with each request, as
+/// configured by `Config::cookie_store`.
+///
+/// Requests are sent with the cookies the store provides for their URL, on every redirect they
+/// follow, and the `Set-Cookie` headers of every response are stored. Implement this to persist
+/// cookies, or to share them with something else.
+pub trait CookieStore: Debug + Send + Sync + 'static {
+    /// Store the cookies set by the `Set-Cookie` header values of a response from `url`.
+    fn set_cookies(&self, url: &Url, set_cookie_headers: &mut dyn Iterator<Item = &str>);
+
+    /// The value of the `Cookie` header to send with a request to `url`, if any.
+    fn cookies(&self, url: &Url) -> Option<String>;
+}
+
+/// An in-memory `CookieStore`, following the semantics of RFC 6265.
+///
+/// Cookies are matched to requests by domain and path, `Secure` cookies are only sent over (and
+/// accepted from) HTTPS, and cookies are dropped once they expire. Public suffixes aren't
+/// checked, so a response may set a cookie for e.g. the whole `co.uk` domain.
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<StoredCookie>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct StoredCookie {
+    name: String,
+    value: String,
+    domain: String,
+    // Only sent to `domain` itself, rather than to its subdomains too, as the cookie didn't
+    // specify a domain.
+    host_only: bool,
+    path: String,
+    secure: bool,
+    // `None` for session cookies, kept until the jar is dropped.
+    expires: Option<SystemTime>,
+}
+
+impl CookieJar {
+    /// Create an empty cookie jar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Store the cookie of a single `Set-Cookie` header value, unless it's invalid for `url`.
+    fn set_cookie(&self, url: &Url, set_cookie: &str) {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return,
+        };
+        let cookie = match Cookie::parse(set_cookie) {
+            Ok(cookie) => cookie,
+            Err(e) => {
+                log::debug!("ignoring invalid cookie from {}: {}", host, e);
+                return;
+            }
+        };
+
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) if !domain.is_empty() => {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                if !domain_matches(&host, &domain) {
+                    return;
+                }
+                (domain, false)
+            }
+            _ => (host, true),
+        };
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            _ => default_path(url),
+        };
+        let secure = cookie.secure().unwrap_or(false);
+        if secure && url.scheme() != "https" {
+            return;
+        }
+        // `Max-Age` takes precedence over `Expires`.
+        let expires = match (cookie.max_age(), cookie.expires()) {
+            (Some(max_age), _) => {
+                let seconds = u64::try_from(max_age.whole_seconds()).unwrap_or(0);
+                Some(SystemTime::now() + Duration::from_secs(seconds))
+            }
+            (None, Some(expires)) => Some(SystemTime::from(expires)),
+            (None, None) => None,
+        };
+
+        let stored = StoredCookie {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain,
+            host_only,
+            path,
+            secure,
+            expires,
+        };
+        let mut cookies = self.cookies.lock().unwrap();
+        // A cookie replaces the one with the same name, domain and path, keeping its place so
+        // cookies are still sent in the order they were first set. An expired one removes it.
+        let existing = cookies.iter().position(|c| {
+            c.name == stored.name && c.domain == stored.domain && c.path == stored.path
+        });
+        match (existing, stored.is_expired(SystemTime::now())) {
+            (Some(idx), true) => drop(cookies.remove(idx)),
+            (Some(idx), false) => cookies[idx] = stored,
+            (None, true) => {}
+            (None, false) => cookies.push(stored),
+        }
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, url: &Url, set_cookie_headers: &mut dyn Iterator<Item = &str>) {
+        for set_cookie in set_cookie_headers {
+            self.set_cookie(url, set_cookie);
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let now = SystemTime::now();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let mut matching = cookies
+            .iter()
+            .filter(|cookie| {
+                let domain_ok = if cookie.host_only {
+                    host == cookie.domain
+                } else {
+                    domain_matches(&host, &cookie.domain)
+                };
+                domain_ok
+                    && path_matches(url.path(), &cookie.path)
+                    && (!cookie.secure || url.scheme() == "https")
+            })
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            return None;
+        }
+        // Cookies with longer paths are sent first; the sort is stable, so others keep the
+        // order they were set in.
+        matching.sort_by_key(|cookie| Reverse(cookie.path.len()));
+        let pairs = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>();
+        Some(pairs.join("; "))
+    }
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Parse a line of a Netscape `cookies.txt` file.
+    fn from_netscape(line: &str) -> Option<Self> {
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        let fields = line.split('\t').collect::<Vec<_>>();
+        if let [domain, include_subdomains, path, secure, expires, name, value] = fields[..] {
+            let expires = match expires.parse::<u64>().ok()? {
+                0 => None,
+                secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+            };
+            Some(Self {
+                name: name.to_string(),
+                value: value.to_string(),
+                domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+                host_only: include_subdomains != "TRUE",
+                path: path.to_string(),
+                secure: secure == "TRUE",
+                expires,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Format the cookie as a line of a Netscape `cookies.txt` file.
+    fn to_netscape(&self) -> String {
+        let bool = |b| if b { "TRUE" } else { "FALSE" };
+        let expires = self
+            .expires
+            .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |expires| expires.as_secs().max(1));
+        format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.host_only { "" } else { "." },
+            self.domain,
+            bool(!self.host_only),
+            self.path,
+            bool(self.secure),
+            expires,
+            self.name,
+            self.value
+        )
+    }
+}
+
+/// A `CookieStore` persisting its cookies to a file in the Netscape `cookies.txt` format, as
+/// used by curl and wget, so sessions survive restarts.
+///
+/// The file is loaded when the store is opened, and rewritten whenever a response sets
+/// cookies. Session cookies, without an expiry, are persisted too.
+#[derive(Debug)]
+pub struct FileCookieStore {
+    path: PathBuf,
+    jar: CookieJar,
+}
+
+impl FileCookieStore {
+    /// Open the store persisted at `path`, loading the cookies it holds. The file is created
+    /// once cookies are first set, if it doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let jar = CookieJar::new();
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let now = SystemTime::now();
+                let cookies = contents
+                    .lines()
+                    .filter_map(StoredCookie::from_netscape)
+                    .filter(|cookie| !cookie.is_expired(now));
+                jar.cookies.lock().unwrap().extend(cookies);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self { path, jar })
+    }
+
+    /// The path the cookies are persisted to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the cookies to the file, replacing it.
+    pub fn save(&self) -> io::Result<()> {
+        let mut contents = String::from("# Netscape HTTP Cookie File\n");
+        let now = SystemTime::now();
+        for cookie in self.jar.cookies.lock().unwrap().iter() {
+            if !cookie.is_expired(now) {
+                writeln!(contents, "{}", cookie.to_netscape()).unwrap();
+            }
+        }
+        // Written to a temporary file first, so a crash never leaves a truncated file behind.
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl CookieStore for FileCookieStore {
+    fn set_cookies(&self, url: &Url, set_cookie_headers: &mut dyn Iterator<Item = &str>) {
+        let mut set_cookie_headers = set_cookie_headers.peekable();
+        if set_cookie_headers.peek().is_none() {
+            return;
+        }
+        self.jar.set_cookies(url, &mut set_cookie_headers);
+        if let Err(e) = self.save() {
+            log::debug!("failed to save cookies to {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<String> {
+        self.jar.cookies(url)
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains. IP addresses only match themselves.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_ok();
+    !is_ip
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+/// Whether a request to `path` falls under a cookie's `cookie_path`.
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path.as_bytes()[cookie_path.len()] == b'/'))
+}
+
+/// The path of cookies set without one: the directory of the request's path.
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(idx) => url.path()[..idx].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn matches_domains_and_paths() {
+        let jar = CookieJar::new();
+        let origin = url("https://www.example.test/account/login");
+        jar.set_cookies(
+            &origin,
+            &mut [
+                "host=1",
+                "domain=2; Domain=.example.test",
+                "root=3; Domain=example.test; Path=/",
+                "secure=4; Secure; Path=/",
+                "other=5; Domain=other.test",
+            ]
+            .iter()
+            .copied(),
+        );
+
+        assert_eq!(
+            jar.cookies(&url("https://www.example.test/account/settings"))
+                .as_deref(),
+            Some("host=1; domain=2; root=3; secure=4")
+        );
+        // Only cookies set for the whole domain are sent to its other subdomains, and only
+        // those under the request's path.
+        assert_eq!(
+            jar.cookies(&url("https://api.example.test/")).as_deref(),
+            Some("root=3")
+        );
+        assert_eq!(
+            jar.cookies(&url("https://api.example.test/account/x"))
+                .as_deref(),
+            Some("domain=2; root=3")
+        );
+        assert_eq!(
+            jar.cookies(&url("http://www.example.test/")).as_deref(),
+            Some("root=3")
+        );
+        assert_eq!(jar.cookies(&url("https://other.test/")), None);
+        assert_eq!(jar.cookies(&url("https://notexample.test/")), None);
+    }
+
+    #[test]
+    fn persists_cookies_to_a_file() -> io::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("http-client-cookies-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let origin = url("https://www.example.test/");
+
+        let store = FileCookieStore::open(&path)?;
+        store.set_cookies(
+            &origin,
+            &mut [
+                "session=abc",
+                "remember=1; Domain=example.test; Max-Age=3600; Secure",
+                "gone=1; Max-Age=0",
+            ]
+            .iter()
+            .copied(),
+        );
+        drop(store);
+
+        let store = FileCookieStore::open(&path)?;
+        assert_eq!(
+            store.cookies(&origin).as_deref(),
+            Some("session=abc; remember=1")
+        );
+        assert_eq!(
+            store.cookies(&url("https://api.example.test/")).as_deref(),
+            Some("remember=1")
+        );
+        fs::remove_file(&path)
+    }
+
+    #[test]
+    fn replaces_and_expires_cookies() {
+        let jar = CookieJar::new();
+        let origin = url("http://example.test/");
+        jar.set_cookies(
+            &origin,
+            &mut ["a=1", "b=2; Max-Age=3600", "c=3"].iter().copied(),
+        );
+        jar.set_cookies(
+            &origin,
+            &mut [
+                "a=changed",
+                "b=gone; Max-Age=0",
+                "c=gone; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(jar.cookies(&origin).as_deref(), Some("a=changed"));
+
+        // Secure cookies aren't accepted over plain HTTP.
+        jar.set_cookies(&origin, &mut ["d=4; Secure"].iter().copied());
+        assert_eq!(
+            jar.cookies(&url("https://example.test/")).as_deref(),
+            Some("a=changed")
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Asking for compressed responses, and decompressing their bodies as they're read.
+
+#[cfg(feature = "brotli")]
+use async_compression::futures::bufread::BrotliDecoder;
+#[cfg(feature = "zstd")]
+use async_compression::futures::bufread::ZstdDecoder;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_compression::futures::bufread::{GzipDecoder, ZlibDecoder};
+use async_std::io::{self, BufRead, BufReader, Read};
+use http_types::Method;
+
+use super::ClientError;
+use crate::{Body, Config, Request, Response};
+
+/// How much a body may decompress to before `Config::max_decompression_ratio` is checked, as
+/// small bodies may well compress far better than large ones.
+const RATIO_GRACE: u64 = 1024 * 1024;
+
+/// The encodings listed in the `Accept-Encoding` sent with requests, which `decode` handles.
+const ACCEPTED: &[&str] = &[
+    "gzip",
+    "deflate",
+    #[cfg(feature = "brotli")]
+    "br",
+    #[cfg(feature = "zstd")]
+    "zstd",
+];
+
+/// A content coding of response bodies, which can be decompressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Gzip,
+    // "deflate" is the zlib format, per RFC 7230.
+    Deflate,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    fn from_header(value: &str) -> Option<Self> {
+        match value.trim() {
+            v if v.eq_ignore_ascii_case("gzip") || v.eq_ignore_ascii_case("x-gzip") => {
+                Some(Encoding::Gzip)
+            }
+            v if v.eq_ignore_ascii_case("deflate") => Some(Encoding::Deflate),
+            #[cfg(feature = "brotli")]
+            v if v.eq_ignore_ascii_case("br") => Some(Encoding::Brotli),
+            #[cfg(feature = "zstd")]
+            v if v.eq_ignore_ascii_case("zstd") => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Ask for a compressed response to `req`, unless it already says which encodings it accepts,
+/// returning whether the response should be decompressed.
+pub(crate) fn accept(req: &mut Request) -> bool {
+    if req.header("Accept-Encoding").is_some() {
+        return false;
+    }
+    req.insert_header("Accept-Encoding", ACCEPTED.join(", "));
+    true
+}
+
+/// Decompress the body of `res` as it's read, if it's encoded with one of the encodings asked
+/// for, within the limits of `Config::max_decompressed_size` and
+/// `Config::max_decompression_ratio`. The `Content-Encoding` and `Content-Length` headers
+/// describe the compressed body, so they're removed.
+pub(crate) fn decode(method: Method, mut res: Response, config: &Config) -> Response {
+    // Responses with several encodings applied are left for the caller to decode.
+    let encoding = match res.header("Content-Encoding") {
+        Some(values) if values.iter().count() == 1 => {
+            match Encoding::from_header(values.last().as_str()) {
+                Some(encoding) => encoding,
+                None => return res,
+            }
+        }
+        _ => return res,
+    };
+    if method == Method::Head || res.len() == Some(0) {
+        return res;
+    }
+
+    let body = res.take_body();
+    let mime = body.mime().clone();
+    let compressed = Arc::new(AtomicU64::new(0));
+    let body = Counted {
+        body,
+        read: compressed.clone(),
+    };
+    let decoder: Box<dyn Read + Unpin + Send + Sync> = match encoding {
+        Encoding::Gzip => {
+            let mut decoder = GzipDecoder::new(body);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Encoding::Deflate => Box::new(ZlibDecoder::new(body)),
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => Box::new(BrotliDecoder::new(body)),
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => Box::new(ZstdDecoder::new(body)),
+    };
+    let limited = Limited {
+        decoder,
+        compressed,
+        decompressed: 0,
+        max_size: config.max_decompressed_size,
+        max_ratio: config.max_decompression_ratio,
+    };
+    let mut body = Body::from_reader(BufReader::new(limited), None);
+    body.set_mime(mime);
+    res.remove_header("Content-Encoding");
+    res.remove_header("Content-Length");
+    res.set_body(body);
+    res
+}
+
+/// A compressed body, counting how much of it the decoder has read.
+struct Counted {
+    body: Body,
+    read: Arc<AtomicU64>,
+}
+
+impl Read for Counted {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.body).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl BufRead for Counted {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().body).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.read.fetch_add(amt as u64, Ordering::Relaxed);
+        Pin::new(&mut self.body).consume(amt)
+    }
+}
+
+/// A decompressed body, which fails to be read once it exceeds the configured limits.
+struct Limited {
+    decoder: Box<dyn Read + Unpin + Send + Sync>,
+    compressed: Arc<AtomicU64>,
+    decompressed: u64,
+    max_size: Option<u64>,
+    max_ratio: Option<u64>,
+}
+
+impl Limited {
+    fn exceeded(&self) -> bool {
+        let compressed = self.compressed.load(Ordering::Relaxed);
+        self.max_size.is_some_and(|max| self.decompressed > max)
+            || self.max_ratio.is_some_and(|ratio| {
+                self.decompressed > RATIO_GRACE
+                    && self.decompressed > compressed.saturating_mul(ratio)
+            })
+    }
+}
+
+impl Read for Limited {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = match Pin::new(&mut self.decoder).poll_read(cx, buf) {
+            Poll::Ready(Ok(n)) => n,
+            poll => return poll,
+        };
+        self.decompressed += n as u64;
+        if self.exceeded() {
+            let error = ClientError::DecompressionLimitExceeded;
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, error)));
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "brotli")]
+    use async_compression::futures::bufread::BrotliEncoder;
+    #[cfg(feature = "zstd")]
+    use async_compression::futures::bufread::ZstdEncoder;
+    use async_compression::futures::bufread::{GzipEncoder, ZlibEncoder};
+    use async_std::io::{self, ReadExt};
+    use http_types::StatusCode;
+
+    async fn compress(encoding: Encoding, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut compressed = Vec::new();
+        match encoding {
+            Encoding::Gzip => GzipEncoder::new(data).read_to_end(&mut compressed).await?,
+            Encoding::Deflate => ZlibEncoder::new(data).read_to_end(&mut compressed).await?,
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                BrotliEncoder::new(data)
+                    .read_to_end(&mut compressed)
+                    .await?
+            }
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => ZstdEncoder::new(data).read_to_end(&mut compressed).await?,
+        };
+        Ok(compressed)
+    }
+
+    fn response(content_encoding: &str, body: Vec<u8>) -> Response {
+        let mut res = Response::new(StatusCode::Ok);
+        res.insert_header("Content-Encoding", content_encoding);
+        res.set_body(body);
+        res
+    }
+
+    #[async_std::test]
+    async fn decodes_supported_encodings() -> http_types::Result<()> {
+        let encodings = [
+            (Encoding::Gzip, "gzip"),
+            (Encoding::Deflate, "deflate"),
+            #[cfg(feature = "brotli")]
+            (Encoding::Brotli, "br"),
+            #[cfg(feature = "zstd")]
+            (Encoding::Zstd, "zstd"),
+        ];
+        for (encoding, name) in encodings.iter().copied() {
+            let res = response(name, compress(encoding, b"hello world").await?);
+            let mut res = decode(Method::Get, res, &Config::new());
+            assert!(res.header("Content-Encoding").is_none());
+            assert_eq!(res.len(), None);
+            assert_eq!(res.body_string().await?, "hello world");
+        }
+
+        // Unknown or stacked encodings are left as they are.
+        let compressed = compress(Encoding::Gzip, b"hello world").await?;
+        let res = response("gzip, identity", compressed.clone());
+        let mut res = decode(Method::Get, res, &Config::new());
+        assert_eq!(res.body_bytes().await?, compressed);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn limits_decompressed_size() -> http_types::Result<()> {
+        let zeros = vec![0; 4 * 1024 * 1024];
+        let compressed = compress(Encoding::Gzip, &zeros).await?;
+
+        let config = Config::new().set_max_decompressed_size(Some(1024 * 1024));
+        let mut res = decode(Method::Get, response("gzip", compressed.clone()), &config);
+        let err = res.body_bytes().await.unwrap_err();
+        let err = err
+            .downcast_ref::<io::Error>()
+            .and_then(|e| e.get_ref())
+            .and_then(|e| e.downcast_ref::<ClientError>());
+        assert_eq!(err, Some(&ClientError::DecompressionLimitExceeded));
+
+        // Zeros compress about a thousandfold.
+        let config = Config::new().set_max_decompression_ratio(Some(100));
+        let mut res = decode(Method::Get, response("gzip", compressed.clone()), &config);
+        assert!(res.body_bytes().await.is_err());
+        let config = Config::new().set_max_decompression_ratio(Some(10_000));
+        let mut res = decode(Method::Get, response("gzip", compressed), &config);
+        assert_eq!(res.body_bytes().await?.len(), zeros.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+//! DNS-over-HTTPS resolver (RFC 8484), sending its queries with an `H1Client` of its own.
+
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use async_std::io;
+use http_types::{Method, Url};
+
+use super::{H1Client, Resolver};
+use crate::{async_trait, Config, HttpClient, Request};
+
+const DNS_MESSAGE: &str = "application/dns-message";
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// Resolves host names by querying a DNS-over-HTTPS server, such as
+/// `https://cloudflare-dns.com/dns-query`, for both IPv6 and IPv4 addresses.
+///
+/// The server's own host name is never resolved: connections to it are made to the bootstrap
+/// addresses it's constructed with, while its certificate is still verified against the name.
+#[derive(Debug)]
+pub struct DohResolver {
+    url: Url,
+    client: H1Client,
+}
+
+impl DohResolver {
+    /// Query the DoH server at `url`, connecting to it at one of the `bootstrap` addresses.
+    pub fn new(url: Url, bootstrap: Vec<IpAddr>) -> Self {
+        Self::with_config(url, bootstrap, Config::new())
+    }
+
+    /// Query the DoH server at `url` with a client using the given configuration, e.g. to trust
+    /// a private CA. `config.resolver` is replaced with the `bootstrap` addresses.
+    pub fn with_config(url: Url, bootstrap: Vec<IpAddr>, config: Config) -> Self {
+        let client = H1Client::with_config(config.set_resolver(Bootstrap(bootstrap)));
+        Self { url, client }
+    }
+
+    /// Query the addresses of `host` of the given record type, along with their shortest TTL.
+    async fn query(&self, host: &str, qtype: u16) -> io::Result<(Vec<IpAddr>, Option<Duration>)> {
+        let mut req = Request::new(Method::Post, self.url.clone());
+        req.insert_header("Accept", DNS_MESSAGE);
+        req.insert_header("Content-Type", DNS_MESSAGE);
+        req.set_body(encode_query(host, qtype)?);
+
+        let mut res = self
+            .client
+            .send(req)
+            .await
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        if !res.status().is_success() {
+            return Err(io::Error::other(format!(
+                "DoH server responded with {}",
+                res.status()
+            )));
+        }
+        let body = res
+            .body_bytes()
+            .await
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        decode_answers(&body, qtype)
+    }
+}
+
+#[async_trait]
+impl Resolver for DohResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(self.resolve_with_ttl(host, port).await?.0)
+    }
+
+    async fn resolve_with_ttl(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> io::Result<(Vec<SocketAddr>, Option<Duration>)> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok((vec![SocketAddr::new(ip, port)], None));
+        }
+
+        let mut addrs = Vec::new();
+        let mut ttl: Option<Duration> = None;
+        for qtype in [TYPE_AAAA, TYPE_A] {
+            let (ips, answer_ttl) = self.query(host, qtype).await?;
+            addrs.extend(ips.into_iter().map(|ip| SocketAddr::new(ip, port)));
+            ttl = match (ttl, answer_ttl) {
+                (Some(ttl), Some(answer_ttl)) => Some(ttl.min(answer_ttl)),
+                (ttl, answer_ttl) => ttl.or(answer_ttl),
+            };
+        }
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no addresses found for {}", host),
+            ));
+        }
+        Ok((addrs, ttl))
+    }
+}
+
+/// Resolves every host to the DoH server's bootstrap addresses.
+#[derive(Debug)]
+struct Bootstrap(Vec<IpAddr>);
+
+#[async_trait]
+impl Resolver for Bootstrap {
+    async fn resolve(&self, _host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(self.0.iter().map(|&ip| SocketAddr::new(ip, port)).collect())
+    }
+}
+
+/// Encode a DNS query message for the `qtype` records of `host`.
+fn encode_query(host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    // An ID of 0 (as recommended for DoH, to be cache friendly), recursion desired, and a single
+    // question.
+    let mut query = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid host name '{}'", host),
+            ));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Decode the addresses of the `qtype` records in a DNS response message, along with their
+/// shortest TTL. Other records, like the `CNAME`s leading to the addresses, are skipped.
+fn decode_answers(msg: &[u8], qtype: u16) -> io::Result<(Vec<IpAddr>, Option<Duration>)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid DNS response");
+    let u16_at = |pos: usize| {
+        msg.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(invalid)
+    };
+
+    match u16_at(2)? & 0x000f {
+        0 => {}
+        RCODE_NXDOMAIN => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "host name doesn't exist",
+            ))
+        }
+        rcode => {
+            return Err(io::Error::other(format!(
+                "DNS server responded with error code {}",
+                rcode
+            )))
+        }
+    }
+
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        // The name is followed by the question's type and class.
+        pos = skip_name(msg, pos).ok_or_else(invalid)? + 4;
+    }
+
+    let mut ips = Vec::new();
+    let mut ttl: Option<Duration> = None;
+    for _ in 0..answers {
+        pos = skip_name(msg, pos).ok_or_else(invalid)?;
+        let rtype = u16_at(pos)?;
+        let record_ttl = msg
+            .get(pos + 4..pos + 8)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(invalid)?;
+        let len = u16_at(pos + 8)? as usize;
+        let data = msg.get(pos + 10..pos + 10 + len).ok_or_else(invalid)?;
+        pos += 10 + len;
+
+        if rtype != qtype {
+            continue;
+        }
+        let ip = match rtype {
+            TYPE_A => IpAddr::from(Ipv4Addr::from(
+                <[u8; 4]>::try_from(data).map_err(|_| invalid())?,
+            )),
+            _ => IpAddr::from(Ipv6Addr::from(
+                <[u8; 16]>::try_from(data).map_err(|_| invalid())?,
+            )),
+        };
+        ips.push(ip);
+        let record_ttl = Duration::from_secs(record_ttl.into());
+        ttl = Some(ttl.map_or(record_ttl, |ttl| ttl.min(record_ttl)));
+    }
+    Ok((ips, ttl))
+}
+
+/// Skip over the (possibly compressed) name at `pos`, returning the position following it.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        match *msg.get(pos)? {
+            0 => return Some(pos + 1),
+            // A pointer to the rest of the name, elsewhere in the message, ends it.
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h1::Certificate;
+    use async_std::prelude::*;
+    use async_std::task;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Answer a query with `ips`, first pointing its name to another with a `CNAME` record.
+    fn answer(query: &[u8], ips: &[IpAddr]) -> Vec<u8> {
+        let mut res = query[..12].to_vec();
+        res[2] |= 0x80;
+        res[7] = 1 + ips.len() as u8;
+        res.extend_from_slice(&query[12..]);
+
+        // `example.test. CNAME cdn.test.`, its name pointing to the question's.
+        res.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 10]);
+        let cname = res.len();
+        res.extend_from_slice(b"\x03cdn\x04test\x00");
+        for ip in ips {
+            let (rtype, data) = match ip {
+                IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+                IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+            };
+            res.extend_from_slice(&[0xc0, cname as u8]);
+            res.extend_from_slice(&rtype.to_be_bytes());
+            res.extend_from_slice(&[0, 1, 0, 0, 0, 30, 0, data.len() as u8]);
+            res.extend_from_slice(&data);
+        }
+        res
+    }
+
+    #[test]
+    fn decodes_responses() -> io::Result<()> {
+        let query = encode_query("example.test", TYPE_A)?;
+        let ip = IpAddr::from([10, 0, 0, 5]);
+        let (ips, ttl) = decode_answers(&answer(&query, &[ip]), TYPE_A)?;
+        assert_eq!(ips, [ip]);
+        assert_eq!(ttl, Some(Duration::from_secs(30)));
+
+        let mut not_found = answer(&query, &[]);
+        not_found[3] |= RCODE_NXDOMAIN as u8;
+        let err = decode_answers(&not_found, TYPE_A).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        assert!(decode_answers(&answer(&query, &[ip])[..40], TYPE_A).is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn resolves_through_doh_server() -> http_types::Result<()> {
+        let port = portpicker::pick_unused_port().unwrap();
+        let mut app = tide::new();
+        app.at("/dns-query")
+            .post(|mut r: tide::Request<()>| async move {
+                assert_eq!(r.content_type().unwrap().essence(), DNS_MESSAGE);
+                let query = r.body_bytes().await?;
+                let ips = match u16::from_be_bytes([query[query.len() - 4], query[query.len() - 3]])
+                {
+                    TYPE_A => vec![IpAddr::from([10, 0, 0, 5])],
+                    _ => vec![],
+                };
+                let mut res = tide::Response::new(200);
+                res.set_content_type(DNS_MESSAGE);
+                res.set_body(answer(&query, &ips));
+                Ok(res)
+            });
+
+        let server = task::spawn(async move {
+            let listener = tide_rustls::TlsListener::build()
+                .addrs(("localhost", port))
+                .cert(fixture("server.pem"))
+                .key(fixture("server.key"));
+            app.listen(listener).await?;
+            http_types::Result::Ok(())
+        });
+
+        let client = task::spawn(async move {
+            task::sleep(Duration::from_millis(100)).await;
+            let ca = Certificate::from_pem(&std::fs::read(fixture("ca.pem"))?)?;
+            let url = Url::parse(&format!("https://localhost:{}/dns-query", port))?;
+            let bootstrap = vec!["127.0.0.1".parse()?, "::1".parse()?];
+            let resolver =
+                DohResolver::with_config(url, bootstrap, Config::new().add_root_certificate(ca));
+
+            let (addrs, ttl) = resolver.resolve_with_ttl("example.test", 443).await?;
+            assert_eq!(addrs, [SocketAddr::from(([10, 0, 0, 5], 443))]);
+            assert_eq!(ttl, Some(Duration::from_secs(30)));
+            Ok(())
+        });
+
+        server.race(client).await?;
+
+        Ok(())
+    }
+}
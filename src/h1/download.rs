@@ -0,0 +1,224 @@
+//! Downloading a resource to a writer or file, resuming after interruptions.
+
+use std::path::Path;
+
+use async_std::fs::File;
+use async_std::io::{self, prelude::SeekExt, ReadExt, SeekFrom, Write, WriteExt};
+use http_types::{Method, StatusCode, Url};
+
+use super::buffers::Buffer;
+use crate::{async_trait, Error, HttpClient, Request, Response};
+
+/// How many times a download is resumed after being interrupted, before giving up.
+const MAX_RESUMES: usize = 5;
+
+/// Download `url` with `client`, writing the body to `writer`, and return its length.
+///
+/// If the download is interrupted, because the connection failed or the body ended early, it's
+/// resumed with a `Range` request for the rest, if the server advertised `Accept-Ranges: bytes`
+/// and an `ETag` or `Last-Modified` date. The resumed request is sent with `If-Range`, so if the
+/// resource changed meanwhile the server sends all of it again, which fails the download, as
+/// what was already written to `writer` can't be taken back. Use `download_file` to start over
+/// instead.
+pub async fn download<C, W>(client: &C, url: Url, writer: &mut W) -> Result<u64, Error>
+where
+    C: HttpClient + ?Sized,
+    W: Write + Unpin + Send,
+{
+    fetch(client, url, &mut Append(writer)).await
+}
+
+/// Download `url` with `client` to the file at `path`, creating or truncating it, and return the
+/// body's length.
+///
+/// Interrupted downloads are resumed like with `download`, and if the resource changed meanwhile
+/// the file is truncated to start over.
+pub async fn download_file<C>(client: &C, url: Url, path: impl AsRef<Path>) -> Result<u64, Error>
+where
+    C: HttpClient + ?Sized,
+{
+    let mut file = File::create(path.as_ref()).await?;
+    let len = fetch(client, url, &mut file).await?;
+    file.sync_all().await?;
+    Ok(len)
+}
+
+/// Where a download is written to.
+#[async_trait]
+trait Output: Write + Unpin + Send {
+    /// Discard everything written so far, to start over.
+    async fn restart(&mut self) -> io::Result<()>;
+}
+
+#[async_trait]
+impl Output for File {
+    async fn restart(&mut self) -> io::Result<()> {
+        self.set_len(0).await?;
+        self.seek(SeekFrom::Start(0)).await?;
+        Ok(())
+    }
+}
+
+/// A writer which can only be appended to.
+struct Append<'a, W>(&'a mut W);
+
+impl<W: Write + Unpin> Write for Append<'_, W> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        std::pin::Pin::new(&mut *self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut *self.0).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut *self.0).poll_close(cx)
+    }
+}
+
+#[async_trait]
+impl<W: Write + Unpin + Send> Output for Append<'_, W> {
+    async fn restart(&mut self) -> io::Result<()> {
+        Err(io::Error::other(
+            "the resource changed while it was downloaded",
+        ))
+    }
+}
+
+/// What's known about the resource being downloaded.
+#[derive(Debug, Default)]
+struct Progress {
+    written: u64,
+    len: Option<u64>,
+    // The `ETag` or `Last-Modified` date to send with `If-Range`, if ranges may be requested.
+    validator: Option<String>,
+}
+
+async fn fetch<C, O>(client: &C, url: Url, output: &mut O) -> Result<u64, Error>
+where
+    C: HttpClient + ?Sized,
+    O: Output,
+{
+    let mut progress = Progress::default();
+    let mut resumes = 0;
+    loop {
+        let mut req = Request::new(Method::Get, url.clone());
+        // Byte ranges of a compressed body wouldn't line up with what was written.
+        req.insert_header("Accept-Encoding", "identity");
+        if let (true, Some(validator)) = (progress.written > 0, &progress.validator) {
+            req.insert_header("Range", format!("bytes={}-", progress.written));
+            req.insert_header("If-Range", validator.as_str());
+        }
+
+        let interrupted = match client.send(req).await {
+            Ok(res) => receive(res, output, &mut progress).await?.err(),
+            Err(e) => Some(e),
+        };
+        let complete = progress.len.is_none_or(|len| progress.written == len);
+        match interrupted {
+            None if complete => {
+                output.flush().await?;
+                return Ok(progress.written);
+            }
+            None => log::debug!(
+                "download of {} ended after {} of {:?} bytes",
+                url,
+                progress.written,
+                progress.len
+            ),
+            Some(e) if progress.written == 0 || progress.validator.is_none() => return Err(e),
+            Some(e) => log::debug!("download of {} interrupted: {}", url, e),
+        }
+        if progress.validator.is_none() || resumes == MAX_RESUMES {
+            return Err(Error::from_str(
+                StatusCode::BadGateway,
+                format!(
+                    "download of {} interrupted after {} bytes",
+                    url, progress.written
+                ),
+            ));
+        }
+        resumes += 1;
+    }
+}
+
+/// Write the body of `res` to `output`, recording the progress made. Failing to read the body
+/// interrupts the download, with the inner error, while other failures end it.
+async fn receive<O: Output>(
+    mut res: Response,
+    output: &mut O,
+    progress: &mut Progress,
+) -> Result<Result<(), Error>, Error> {
+    match res.status() {
+        StatusCode::PartialContent if progress.written > 0 => {
+            if content_range_start(&res) != Some(progress.written) {
+                return Err(Error::from_str(
+                    StatusCode::BadGateway,
+                    "the server resumed the download at the wrong offset",
+                ));
+            }
+        }
+        status if status.is_success() => {
+            // A full response, to the first request or because the resource changed.
+            if progress.written > 0 {
+                output.restart().await?;
+                progress.written = 0;
+            }
+            let ranges = res
+                .header("Accept-Ranges")
+                .is_some_and(|value| value.as_str().eq_ignore_ascii_case("bytes"));
+            progress.validator = validator(&res).filter(|_| ranges);
+            progress.len = res.len().map(|len| len as u64);
+        }
+        status => {
+            return Err(Error::from_str(
+                status,
+                format!("download failed with status {}", status),
+            ))
+        }
+    }
+
+    let mut body = res.take_body();
+    let mut buf = Buffer::zeroed(16 * 1024);
+    loop {
+        let n = match body.read(&mut buf).await {
+            Ok(0) => return Ok(Ok(())),
+            Ok(n) => n,
+            Err(e) => return Ok(Err(e.into())),
+        };
+        output.write_all(&buf[..n]).await?;
+        progress.written += n as u64;
+    }
+}
+
+/// The validator to resume a download of `res` with: its `ETag` if it's strong, or else its
+/// `Last-Modified` date.
+fn validator(res: &Response) -> Option<String> {
+    if let Some(etag) = res.header("ETag") {
+        let etag = etag.last().as_str();
+        // Weak `ETag`s can't be used with `If-Range`.
+        if !etag.starts_with("W/") {
+            return Some(etag.to_string());
+        }
+    }
+    res.header("Last-Modified")
+        .map(|modified| modified.last().as_str().to_string())
+}
+
+/// The offset of the first byte of a `206 Partial Content` response.
+fn content_range_start(res: &Response) -> Option<u64> {
+    let range = res.header("Content-Range")?.last().as_str();
+    let range = range.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
@@ -0,0 +1,19 @@
+//! Surfacing `103 Early Hints` responses received ahead of the final response.
+
+use std::fmt::Debug;
+
+use http_types::Url;
+
+use crate::Response;
+
+/// Receives the `103 Early Hints` responses a server sends ahead of the final response, while it
+/// prepares it, e.g. to preconnect to the origins of the resources they link to with
+/// `H1Client::preconnect`.
+///
+/// Set one with `Config::set_early_hints_handler`. It's called from the task sending the
+/// request, which waits for it to return, so it shouldn't block.
+pub trait EarlyHintsHandler: Debug + Send + Sync + 'static {
+    /// Handle the `hints` sent in response to a request to `url`. They have headers, typically
+    /// `Link`, but no body.
+    fn early_hints(&self, url: &Url, hints: &Response);
+}
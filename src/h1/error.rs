@@ -0,0 +1,546 @@
+//! Errors raised by the h1 client itself, and the kinds of failures requests run into.
+
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+
+use async_std::io;
+use http_types::{StatusCode, Url};
+
+use super::Phase;
+use crate::middleware::SharedError;
+use crate::{Error, Request};
+
+/// What failed when a request did, as told by `ErrorKind::of`.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Resolving the host of a new connection failed.
+    Dns,
+    /// Establishing the TCP connection failed.
+    Connect,
+    /// The TLS handshake failed.
+    Tls,
+    /// Writing the request head or body failed.
+    RequestWrite,
+    /// Reading the response head failed, or it was malformed.
+    ResponseParse,
+    /// Reading the response body failed.
+    Body,
+    /// The request, or waiting for data from the server, took longer than its timeout. Failures
+    /// of the other kinds may have been timeouts too, as `ErrorExt::is_timeout` tells.
+    Timeout,
+    /// Checking a connection out of the pool failed, other than because establishing it did.
+    Pool,
+    /// The client refused to go on, as configured: too many redirects, a response too large, an
+    /// open circuit breaker or a full rate limit queue.
+    Policy,
+    /// Something else failed, such as a body being read from a request.
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind of `error`, returned for a request, or while reading its response body.
+    pub fn of(error: &Error) -> Self {
+        let error = original(error);
+        if let Some(error) = request_error(error) {
+            return error.kind;
+        }
+        if let Some(error) = client_error(error) {
+            return error.kind();
+        }
+        match error.downcast_ref::<io::Error>() {
+            Some(error) if error.kind() == io::ErrorKind::TimedOut => ErrorKind::Timeout,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Dns => "resolving the host failed",
+            ErrorKind::Connect => "connecting failed",
+            ErrorKind::Tls => "TLS handshake failed",
+            ErrorKind::RequestWrite => "writing the request failed",
+            ErrorKind::ResponseParse => "reading the response head failed",
+            ErrorKind::Body => "reading the response body failed",
+            ErrorKind::Timeout => "timed out",
+            ErrorKind::Pool => "checking out a connection failed",
+            ErrorKind::Policy => "refused by the client's configuration",
+            ErrorKind::Other => "request failed",
+        })
+    }
+}
+
+/// A failure of the connection to the server, or of the server to speak HTTP, with what failed,
+/// the URL the request was sent to and the address of the server, if it was connected to.
+///
+/// It's the source of the `Error` returned for the request, or of the `io::Error` returned
+/// while reading the response body, like `ClientError`, and its own source is the error it was
+/// raised for.
+#[derive(Debug)]
+pub struct RequestError {
+    kind: ErrorKind,
+    url: Url,
+    peer_addr: Option<SocketAddr>,
+    unsent: bool,
+    source: Box<dyn StdError + Send + Sync>,
+}
+
+impl RequestError {
+    /// What failed.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The URL the request was sent to.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The address of the server, unless the request failed before connecting to it.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Whether the request failed before any of it was written to the connection, so the server
+    /// can't have seen it.
+    pub fn is_unsent(&self) -> bool {
+        self.unsent
+    }
+
+    /// The `io::Error` this was raised for, if it was.
+    pub(crate) fn io_error(&self) -> Option<&io::Error> {
+        self.source.downcast_ref()
+    }
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} for {}", self.kind, self.url)?;
+        if let Some(addr) = self.peer_addr {
+            write!(f, " ({})", addr)?;
+        }
+        write!(f, ": {}", self.source)
+    }
+}
+
+impl StdError for RequestError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// Predicates on the errors returned for requests, or while reading their response bodies, to
+/// tell what went wrong and whether it's worth trying again.
+///
+/// ```no_run
+/// # async fn fetch() -> http_types::Result<()> {
+/// use http_client::h1::{ErrorExt, H1Client};
+/// use http_client::{HttpClient, Request};
+///
+/// let client = H1Client::new();
+/// match client.send(Request::get("https://example.com/")).await {
+///     Err(e) if e.is_connect() => println!("the server is unreachable: {}", e),
+///     result => println!("{:?}", result?.status()),
+/// }
+/// # Ok(()) }
+/// ```
+pub trait ErrorExt {
+    /// What failed, as `ErrorKind::of` tells.
+    fn error_kind(&self) -> ErrorKind;
+
+    /// Whether no connection to the server could be established: resolving its host, connecting
+    /// or the TLS handshake failed, or timed out.
+    fn is_connect(&self) -> bool;
+
+    /// Whether resolving the host failed.
+    fn is_dns(&self) -> bool;
+
+    /// Whether the TLS handshake failed, or timed out.
+    fn is_tls(&self) -> bool;
+
+    /// Whether something took longer than allowed: the request's timeout elapsed, no data arrived
+    /// within the read timeout, or connecting timed out.
+    fn is_timeout(&self) -> bool;
+
+    /// Whether reading the response body failed, after its head had been received.
+    fn is_body(&self) -> bool;
+
+    /// Whether the request failed before any of it was written to a connection, so the server
+    /// can't have seen it, and it's safe to send again whatever its method.
+    fn is_unsent(&self) -> bool;
+
+    /// Whether the failure is likely transient, so sending the request again may succeed:
+    /// connecting was refused or timed out, or the connection was lost. Requests which may have
+    /// reached the server are only safe to retry if they're idempotent, unless `is_unsent`.
+    fn is_retryable(&self) -> bool;
+}
+
+impl ErrorExt for Error {
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::of(self)
+    }
+
+    fn is_connect(&self) -> bool {
+        match client_error(self) {
+            Some(ClientError::Timeout { phase }) => {
+                matches!(phase, Phase::Resolve | Phase::Connect | Phase::Tls)
+            }
+            Some(ClientError::TlsHandshakeTimeout) => true,
+            Some(_) => false,
+            None => matches!(
+                self.error_kind(),
+                ErrorKind::Dns | ErrorKind::Connect | ErrorKind::Tls
+            ),
+        }
+    }
+
+    fn is_dns(&self) -> bool {
+        match client_error(self) {
+            Some(error) => {
+                *error
+                    == ClientError::Timeout {
+                        phase: Phase::Resolve,
+                    }
+            }
+            None => self.error_kind() == ErrorKind::Dns,
+        }
+    }
+
+    fn is_tls(&self) -> bool {
+        match client_error(self) {
+            Some(ClientError::Timeout { phase }) => *phase == Phase::Tls,
+            Some(error) => *error == ClientError::TlsHandshakeTimeout,
+            None => self.error_kind() == ErrorKind::Tls,
+        }
+    }
+
+    fn is_timeout(&self) -> bool {
+        self.error_kind() == ErrorKind::Timeout
+            || io_error(self).is_some_and(|error| error.kind() == io::ErrorKind::TimedOut)
+    }
+
+    fn is_body(&self) -> bool {
+        match client_error(self) {
+            Some(ClientError::Timeout { phase }) => *phase == Phase::Body,
+            Some(_) => false,
+            None => self.error_kind() == ErrorKind::Body,
+        }
+    }
+
+    fn is_unsent(&self) -> bool {
+        if let Some(error) = client_error(self) {
+            return match error {
+                ClientError::Timeout { phase } => !matches!(phase, Phase::Request | Phase::Body),
+                ClientError::TlsHandshakeTimeout
+                | ClientError::CircuitOpen
+                | ClientError::RateLimitQueueFull
+                | ClientError::ShutDown => true,
+                _ => false,
+            };
+        }
+        request_error(self).is_some_and(RequestError::is_unsent)
+    }
+
+    fn is_retryable(&self) -> bool {
+        if let Some(error) = client_error(self) {
+            return match error {
+                ClientError::Timeout { phase } => {
+                    matches!(phase, Phase::Resolve | Phase::Connect | Phase::Tls)
+                }
+                ClientError::TlsHandshakeTimeout => true,
+                _ => false,
+            };
+        }
+        io_error(self).is_some_and(|error| {
+            matches!(
+                error.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+            )
+        })
+    }
+}
+
+/// The `ClientError` in `error`, whether as it is or wrapped in an `io::Error`.
+fn client_error(error: &Error) -> Option<&ClientError> {
+    let error = original(error);
+    error.downcast_ref().or_else(|| {
+        error
+            .downcast_ref::<io::Error>()
+            .and_then(|error| error.get_ref())
+            .and_then(|inner| inner.downcast_ref())
+    })
+}
+
+/// The `RequestError` in `error`, whether as it is or wrapped in an `io::Error`.
+fn request_error(error: &Error) -> Option<&RequestError> {
+    let error = original(error);
+    error.downcast_ref().or_else(|| {
+        error
+            .downcast_ref::<io::Error>()
+            .and_then(|error| error.get_ref())
+            .and_then(|inner| inner.downcast_ref())
+    })
+}
+
+/// The `io::Error` in `error`, whether as it is or as the source of a `RequestError`, which may
+/// itself be wrapped in an `io::Error`.
+pub(crate) fn io_error(error: &Error) -> Option<&io::Error> {
+    match request_error(error) {
+        Some(error) => error.io_error(),
+        None => original(error).downcast_ref(),
+    }
+}
+
+/// `error`, or the error it shares if the `Coalesce` middleware shared it between requests.
+fn original(error: &Error) -> &Error {
+    match error.downcast_ref::<SharedError>() {
+        Some(shared) => &shared.0,
+        None => error,
+    }
+}
+
+/// Where a request is sent, to raise `RequestError`s for.
+#[derive(Clone, Debug)]
+pub(crate) struct Target {
+    url: Url,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl Target {
+    /// The target of `req`, connected to its peer address if it has one.
+    pub(crate) fn new(req: &Request) -> Self {
+        Self {
+            url: req.url().clone(),
+            peer_addr: req.peer_addr().and_then(|addr| addr.parse().ok()),
+        }
+    }
+
+    /// Raise `error` as a `kind` of failure, unless it's a `ClientError`, which tells what failed
+    /// already.
+    pub(crate) fn fail(&self, kind: ErrorKind, error: impl Into<Error>) -> Error {
+        let unsent = matches!(
+            kind,
+            ErrorKind::Dns | ErrorKind::Connect | ErrorKind::Tls | ErrorKind::Pool
+        );
+        self.raise(kind, error.into(), unsent)
+    }
+
+    /// Raise `error`, from writing the start of the request, as a `RequestWrite` failure before
+    /// any of the request was sent.
+    pub(crate) fn fail_unsent(&self, error: impl Into<Error>) -> Error {
+        self.raise(ErrorKind::RequestWrite, error.into(), true)
+    }
+
+    fn raise(&self, kind: ErrorKind, error: Error, unsent: bool) -> Error {
+        if is_classified(&error) {
+            return error;
+        }
+        let status = error.status();
+        // An `io::Error` is kept as it is, for the retry logic to look at.
+        let source: Box<dyn StdError + Send + Sync> = match error.downcast::<io::Error>() {
+            Ok(error) => Box::new(error),
+            Err(error) => error.into_inner().into(),
+        };
+        Error::new(status, self.error(kind, source, unsent))
+    }
+
+    /// Raise `error`, from reading a response body, as a `Body` failure, unless it's a
+    /// `ClientError`.
+    pub(crate) fn fail_body(&self, error: io::Error) -> io::Error {
+        let inner = error.get_ref();
+        if inner.is_some_and(|inner| inner.is::<ClientError>() || inner.is::<RequestError>()) {
+            return error;
+        }
+        let kind = error.kind();
+        io::Error::new(kind, self.error(ErrorKind::Body, Box::new(error), false))
+    }
+
+    fn error(
+        &self,
+        kind: ErrorKind,
+        source: Box<dyn StdError + Send + Sync>,
+        unsent: bool,
+    ) -> RequestError {
+        RequestError {
+            kind,
+            url: self.url.clone(),
+            peer_addr: self.peer_addr,
+            unsent,
+            source,
+        }
+    }
+}
+
+/// Whether `error` tells what failed already, as a `ClientError` or `RequestError`, either as it
+/// is or wrapped in an `io::Error`.
+fn is_classified(error: &Error) -> bool {
+    if error.downcast_ref::<ClientError>().is_some()
+        || error.downcast_ref::<RequestError>().is_some()
+    {
+        return true;
+    }
+    error
+        .downcast_ref::<io::Error>()
+        .and_then(|error| error.get_ref())
+        .is_some_and(|inner| inner.is::<ClientError>() || inner.is::<RequestError>())
+}
+
+/// An error raised by the h1 client itself, rather than by the connection or the server.
+///
+/// It's the source of the `Error` returned for the request, or of the `io::Error` returned
+/// while reading the response body, so get at it with `downcast_ref::<ClientError>()`.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientError {
+    /// The request didn't complete within its timeout.
+    Timeout {
+        /// What the request was doing when its timeout elapsed.
+        phase: Phase,
+    },
+    /// No data arrived from the server within the read timeout.
+    ReadTimeout,
+    /// The server accepted the connection, but didn't complete the TLS handshake within the
+    /// TLS handshake timeout.
+    TlsHandshakeTimeout,
+    /// The request was redirected more times in a row than the redirect policy allows.
+    TooManyRedirects,
+    /// The response head or body was longer than `Config::max_response_head_size` or
+    /// `Config::max_response_body_size`, or had more headers than
+    /// `Config::max_response_headers`.
+    ResponseTooLarge,
+    /// The response body decompressed to more than the maximum decompressed size or ratio.
+    DecompressionLimitExceeded,
+    /// The circuit breaker is open for the host, as too many requests to it failed recently.
+    CircuitOpen,
+    /// The request would have had to wait for its turn under the rate limit, but the most
+    /// requests which may wait already are.
+    RateLimitQueueFull,
+    /// The client has been shut down, with `H1Client::shutdown`.
+    ShutDown,
+}
+
+impl ClientError {
+    /// What failed, as far as the kinds of errors go.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ClientError::Timeout { .. }
+            | ClientError::ReadTimeout
+            | ClientError::TlsHandshakeTimeout => ErrorKind::Timeout,
+            ClientError::TooManyRedirects
+            | ClientError::ResponseTooLarge
+            | ClientError::DecompressionLimitExceeded
+            | ClientError::CircuitOpen
+            | ClientError::RateLimitQueueFull => ErrorKind::Policy,
+            ClientError::ShutDown => ErrorKind::Pool,
+        }
+    }
+
+    /// Wrap this in an `Error`, with a status code matching it.
+    pub(crate) fn into_error(self) -> Error {
+        let status = match self {
+            ClientError::Timeout { .. }
+            | ClientError::ReadTimeout
+            | ClientError::TlsHandshakeTimeout => StatusCode::RequestTimeout,
+            ClientError::TooManyRedirects => StatusCode::LoopDetected,
+            ClientError::ResponseTooLarge | ClientError::DecompressionLimitExceeded => {
+                StatusCode::PayloadTooLarge
+            }
+            ClientError::CircuitOpen => StatusCode::ServiceUnavailable,
+            ClientError::RateLimitQueueFull => StatusCode::TooManyRequests,
+            ClientError::ShutDown => StatusCode::ServiceUnavailable,
+        };
+        Error::new(status, self)
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Timeout { phase } => write!(f, "request timed out while {}", phase),
+            ClientError::ReadTimeout => f.write_str("timed out waiting for data from the server"),
+            ClientError::TlsHandshakeTimeout => f.write_str("TLS handshake timed out"),
+            ClientError::TooManyRedirects => f.write_str("too many redirects"),
+            ClientError::ResponseTooLarge => {
+                f.write_str("response exceeded the maximum response size")
+            }
+            ClientError::DecompressionLimitExceeded => {
+                f.write_str("response body exceeded the decompression limits")
+            }
+            ClientError::CircuitOpen => {
+                f.write_str("circuit breaker is open, as requests to the host keep failing")
+            }
+            ClientError::RateLimitQueueFull => {
+                f.write_str("too many requests are waiting for their turn under the rate limit")
+            }
+            ClientError::ShutDown => f.write_str("client has been shut down"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors() {
+        let mut req = Request::get("http://example.test/");
+        req.set_peer_addr(Some("127.0.0.1:80"));
+        let target = Target::new(&req);
+        let io_error = |kind| io::Error::new(kind, "failed");
+
+        let refused = target.fail(
+            ErrorKind::Connect,
+            io_error(io::ErrorKind::ConnectionRefused),
+        );
+        assert_eq!(refused.error_kind(), ErrorKind::Connect);
+        assert!(refused.is_connect() && refused.is_unsent() && refused.is_retryable());
+        assert!(!refused.is_timeout());
+        let error = refused.downcast_ref::<RequestError>().unwrap();
+        assert_eq!(error.peer_addr(), Some(([127, 0, 0, 1], 80).into()));
+
+        let timed_out = target.fail(ErrorKind::Connect, io_error(io::ErrorKind::TimedOut));
+        assert!(timed_out.is_connect() && timed_out.is_timeout());
+
+        // Reset before any of the request was written, or while waiting for the response.
+        let reset = || io_error(io::ErrorKind::ConnectionReset);
+        let unsent = target.fail_unsent(reset());
+        assert!(unsent.is_unsent() && unsent.is_retryable() && !unsent.is_connect());
+        let lost = target.fail(ErrorKind::ResponseParse, reset());
+        assert!(!lost.is_unsent() && lost.is_retryable());
+
+        let body = Error::from(target.fail_body(reset()));
+        assert_eq!(body.error_kind(), ErrorKind::Body);
+        assert!(body.is_body() && !body.is_unsent());
+
+        let timeout = ClientError::Timeout {
+            phase: Phase::Resolve,
+        };
+        let timeout = target.fail(ErrorKind::Connect, timeout.into_error());
+        assert_eq!(timeout.error_kind(), ErrorKind::Timeout);
+        assert!(timeout.is_dns() && timeout.is_timeout() && timeout.is_unsent());
+
+        let waited = ClientError::Timeout {
+            phase: Phase::Checkout,
+        };
+        let waited = target.fail(ErrorKind::Pool, waited.into_error());
+        assert_eq!(waited.error_kind(), ErrorKind::Timeout);
+        assert!(waited.is_timeout() && waited.is_unsent() && !waited.is_connect());
+
+        let shut_down = ClientError::ShutDown.into_error();
+        assert_eq!(shut_down.error_kind(), ErrorKind::Pool);
+        assert!(shut_down.is_unsent() && !shut_down.is_retryable());
+
+        let too_large = ClientError::ResponseTooLarge.into_error();
+        assert_eq!(too_large.error_kind(), ErrorKind::Policy);
+        assert!(!too_large.is_retryable() && !too_large.is_unsent());
+    }
+}
@@ -0,0 +1,88 @@
+//! Hooks for the lifecycle of pooled connections, to diagnose pool churn.
+
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_std::io;
+
+use super::pool::Origin;
+use super::NegotiatedProtocol;
+
+/// Is told what becomes of the connections in the client's pools: when they're established,
+/// handed out again, returned idle, evicted, and closed. Each method does nothing unless
+/// implemented.
+///
+/// Set one with `Config::set_connection_observer`. It's called from the task getting or
+/// returning the connection, or from the reaper, so it shouldn't block. A server closing
+/// keep-alive connections sooner than the client expects shows up as connections evicted for
+/// having been closed.
+pub trait ConnectionObserver: Debug + Send + Sync + 'static {
+    /// `conn` was established.
+    fn created(&self, conn: &ConnectionInfo) {
+        let _ = conn;
+    }
+
+    /// `conn` was checked out of the pool again for a request, having been found usable.
+    fn reused(&self, conn: &ConnectionInfo) {
+        let _ = conn;
+    }
+
+    /// `conn` was returned idle to the pool, its response read to the end.
+    fn recycled(&self, conn: &ConnectionInfo) {
+        let _ = conn;
+    }
+
+    /// `conn` was taken out of the pool idle, and closed, for `reason`: it had expired, the
+    /// server had closed it, or it made room for a connection to another host.
+    fn evicted(&self, conn: &ConnectionInfo, reason: &io::Error) {
+        let _ = (conn, reason);
+    }
+
+    /// `conn` was closed, with the last error reading or writing it, if any.
+    fn closed(&self, conn: &ConnectionInfo, error: Option<&io::Error>) {
+        let _ = (conn, error);
+    }
+}
+
+/// What's known of a connection when it's established.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    /// An ID telling the events of this connection apart from those of others, unique within
+    /// the process.
+    pub id: u64,
+    /// The scheme the connection was made for, `http` or `https`.
+    pub scheme: &'static str,
+    /// The host the connection was made to.
+    pub host: String,
+    /// The port the connection was made to.
+    pub port: u16,
+    /// The address of the server, or of the proxy leading to it, unless the connection isn't
+    /// over TCP.
+    pub peer_addr: Option<SocketAddr>,
+    /// The local address of the connection, unless it isn't over TCP.
+    pub local_addr: Option<SocketAddr>,
+    /// The protocol negotiated over ALPN in the TLS handshake, if any.
+    pub protocol: Option<NegotiatedProtocol>,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(
+        origin: &Origin,
+        peer_addr: Option<SocketAddr>,
+        local_addr: Option<SocketAddr>,
+        protocol: Option<NegotiatedProtocol>,
+    ) -> Self {
+        static IDS: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: IDS.fetch_add(1, Ordering::Relaxed),
+            scheme: origin.scheme,
+            host: origin.host.clone(),
+            port: origin.port,
+            peer_addr,
+            local_addr,
+            protocol,
+        }
+    }
+}
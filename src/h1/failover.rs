@@ -0,0 +1,67 @@
+//! Failing requests over to equivalent origins, as configured by `Config::failover_origins`.
+
+use http_types::Url;
+
+/// The URLs to send a request to `url` to in turn: `url` itself, then, if its origin is one of
+/// `origins`, the same path on each of the others, in order, starting after its own.
+pub(crate) fn urls(origins: &[Url], url: &Url) -> Vec<Url> {
+    let own = origins
+        .iter()
+        .position(|origin| origin.origin() == url.origin());
+    let own = match own {
+        Some(own) => own,
+        None => return vec![url.clone()],
+    };
+    let others = origins[own + 1..].iter().chain(&origins[..own]);
+    let mut urls = vec![url.clone()];
+    for origin in others {
+        let mut other = url.clone();
+        // Origins are absolute URLs with a host, so they always fit.
+        let _ = other.set_scheme(origin.scheme());
+        let _ = other.set_host(origin.host_str());
+        let _ = other.set_port(origin.port());
+        urls.push(other);
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_over_in_order() {
+        let origins = [
+            "https://api1.test",
+            "https://api2.test:8443",
+            "http://api3.test",
+        ]
+        .iter()
+        .map(|origin| Url::parse(origin).unwrap())
+        .collect::<Vec<_>>();
+        let urls = |url| {
+            urls(&origins, &Url::parse(url).unwrap())
+                .iter()
+                .map(Url::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            urls("https://api2.test:8443/v1/users?page=2"),
+            [
+                "https://api2.test:8443/v1/users?page=2",
+                "http://api3.test/v1/users?page=2",
+                "https://api1.test/v1/users?page=2",
+            ]
+        );
+        assert_eq!(
+            urls("https://api1.test:443/"),
+            [
+                "https://api1.test/",
+                "https://api2.test:8443/",
+                "http://api3.test/"
+            ]
+        );
+        assert_eq!(urls("https://other.test/"), ["https://other.test/"]);
+        assert_eq!(urls("http://api1.test/"), ["http://api1.test/"]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Injecting faults into requests, to test how code sending them copes with slow and failing
+//! servers without a proxy in between.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_std::io::{self, BufReader, Read};
+use async_std::task::{Context, Poll};
+use http_types::StatusCode;
+
+use super::error::Target;
+use super::runtime;
+use super::ErrorKind;
+use crate::middleware::{Middleware, Next};
+use crate::{async_trait, Body, Error, Request, Response};
+
+/// Which faults to inject into requests to a host, each with the probability, between 0 and 1,
+/// of a request running into it. None are injected by default.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    latency: Option<(f64, Duration)>,
+    reset: f64,
+    truncate: f64,
+    server_error: Option<(f64, StatusCode)>,
+}
+
+impl Faults {
+    /// Inject no faults, until told which.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay requests by `delay` before sending them, with `probability`.
+    pub fn latency(mut self, probability: f64, delay: Duration) -> Self {
+        self.latency = Some((probability, delay));
+        self
+    }
+
+    /// Fail requests as if the connection was reset before the response arrived, with
+    /// `probability`.
+    pub fn reset(mut self, probability: f64) -> Self {
+        self.reset = probability;
+        self
+    }
+
+    /// Cut response bodies off at a random point, failing to read them past it as if the
+    /// connection was lost, with `probability`.
+    pub fn truncate(mut self, probability: f64) -> Self {
+        self.truncate = probability;
+        self
+    }
+
+    /// Answer requests with an empty `status` response without sending them, with
+    /// `probability`.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a `5xx` status.
+    pub fn server_error(mut self, probability: f64, status: StatusCode) -> Self {
+        assert!(status.is_server_error(), "{} isn't a server error", status);
+        self.server_error = Some((probability, status));
+        self
+    }
+}
+
+/// Middleware injecting `Faults` into requests, as configured for their host.
+///
+/// ```no_run
+/// # async fn fetch() -> http_types::Result<()> {
+/// use std::time::Duration;
+/// use http_client::h1::{FaultInjector, Faults};
+/// use http_client::{Client, HttpClient, Request};
+/// use http_types::StatusCode;
+///
+/// let faults = FaultInjector::new(Faults::new().latency(0.1, Duration::from_secs(2)))
+///     .host("api.example.com", Faults::new().server_error(0.2, StatusCode::ServiceUnavailable));
+/// let client = Client::builder().with(faults).build();
+/// let res = client.send(Request::get("https://api.example.com/")).await?;
+/// # Ok(()) }
+/// ```
+///
+/// Faults are injected in front of the client sending requests, so requests failed or answered
+/// by them never take up a connection, and they come with the same errors, as told by
+/// `ErrorExt`, as the real thing.
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjector {
+    faults: Faults,
+    hosts: HashMap<String, Faults>,
+}
+
+impl FaultInjector {
+    /// Inject `faults` into requests to any host without faults of its own.
+    pub fn new(faults: Faults) -> Self {
+        Self {
+            faults,
+            hosts: HashMap::new(),
+        }
+    }
+
+    /// Inject `faults` into requests to `host` instead.
+    pub fn host(mut self, host: impl Into<String>, faults: Faults) -> Self {
+        self.hosts.insert(host.into(), faults);
+        self
+    }
+
+    fn faults(&self, req: &Request) -> &Faults {
+        req.url()
+            .host_str()
+            .and_then(|host| self.hosts.get(host))
+            .unwrap_or(&self.faults)
+    }
+}
+
+#[async_trait]
+impl Middleware for FaultInjector {
+    async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error> {
+        let faults = self.faults(&req);
+        if let Some((probability, delay)) = faults.latency {
+            if chance(probability) {
+                runtime::sleep(delay).await;
+            }
+        }
+        if let Some((probability, status)) = faults.server_error {
+            if chance(probability) {
+                return Ok(Response::new(status));
+            }
+        }
+        let target = Target::new(&req);
+        if chance(faults.reset) {
+            let reset = io::Error::new(io::ErrorKind::ConnectionReset, "connection reset");
+            return Err(target.fail(ErrorKind::ResponseParse, reset));
+        }
+        let truncate = chance(faults.truncate);
+
+        let mut res = next.run(req).await?;
+        if truncate {
+            let body = res.take_body();
+            let len = body.len();
+            let mime = body.mime().clone();
+            let mut data = body.into_bytes().await?;
+            data.truncate((random() * data.len() as f64) as usize);
+            let reader = Truncated {
+                data,
+                read: 0,
+                target,
+            };
+            let mut body = Body::from_reader(BufReader::new(reader), len);
+            body.set_mime(mime);
+            res.set_body(body);
+        }
+        Ok(res)
+    }
+}
+
+/// A body which fails once the part of it which made it is read.
+struct Truncated {
+    data: Vec<u8>,
+    read: usize,
+    target: Target,
+}
+
+impl Read for Truncated {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let rest = &this.data[this.read..];
+        if rest.is_empty() {
+            let lost = io::Error::new(io::ErrorKind::UnexpectedEof, "connection lost");
+            return Poll::Ready(Err(this.target.fail_body(lost)));
+        }
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        this.read += n;
+        Poll::Ready(Ok(n))
+    }
+}
+
+/// A random number between 0 and 1.
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+fn chance(probability: f64) -> bool {
+    probability > 0.0 && random() < probability
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h1::ErrorExt;
+    use crate::{Client, HttpClient};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    /// Answers every request with a body of 100 bytes, counting them.
+    #[derive(Debug, Default)]
+    struct Counter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl HttpClient for Counter {
+        async fn send(&self, _: Request) -> Result<Response, Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(vec![b'a'; 100]);
+            Ok(res)
+        }
+    }
+
+    fn client(faults: FaultInjector, sent: &Arc<AtomicUsize>) -> Client {
+        Client::builder()
+            .with(faults)
+            .build_with(Counter(sent.clone()))
+    }
+
+    #[async_std::test]
+    async fn injects_faults() -> Result<(), Error> {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let faults = FaultInjector::new(Faults::new().reset(1.0))
+            .host(
+                "unavailable.test",
+                Faults::new().server_error(1.0, StatusCode::ServiceUnavailable),
+            )
+            .host("truncated.test", Faults::new().truncate(1.0))
+            .host(
+                "slow.test",
+                Faults::new().latency(1.0, Duration::from_millis(50)),
+            );
+        let client = client(faults, &sent);
+
+        let err = client
+            .send(Request::get("http://example.test/"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_kind(), ErrorKind::ResponseParse);
+        assert!(err.is_retryable());
+
+        let res = client
+            .send(Request::get("http://unavailable.test/"))
+            .await?;
+        assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+        assert_eq!(sent.load(Ordering::SeqCst), 0);
+
+        let mut res = client.send(Request::get("http://truncated.test/")).await?;
+        assert_eq!(res.len(), Some(100));
+        let err = res.body_bytes().await.unwrap_err();
+        assert!(err.is_body());
+
+        let start = Instant::now();
+        let mut res = client.send(Request::get("http://slow.test/")).await?;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(res.body_bytes().await?.len(), 100);
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn injects_nothing_by_default() -> Result<(), Error> {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let client = client(FaultInjector::default(), &sent);
+        for _ in 0..10 {
+            let mut res = client.send(Request::get("http://example.test/")).await?;
+            assert_eq!(res.body_bytes().await?.len(), 100);
+        }
+        assert_eq!(sent.load(Ordering::SeqCst), 10);
+        Ok(())
+    }
+}
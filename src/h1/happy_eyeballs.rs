@@ -0,0 +1,142 @@
+//! Dual-stack connection racing ("Happy Eyeballs", RFC 8305).
+//!
+//! Rather than trying each resolved address in turn, which leaves requests hanging for the
+//! whole connect timeout when e.g. IPv6 is broken on the network, attempts are started one
+//! after the other with a short stagger, alternating address families, and the first to
+//! connect wins.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::future::poll_fn;
+use async_std::io;
+use async_std::task::Poll;
+
+use super::net::{LocalBind, TcpStream};
+use super::runtime;
+
+/// How long to wait for a connection attempt before starting the next one in parallel, as
+/// recommended by the RFC.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connect to the first of `addrs` which accepts a connection, racing staggered attempts, each
+/// bound locally as `bind` has it.
+///
+/// An attempt which fails starts the next one immediately, and is told to `failed`. Attempts
+/// still pending once one of them succeeds are dropped.
+pub(crate) async fn connect(
+    addrs: Vec<SocketAddr>,
+    bind: &LocalBind,
+    failed: impl Fn(SocketAddr),
+) -> io::Result<TcpStream> {
+    let mut addrs = interleave(addrs).into_iter();
+    let mut attempts = Vec::new();
+    let mut last_error = None;
+    loop {
+        if let Some(addr) = addrs.next() {
+            attempts.push((addr, runtime::connect(addr, bind)));
+        }
+        if attempts.is_empty() {
+            break;
+        }
+
+        // Only wait for the stagger while there's another address to try.
+        let mut delay = (addrs.len() > 0).then(|| runtime::sleep(CONNECTION_ATTEMPT_DELAY));
+        let finished = poll_fn(|cx| {
+            for idx in 0..attempts.len() {
+                if let Poll::Ready(result) = attempts[idx].1.as_mut().poll(cx) {
+                    let (addr, _) = attempts.swap_remove(idx);
+                    return Poll::Ready(Some((addr, result)));
+                }
+            }
+            match delay.as_mut().map(|delay| delay.as_mut().poll(cx)) {
+                Some(Poll::Ready(())) => Poll::Ready(None),
+                _ => Poll::Pending,
+            }
+        })
+        .await;
+
+        match finished {
+            Some((_, Ok(stream))) => return Ok(stream),
+            Some((addr, Err(e))) => {
+                failed(addr);
+                last_error = Some(e);
+            }
+            None => {}
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::AddrNotAvailable, "missing valid address")
+    }))
+}
+
+/// Order `addrs` alternating between address families, starting with the family of the first
+/// address, and otherwise keeping the resolver's preference order.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = match addrs.first() {
+        Some(addr) => addr.is_ipv6(),
+        None => return addrs,
+    };
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::TcpListener;
+
+    #[test]
+    fn interleaves_address_families() {
+        let addrs = [
+            "[::1]:1",
+            "[::1]:2",
+            "[::1]:3",
+            "127.0.0.1:4",
+            "127.0.0.1:5",
+        ]
+        .iter()
+        .map(|addr| addr.parse().unwrap())
+        .collect();
+        let ports = interleave(addrs)
+            .iter()
+            .map(|addr| addr.port())
+            .collect::<Vec<_>>();
+        assert_eq!(ports, [1, 4, 2, 5, 3]);
+    }
+
+    #[async_std::test]
+    async fn falls_back_to_the_next_address() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        // Nothing listens on the first address, so connecting to it is refused straight away.
+        let refused = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+
+        let failed = std::sync::Mutex::new(Vec::new());
+        let stream = connect(vec![refused, addr], &LocalBind::default(), |addr| {
+            failed.lock().unwrap().push(addr)
+        })
+        .await?;
+        assert_eq!(stream.peer_addr()?, addr);
+        assert_eq!(*failed.lock().unwrap(), [refused]);
+
+        let refused = connect(vec![refused], &LocalBind::default(), |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(refused.kind(), io::ErrorKind::ConnectionRefused);
+        Ok(())
+    }
+}
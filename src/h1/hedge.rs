@@ -0,0 +1,62 @@
+//! Hedging idempotent requests which are slow to be answered, as configured by
+//! `Config::hedge_delay`.
+//!
+//! Rather than waiting out the slowest responses, such as those stuck behind a busy server
+//! process, a request which hasn't been answered after the delay is sent again, over another
+//! connection from the pool, and whichever response comes first is used.
+
+use std::future::Future;
+use std::time::Duration;
+
+use async_std::future::poll_fn;
+use async_std::task::Poll;
+
+use super::runtime;
+use crate::{Error, Response};
+
+/// Await `first`, and if it hasn't finished after `delay`, the request `second` sends too,
+/// taking whichever succeeds first.
+///
+/// Should both fail, the error of `first` is returned. The request which loses is dropped,
+/// closing its connection.
+pub(crate) async fn race<F>(
+    delay: Duration,
+    first: F,
+    second: impl FnOnce() -> F,
+) -> Result<Response, Error>
+where
+    F: Future<Output = Result<Response, Error>>,
+{
+    let mut first = Box::pin(first);
+    if let Ok(result) = runtime::timeout(delay, first.as_mut()).await {
+        return result;
+    }
+
+    let mut second = Box::pin(second());
+    let mut first_error = None;
+    let mut second_failed = false;
+    poll_fn(|cx| {
+        if first_error.is_none() {
+            match first.as_mut().poll(cx) {
+                Poll::Ready(Ok(res)) => return Poll::Ready(Ok(res)),
+                Poll::Ready(Err(e)) => first_error = Some(e),
+                Poll::Pending => {}
+            }
+        }
+        if !second_failed {
+            match second.as_mut().poll(cx) {
+                Poll::Ready(Ok(res)) => return Poll::Ready(Ok(res)),
+                Poll::Ready(Err(_)) => second_failed = true,
+                Poll::Pending => {}
+            }
+        }
+        match first_error.take() {
+            Some(e) if second_failed => Poll::Ready(Err(e)),
+            e => {
+                first_error = e;
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
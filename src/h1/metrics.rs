@@ -0,0 +1,52 @@
+//! Hooks for exporting metrics of the requests sent and the connections they're sent on.
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use http_types::StatusCode;
+
+use super::{ErrorKind, Timings};
+use crate::{Error, Response};
+
+/// Observes the requests a client sends, to feed counters and histograms of a metrics pipeline,
+/// e.g. a Prometheus exporter, labelled by host. Each method does nothing unless implemented.
+///
+/// Set one with `Config::set_metrics_observer`. It's called from the task sending the request,
+/// or establishing the connection, so it shouldn't block.
+pub trait MetricsObserver: Debug + Send + Sync + 'static {
+    /// A request to `host` got a response with `status`, its head arriving `duration` after it
+    /// was sent, redirects followed included. A counter by host and status, and a histogram.
+    fn request(&self, host: &str, status: StatusCode, duration: Duration) {
+        let _ = (host, status, duration);
+    }
+
+    /// A request to `host` failed, as told by `kind`. A counter by host and kind.
+    fn error(&self, host: &str, kind: ErrorKind) {
+        let _ = (host, kind);
+    }
+
+    /// A new connection to `host` was established. A counter by host.
+    fn connection_created(&self, host: &str) {
+        let _ = host;
+    }
+
+    /// A request to `host` waited `wait` for a connection to be checked out from the pool, or
+    /// for the client's connection limit. A histogram.
+    fn pool_wait(&self, host: &str, wait: Duration) {
+        let _ = (host, wait);
+    }
+}
+
+/// Tell `observer` how a request to `host` went, having spent `timings` until its response.
+pub(crate) fn observe(
+    observer: &dyn MetricsObserver,
+    host: &str,
+    result: &Result<Response, Error>,
+    timings: &Timings,
+) {
+    observer.pool_wait(host, timings.pool_wait);
+    match result {
+        Ok(res) => observer.request(host, res.status(), timings.total()),
+        Err(e) => observer.error(host, ErrorKind::of(e)),
+    }
+}
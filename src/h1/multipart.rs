@@ -0,0 +1,280 @@
+//! `multipart/form-data` request bodies, as HTML forms upload files with.
+//!
+//! ```no_run
+//! # async fn upload() -> http_types::Result<()> {
+//! use http_client::h1::multipart::{Form, Part};
+//! use http_client::h1::H1Client;
+//! use http_client::{HttpClient, Request};
+//!
+//! let form = Form::new()
+//!     .text("title", "Holiday")
+//!     .part("photo", Part::file("beach.jpg").await?);
+//! let mut req = Request::post("https://example.com/upload");
+//! req.set_body(form.into_body());
+//! let res = H1Client::new().send(req).await?;
+//! # Ok(()) }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_std::fs::File;
+use async_std::io::{self, Cursor, Read};
+use bytes1::Bytes;
+use http_types::Mime;
+
+use super::streaming_body;
+use crate::Body;
+
+/// A `multipart/form-data` body, made of named parts each holding the value of a form field.
+///
+/// The parts are streamed as the request is sent, so file parts needn't fit in memory. The body
+/// has a `Content-Length` if the length of each part is known, and is sent chunked otherwise.
+#[derive(Debug)]
+pub struct Form {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+impl Form {
+    /// Create an empty form, with a random boundary.
+    pub fn new() -> Self {
+        let random = || RandomState::new().build_hasher().finish();
+        Self::with_boundary(format!("{:016x}{:016x}", random(), random()))
+    }
+
+    /// Create an empty form, separating its parts with `boundary`, which mustn't occur in any of
+    /// them.
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        Self {
+            boundary: boundary.into(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// The boundary separating the form's parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Add a text field.
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(name, Part::text(value))
+    }
+
+    /// Add a field holding `bytes`.
+    pub fn bytes(self, name: impl Into<String>, bytes: impl Into<Bytes>) -> Self {
+        self.part(name, Part::bytes(bytes))
+    }
+
+    /// Add a field, in the order the fields are sent.
+    pub fn part(mut self, name: impl Into<String>, part: Part) -> Self {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    /// The `Content-Type` of the body, naming its boundary.
+    pub fn mime(&self) -> Mime {
+        let mime = format!("multipart/form-data; boundary={}", self.boundary);
+        mime.parse().expect("multipart mime type is valid")
+    }
+
+    /// Encode the form as a request body, with its `Content-Type`.
+    pub fn into_body(self) -> Body {
+        let mime = self.mime();
+        let mut len = Some(0);
+        let mut readers: VecDeque<Box<dyn Read + Send + Unpin>> = VecDeque::new();
+        for (name, part) in self.parts {
+            let head = part.head(&self.boundary, &name);
+            len = len
+                .zip(part.len)
+                .map(|(len, part_len)| len + head.len() + part_len + 2);
+            readers.push_back(Box::new(Cursor::new(head)));
+            readers.push_back(part.reader);
+            readers.push_back(Box::new(&b"\r\n"[..]));
+        }
+        let end = format!("--{}--\r\n", self.boundary);
+        len = len.map(|len| len + end.len());
+        readers.push_back(Box::new(Cursor::new(end)));
+        let mut body = streaming_body(Concat(readers), len);
+        body.set_mime(mime);
+        body
+    }
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The value of a field of a `Form`.
+pub struct Part {
+    reader: Box<dyn Read + Send + Unpin>,
+    len: Option<usize>,
+    file_name: Option<String>,
+    mime: Option<Mime>,
+}
+
+impl std::fmt::Debug for Part {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Part")
+            .field("len", &self.len)
+            .field("file_name", &self.file_name)
+            .field("mime", &self.mime)
+            .finish()
+    }
+}
+
+impl Part {
+    /// A text value, sent without a `Content-Type`, so as plain text.
+    pub fn text(value: impl Into<String>) -> Self {
+        let value = Bytes::from(value.into());
+        Self::new(value.len(), Cursor::new(value), None)
+    }
+
+    /// A binary value, sent as `application/octet-stream`. It isn't copied, so parts of a
+    /// larger buffer can be sent with `Bytes::slice`.
+    pub fn bytes(bytes: impl Into<Bytes>) -> Self {
+        let bytes = bytes.into();
+        let len = bytes.len();
+        Self::new(len, Cursor::new(bytes), Some(http_types::mime::BYTE_STREAM))
+    }
+
+    /// A value streamed from `reader` as it's sent, sent as `application/octet-stream`.
+    ///
+    /// With a known `len`, `reader` must produce exactly that many bytes.
+    pub fn reader(reader: impl Read + Send + Unpin + 'static, len: Option<usize>) -> Self {
+        Self {
+            reader: Box::new(reader),
+            len,
+            file_name: None,
+            mime: Some(http_types::mime::BYTE_STREAM),
+        }
+    }
+
+    /// The file at `path`, streamed as it's sent. Its file name is sent along, and its
+    /// `Content-Type` is guessed from its extension.
+    pub async fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).await?;
+        let len = file.metadata().await?.len() as usize;
+        let mime = path
+            .extension()
+            .and_then(|extension| Mime::from_extension(extension.to_string_lossy()))
+            .unwrap_or(http_types::mime::BYTE_STREAM);
+        let mut part = Self::reader(file, Some(len)).mime(mime);
+        part.file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        Ok(part)
+    }
+
+    fn new(len: usize, reader: impl Read + Send + Unpin + 'static, mime: Option<Mime>) -> Self {
+        Self {
+            reader: Box::new(reader),
+            len: Some(len),
+            file_name: None,
+            mime,
+        }
+    }
+
+    /// Set the file name the part is sent with.
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Set the `Content-Type` the part is sent with.
+    pub fn mime(mut self, mime: impl Into<Mime>) -> Self {
+        self.mime = Some(mime.into());
+        self
+    }
+
+    /// The delimiter and headers ahead of the part, as a field named `name`.
+    fn head(&self, boundary: &str, name: &str) -> Vec<u8> {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            boundary,
+            escape(name)
+        );
+        if let Some(file_name) = &self.file_name {
+            head.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+        if let Some(mime) = &self.mime {
+            head.push_str(&format!("\r\nContent-Type: {}", mime));
+        }
+        head.push_str("\r\n\r\n");
+        head.into_bytes()
+    }
+}
+
+/// Readers read one after the other.
+struct Concat(VecDeque<Box<dyn Read + Send + Unpin>>);
+
+impl Read for Concat {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while let Some(reader) = self.0.front_mut() {
+            match Pin::new(reader).poll_read(cx, buf) {
+                Poll::Ready(Ok(0)) if !buf.is_empty() => drop(self.0.pop_front()),
+                poll => return poll,
+            }
+        }
+        Poll::Ready(Ok(0))
+    }
+}
+
+/// Escape a field or file name to be quoted in a `Content-Disposition`, as HTML forms do.
+fn escape(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn encodes_parts() -> http_types::Result<()> {
+        let form = Form::with_boundary("XYZ")
+            .text("title", "Holiday")
+            .part("photo", Part::bytes(&b"PNG"[..]).file_name("a\"b.png"))
+            .part(
+                "notes",
+                Part::reader(Cursor::new("streamed"), None).mime(http_types::mime::PLAIN),
+            );
+        assert_eq!(form.mime().param("boundary").unwrap(), "XYZ");
+
+        let expected = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Holiday\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"photo\"; filename=\"a%22b.png\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            PNG\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"notes\"\r\n\
+            Content-Type: text/plain;charset=utf-8\r\n\r\n\
+            streamed\r\n\
+            --XYZ--\r\n";
+        let body = form.into_body();
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_string().await?, expected);
+
+        // With the length of each part known, so is the body's.
+        let form = Form::new().text("a", "1").bytes("b", vec![0; 10]);
+        let body = form.into_body();
+        let len = body.len();
+        assert_eq!(len, Some(body.into_bytes().await?.len()));
+        Ok(())
+    }
+}
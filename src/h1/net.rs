@@ -0,0 +1,274 @@
+//! The TCP streams connections are made over: async-std's, or with the `tokio` feature, tokio's
+//! when connecting from within a tokio runtime, so applications running on tokio don't need
+//! async-std's reactor for them. `runtime::connect` opens them.
+
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_std::io::{self, IoSlice, Read, Write};
+use async_std::task::{Context, Poll};
+use socket2::{Domain, SockRef, Socket, Type};
+#[cfg(feature = "tokio")]
+use tokio_util::compat::Compat;
+
+use crate::Config;
+
+/// When TCP keepalive probes are sent on idle connections, as set by `Config::tcp_keepalive`,
+/// to find out about peers which went away without closing them, and keep NAT and firewall
+/// mappings alive. What isn't set is left to the operating system.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    time: Option<Duration>,
+    interval: Option<Duration>,
+    retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    /// Send keepalive probes, as often as the operating system does by default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start probing once a connection has been idle for `time`.
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Probe every `interval` while the peer doesn't answer.
+    ///
+    /// Only supported on Linux, Android, FreeBSD, Apple platforms and Windows.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Close the connection once `retries` probes went unanswered.
+    ///
+    /// Only supported on Linux, Android, FreeBSD and Apple platforms.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    #[allow(unused_mut)]
+    fn to_socket2(self) -> socket2::TcpKeepalive {
+        let mut keepalive = socket2::TcpKeepalive::new();
+        if let Some(time) = self.time {
+            keepalive = keepalive.with_time(time);
+        }
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_vendor = "apple",
+            windows
+        ))]
+        if let Some(interval) = self.interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_vendor = "apple"
+        ))]
+        if let Some(retries) = self.retries {
+            keepalive = keepalive.with_retries(retries);
+        }
+        keepalive
+    }
+}
+
+/// Where new connections are bound locally, as set by `Config::local_address` and
+/// `Config::interface`, for multi-homed hosts to choose which address requests egress from.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LocalBind {
+    address: Option<IpAddr>,
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    interface: Option<String>,
+}
+
+impl LocalBind {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            address: config.local_address,
+            #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+            interface: config.interface.clone(),
+        }
+    }
+
+    /// Whether connections are bound at all, rather than left to the operating system.
+    pub(crate) fn is_set(&self) -> bool {
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if self.interface.is_some() {
+            return true;
+        }
+        self.address.is_some()
+    }
+
+    /// Whether `addr` can be connected to from the local address, being of the same family.
+    pub(crate) fn reaches(&self, addr: &SocketAddr) -> bool {
+        match self.address {
+            Some(local) => local.is_ipv4() == addr.is_ipv4(),
+            None => true,
+        }
+    }
+
+    /// A nonblocking socket to connect to `addr` from, bound as set.
+    pub(crate) fn socket(&self, addr: SocketAddr) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        socket.set_nonblocking(true)?;
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(interface) = &self.interface {
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+        if let Some(address) = self.address {
+            socket.bind(&SocketAddr::new(address, 0).into())?;
+        }
+        Ok(socket)
+    }
+}
+
+/// Start connecting the nonblocking `socket` to `addr`, the connection being established once
+/// the socket becomes writable.
+pub(crate) fn start_connect(socket: &Socket, addr: SocketAddr) -> io::Result<()> {
+    match socket.connect(&addr.into()) {
+        Ok(()) => Ok(()),
+        #[cfg(unix)]
+        Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// A TCP connection.
+#[derive(Debug)]
+pub(crate) enum TcpStream {
+    AsyncStd(async_std::net::TcpStream),
+    #[cfg(feature = "tokio")]
+    Tokio(Compat<tokio1::net::TcpStream>),
+}
+
+impl TcpStream {
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Self::AsyncStd(stream) => stream.peer_addr(),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => stream.get_ref().peer_addr(),
+        }
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Self::AsyncStd(stream) => stream.local_addr(),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => stream.get_ref().local_addr(),
+        }
+    }
+
+    /// Set the socket options of `config` on a newly established connection: `TCP_NODELAY`,
+    /// keepalive probes and buffer sizes.
+    pub(crate) fn configure(&self, config: &Config) -> io::Result<()> {
+        let socket = match self {
+            Self::AsyncStd(stream) => SockRef::from(stream),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => SockRef::from(stream.get_ref()),
+        };
+        socket.set_nodelay(config.tcp_nodelay)?;
+        if let Some(keepalive) = config.tcp_keepalive {
+            socket.set_tcp_keepalive(&keepalive.to_socket2())?;
+        }
+        if let Some(size) = config.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = config.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::AsyncStd(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Write for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::AsyncStd(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::AsyncStd(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            // The compatibility layer would only write the first buffer.
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => {
+                tokio1::io::AsyncWrite::poll_write_vectored(Pin::new(stream.get_mut()), cx, bufs)
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::AsyncStd(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::AsyncStd(stream) => Pin::new(stream).poll_close(cx),
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::TcpListener;
+
+    #[async_std::test]
+    async fn configures_sockets() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let stream = async_std::net::TcpStream::connect(listener.local_addr()?).await?;
+        let config = Config::new()
+            .set_tcp_keepalive(Some(TcpKeepalive::new().with_time(Duration::from_secs(42))))
+            .set_send_buffer_size(Some(64 * 1024));
+        TcpStream::AsyncStd(stream.clone()).configure(&config)?;
+
+        let socket = SockRef::from(&stream);
+        assert!(socket.nodelay()?);
+        assert!(socket.keepalive()?);
+        #[cfg(target_os = "linux")]
+        assert_eq!(socket.keepalive_time()?, Duration::from_secs(42));
+        assert!(socket.send_buffer_size()? >= 64 * 1024);
+        Ok(())
+    }
+}
@@ -0,0 +1,662 @@
+//! Connection pooling shared by the TCP and TLS connection managers.
+
+use std::cell::Cell;
+use std::fmt::{self, Debug};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::io::{self, IoSlice, Read, Write};
+use async_std::task::{Context, Poll, Waker};
+use dashmap::DashMap;
+use deadpool::managed::{Manager, Object, Pool, PoolError, TimeoutType, Timeouts};
+use http_types::{StatusCode, Url};
+use tokio1::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::balance::Lease;
+use super::runtime::{self, Sleep};
+use super::{ClientError, ConnectionInfo, ConnectionObserver, MetricsObserver};
+use crate::{Config, Error, Response};
+
+/// How much earlier than advertised a server's keep-alive timeout is assumed to expire, as the
+/// server starts counting once it has written a response, before we have finished reading it.
+const KEEP_ALIVE_MARGIN: Duration = Duration::from_secs(1);
+
+/// Build a connection pool for a single host, sized according to `config`.
+///
+/// The pool has no runtime of its own: waiting for a connection is timed out by `checkout`, on
+/// the runtime the request is sent from.
+pub(crate) fn build_pool<M>(manager: M, config: &Config) -> Result<Pool<M>, Error>
+where
+    M: Manager,
+    M::Error: std::fmt::Display,
+{
+    Pool::builder(manager)
+        .max_size(config.max_connections_per_host)
+        .build()
+        .map_err(|e| Error::from_str(StatusCode::InternalServerError, e.to_string()))
+}
+
+/// Check a connection out of `pool`, queueing for one for as long as `config` allows.
+pub(crate) async fn checkout<M: Manager>(
+    pool: &Pool<M>,
+    config: &Config,
+) -> Result<Object<M>, PoolError<M::Error>> {
+    match wait_timeout(config) {
+        // Not waiting at all needs no timer, so deadpool can do it without a runtime.
+        Some(wait) if wait.is_zero() => {
+            let timeouts = Timeouts {
+                wait: Some(wait),
+                ..Timeouts::default()
+            };
+            pool.timeout_get(&timeouts).await
+        }
+        Some(wait) => runtime::timeout(wait, pool.get())
+            .await
+            .unwrap_or(Err(PoolError::Timeout(TimeoutType::Wait))),
+        None => pool.get().await,
+    }
+}
+
+/// How long to queue for a connection before failing the request, `None` waiting indefinitely.
+fn wait_timeout(config: &Config) -> Option<Duration> {
+    if config.pool_fail_fast {
+        Some(Duration::from_secs(0))
+    } else {
+        config.pool_checkout_timeout
+    }
+}
+
+/// The client-wide limit on open connections, shared by the connection managers of all pools.
+pub(crate) struct ConnectionLimit {
+    permits: Option<Arc<Semaphore>>,
+    waiting: AtomicUsize,
+    wait_timeout: Option<Duration>,
+    // Closes an idle connection in any pool, returning whether there was one.
+    close_idle: Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl Debug for ConnectionLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionLimit")
+            .field(
+                "available",
+                &self.permits.as_ref().map(|p| p.available_permits()),
+            )
+            .field("waiting", &self.waiting.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl ConnectionLimit {
+    pub(crate) fn new(
+        config: &Config,
+        close_idle: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            permits: config
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            waiting: AtomicUsize::new(0),
+            wait_timeout: wait_timeout(config),
+            close_idle: Box::new(close_idle),
+        }
+    }
+
+    /// Wait for room to open a new connection, which lasts as long as the returned permit.
+    ///
+    /// The semaphore hands out permits in the order they were requested, so hosts with many
+    /// requests queued can't starve the others.
+    pub(crate) async fn acquire(self: &Arc<Self>) -> io::Result<Option<ConnectionPermit>> {
+        let permits = match &self.permits {
+            Some(permits) => permits.clone(),
+            None => return Ok(None),
+        };
+
+        // While anyone is waiting, connections are closed instead of returned idle to the pool.
+        let _waiting = Waiting::new(&self.waiting);
+        let permit = match permits.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                (self.close_idle)();
+                let acquire = permits.acquire_owned();
+                match self.wait_timeout {
+                    Some(timeout) => runtime::timeout(timeout, acquire).await.ok(),
+                    None => Some(acquire.await),
+                }
+                .map(|permit| permit.expect("the connection limit is never closed"))
+            }
+        };
+
+        let permit = permit.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out waiting for the client's connection limit",
+            )
+        })?;
+        Ok(Some(ConnectionPermit {
+            _permit: permit,
+            limit: self.clone(),
+        }))
+    }
+}
+
+/// Counts a request as queued for the connection limit until dropped, even if it's cancelled.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::SeqCst);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Room for one open connection under the client's [`ConnectionLimit`], released on drop.
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    _permit: OwnedSemaphorePermit,
+    limit: Arc<ConnectionLimit>,
+}
+
+impl ConnectionPermit {
+    /// Whether requests are queued for the client's connection limit.
+    pub(crate) fn contended(&self) -> bool {
+        self.limit.waiting.load(Ordering::SeqCst) > 0
+    }
+}
+
+/// Close one idle connection in `pools`, returning whether there was one.
+pub(crate) fn close_idle<M, S>(pools: &DashMap<Origin, Pool<M>>) -> bool
+where
+    M: CountingManager<Type = Pooled<S>>,
+{
+    for pool in pools.iter() {
+        let closed = Cell::new(false);
+        pool.retain(|conn, _| {
+            if closed.replace(true) {
+                return true;
+            }
+            let reason =
+                io::Error::other("closed to make room under the client's connection limit");
+            pool.manager().counters().evicted(conn, &reason);
+            false
+        });
+        if closed.get() {
+            return true;
+        }
+    }
+    false
+}
+
+/// The scheme, host and port identifying which pool a request's connection comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Origin {
+    pub(crate) scheme: &'static str,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+impl Origin {
+    pub(crate) fn from_url(url: &Url) -> Result<Self, Error> {
+        let scheme = match url.scheme() {
+            "http" => "http",
+            "https" => "https",
+            scheme => {
+                return Err(Error::from_str(
+                    StatusCode::BadRequest,
+                    format!("invalid url scheme '{}'", scheme),
+                ))
+            }
+        };
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::from_str(StatusCode::BadRequest, "missing hostname"))?;
+        // IPv6 literals are bracketed in URLs, but not when resolving or connecting.
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| Error::from_str(StatusCode::BadRequest, "missing port"))?;
+        Ok(Self { scheme, host, port })
+    }
+}
+
+/// Close the idle connections in `pools` which have expired.
+pub(crate) fn reap<M, S>(pools: &DashMap<Origin, Pool<M>>, config: &Config)
+where
+    M: CountingManager<Type = Pooled<S>>,
+{
+    for pool in pools.iter() {
+        pool.retain(|conn, _| match conn.check_expiry(config) {
+            Ok(()) => true,
+            Err(reason) => {
+                pool.manager().counters().evicted(conn, &reason);
+                false
+            }
+        });
+    }
+}
+
+/// Connection statistics for the pool of a single host.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct PoolStats {
+    /// The scheme connections are made for, `http` or `https`.
+    pub scheme: &'static str,
+    /// The host connections are made to.
+    pub host: String,
+    /// The port connections are made to.
+    pub port: u16,
+    /// The number of open connections waiting in the pool to be reused.
+    pub idle: usize,
+    /// The number of open connections currently handling a request.
+    pub in_use: usize,
+    /// The total number of open connections.
+    pub total: usize,
+    /// The number of connections established since the pool was created.
+    pub created: u64,
+    /// The number of times an idle connection was handed out again.
+    pub recycled: u64,
+    /// The number of idle connections closed because they had expired or were found broken.
+    pub evicted: u64,
+}
+
+impl PoolStats {
+    pub(crate) fn new<M: CountingManager>(origin: &Origin, pool: &Pool<M>) -> Self {
+        let status = pool.status();
+        let idle = status.available.max(0) as usize;
+        let counters = pool.manager().counters();
+        Self {
+            scheme: origin.scheme,
+            host: origin.host.clone(),
+            port: origin.port,
+            idle,
+            in_use: status.size.saturating_sub(idle),
+            total: status.size,
+            created: counters.created.load(Ordering::Relaxed),
+            recycled: counters.recycled.load(Ordering::Relaxed),
+            evicted: counters.evicted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Cumulative counters for the pool of a single host, kept by its connection manager, which
+/// also tell `Config::metrics_observer` of new connections, and `Config::connection_observer`
+/// what becomes of them.
+#[derive(Debug)]
+pub(crate) struct PoolCounters {
+    host: String,
+    observer: Option<Arc<dyn MetricsObserver>>,
+    created: AtomicU64,
+    recycled: AtomicU64,
+    evicted: AtomicU64,
+}
+
+impl PoolCounters {
+    pub(crate) fn new(origin: &Origin, config: &Config) -> Self {
+        Self {
+            host: origin.host.clone(),
+            observer: config.metrics_observer.clone(),
+            created: AtomicU64::new(0),
+            recycled: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a newly established connection.
+    pub(crate) fn created<S>(&self, conn: &Pooled<S>) {
+        self.created.fetch_add(1, Ordering::Relaxed);
+        if let Some(observer) = &self.observer {
+            observer.connection_created(&self.host);
+        }
+        conn.notify(|observer, info| observer.created(info));
+    }
+
+    /// Count the outcome of checking whether an idle connection can be recycled.
+    pub(crate) fn recycle<S>(&self, conn: &Pooled<S>, result: io::Result<()>) -> io::Result<()> {
+        match &result {
+            Ok(()) => {
+                self.recycled.fetch_add(1, Ordering::Relaxed);
+                conn.notify(|observer, info| observer.reused(info));
+            }
+            Err(reason) => self.evicted(conn, reason),
+        }
+        result
+    }
+
+    /// Count an idle connection closed for `reason`.
+    pub(crate) fn evicted<S>(&self, conn: &Pooled<S>, reason: &io::Error) {
+        self.evicted.fetch_add(1, Ordering::Relaxed);
+        conn.notify(|observer, info| observer.evicted(info, reason));
+    }
+}
+
+/// A connection manager which keeps [`PoolCounters`] for its pool.
+pub(crate) trait CountingManager: Manager {
+    fn counters(&self) -> &PoolCounters;
+}
+
+/// The reuse limits a server advertised for a connection, in a `Keep-Alive` response header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct KeepAlive {
+    timeout: Option<Duration>,
+    max: Option<usize>,
+}
+
+impl KeepAlive {
+    /// Parse a `Keep-Alive: timeout=5, max=100` header value, ignoring unknown parameters.
+    pub(crate) fn parse(header: &str) -> Self {
+        let mut keep_alive = Self::default();
+        for param in header.split(',') {
+            let mut parts = param.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim();
+            let value = parts.next().unwrap_or_default().trim();
+            if name.eq_ignore_ascii_case("timeout") {
+                keep_alive.timeout = value.parse().ok().map(Duration::from_secs);
+            } else if name.eq_ignore_ascii_case("max") {
+                keep_alive.max = value.parse().ok();
+            }
+        }
+        keep_alive
+    }
+
+    /// Record the `Keep-Alive` header of a response received over the connection, if any.
+    pub(crate) fn record(handle: &Mutex<KeepAlive>, res: &Response) {
+        if let Some(header) = res.header("Keep-Alive") {
+            *handle.lock().unwrap() = Self::parse(header.last().as_str());
+        }
+    }
+}
+
+/// Whether the response to a request has been read off its connection to the end, shared
+/// between the connection and the response's body. Connections whose response is abandoned part
+/// way are closed rather than returned to the pool, as the rest of it would be read as the
+/// response to the next request.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Exchange(Arc<ExchangeState>);
+
+#[derive(Debug, Default)]
+struct ExchangeState {
+    finished: AtomicBool,
+    discarded: AtomicBool,
+}
+
+impl Exchange {
+    /// Record that the response has been read to its end.
+    pub(crate) fn finish(&self) {
+        self.0.finished.store(true, Ordering::Release);
+    }
+
+    /// Record that the connection can't be reused, however much of the response is read.
+    pub(crate) fn discard(&self) {
+        self.0.discarded.store(true, Ordering::Release);
+    }
+
+    /// Whether the connection may be returned to the pool.
+    pub(crate) fn reusable(&self) -> bool {
+        self.0.finished.load(Ordering::Acquire) && !self.0.discarded.load(Ordering::Acquire)
+    }
+}
+
+/// A pooled connection, along with when it was established and last returned to the pool.
+#[derive(Debug)]
+pub(crate) struct Pooled<S> {
+    stream: S,
+    info: ConnectionInfo,
+    observer: Option<Arc<dyn ConnectionObserver>>,
+    // The last error reading or writing the stream, told to the observer once it's closed.
+    error: Option<io::Error>,
+    created: Instant,
+    last_used: Instant,
+    // Written once the response is received, while the stream itself is owned by the response body.
+    keep_alive: Arc<Mutex<KeepAlive>>,
+    permit: Option<ConnectionPermit>,
+    // Counts the connection against its address, for `Config::load_balancer`.
+    lease: Option<Lease>,
+    read_timeout: Option<Duration>,
+    // Running while a read is waiting for data.
+    read_timer: Option<ReadTimer>,
+    timed_out: bool,
+}
+
+impl<S> Pooled<S> {
+    pub(crate) fn new(
+        stream: S,
+        info: ConnectionInfo,
+        permit: Option<ConnectionPermit>,
+        config: &Config,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            stream,
+            info,
+            observer: config.connection_observer.clone(),
+            error: None,
+            created: now,
+            last_used: now,
+            keep_alive: Arc::default(),
+            permit,
+            lease: None,
+            read_timeout: config.read_timeout,
+            read_timer: None,
+            timed_out: false,
+        }
+    }
+
+    /// Count the connection against its address until it's closed.
+    pub(crate) fn with_lease(mut self, lease: Option<Lease>) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Stop timing out reads, for HTTP/2 and upgraded connections, which sit idle between
+    /// messages without leaving the `Pooled` connection.
+    pub(crate) fn without_read_timeout(mut self) -> Self {
+        self.read_timeout = None;
+        self
+    }
+
+    /// A handle to record the server's `Keep-Alive` limits for this connection.
+    pub(crate) fn keep_alive(&self) -> Arc<Mutex<KeepAlive>> {
+        self.keep_alive.clone()
+    }
+
+    /// Record that the connection is done with a request, and about to become idle.
+    pub(crate) fn mark_used(&mut self) {
+        self.last_used = Instant::now();
+    }
+
+    /// Whether the connection should be closed rather than returned idle to the pool, to make
+    /// room for requests queued for the client's connection limit.
+    pub(crate) fn contended(&self) -> bool {
+        self.permit
+            .as_ref()
+            .is_some_and(ConnectionPermit::contended)
+    }
+
+    /// Whether a read timed out, leaving the rest of the response unread, so the connection
+    /// mustn't be reused.
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// Tell `Config::connection_observer`, if any, of an event of this connection.
+    pub(crate) fn notify(&self, event: impl FnOnce(&dyn ConnectionObserver, &ConnectionInfo)) {
+        if let Some(observer) = &self.observer {
+            event(&**observer, &self.info);
+        }
+    }
+
+    /// Remember the error a read or write failed with, to tell it once the connection closes.
+    fn record<T>(&mut self, result: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        if let Poll::Ready(Err(e)) = &result {
+            if self.observer.is_some() {
+                self.error = Some(io::Error::new(e.kind(), e.to_string()));
+            }
+        }
+        result
+    }
+}
+
+impl<S> Drop for Pooled<S> {
+    fn drop(&mut self) {
+        self.notify(|observer, info| observer.closed(info, self.error.as_ref()));
+    }
+}
+
+impl<S> Pooled<S> {
+    /// Check that an idle connection is within the configured idle timeout and max lifetime, as
+    /// well as the limits advertised by the server.
+    pub(crate) fn check_expiry(&self, config: &Config) -> io::Result<()> {
+        let keep_alive = *self.keep_alive.lock().unwrap();
+        if let Some(timeout) = keep_alive.timeout {
+            if self.last_used.elapsed() >= timeout.saturating_sub(KEEP_ALIVE_MARGIN) {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection exceeded the server's keep-alive timeout",
+                ));
+            }
+        }
+        if keep_alive.max == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection reached the server's keep-alive request limit",
+            ));
+        }
+
+        if let Some(idle_timeout) = config.idle_timeout {
+            if self.last_used.elapsed() >= idle_timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection exceeded its idle timeout",
+                ));
+            }
+        }
+        if let Some(max_lifetime) = config.max_lifetime {
+            if self.created.elapsed() >= max_lifetime {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection exceeded its max lifetime",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Unpin> Pooled<S> {
+    /// Check that an idle connection may be handed out again: it mustn't have expired, and the
+    /// peer mustn't have closed it or sent anything unsolicited while it sat in the pool.
+    pub(crate) fn check(&mut self, config: &Config) -> io::Result<()> {
+        self.check_expiry(config)?;
+
+        let mut buf = [0; 4];
+        let mut cx = Context::from_waker(Waker::noop());
+        match Pin::new(&mut self.stream).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(Err(error)) => Err(error),
+            Poll::Ready(Ok(0)) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection appeared to be closed (EoF)",
+            )),
+            Poll::Ready(Ok(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected data on an idle connection",
+            )),
+            Poll::Pending => Ok(()),
+        }
+    }
+}
+
+// Connections taken out of the pool (for HTTP/2, or a single request) keep their permit, so they
+// are used through `Pooled` rather than the bare stream.
+impl<S: Read + Unpin> Read for Pooled<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Poll::Ready(result) = Pin::new(&mut this.stream).poll_read(cx, buf) {
+            this.read_timer = None;
+            return this.record(Poll::Ready(result));
+        }
+
+        let timeout = match this.read_timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending,
+        };
+        let timer = this
+            .read_timer
+            .get_or_insert_with(|| ReadTimer(runtime::sleep(timeout)));
+        if timer.0.as_mut().poll(cx).is_ready() {
+            this.read_timer = None;
+            this.timed_out = true;
+            let error = io::Error::new(io::ErrorKind::TimedOut, ClientError::ReadTimeout);
+            return this.record(Poll::Ready(Err(error)));
+        }
+        Poll::Pending
+    }
+}
+
+/// Elapses once a read has waited for `Config::read_timeout`.
+struct ReadTimer(Sleep);
+
+impl Debug for ReadTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReadTimer")
+    }
+}
+
+impl<S: Write + Unpin> Write for Pooled<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.stream).poll_write(cx, buf);
+        self.record(result)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.stream).poll_write_vectored(cx, bufs);
+        self.record(result)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let result = Pin::new(&mut self.stream).poll_flush(cx);
+        self.record(result)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let result = Pin::new(&mut self.stream).poll_close(cx);
+        self.record(result)
+    }
+}
+
+impl<S> Deref for Pooled<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.stream
+    }
+}
+
+impl<S> DerefMut for Pooled<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+}
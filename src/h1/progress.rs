@@ -0,0 +1,209 @@
+//! Reporting the progress of request and response bodies, as set with `OnProgress`, and pacing
+//! them to their `Throttle`.
+
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use async_std::io::{self, BufRead, Read};
+
+use super::buffers::Buffer;
+use super::runtime::{self, Sleep};
+use super::throttle::{Bandwidth, Throttle};
+use crate::{Body, Request, Response};
+
+/// The most of a response body read at once.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Is told how much of the bodies of a request and its response have been transferred.
+///
+/// Set one for a request by inserting an `OnProgress` into its extensions. It's called from the
+/// task sending the request or reading the response, which waits for it to return, so it
+/// shouldn't block. Uploads are only reported for requests sent over HTTP/1.1.
+pub trait ProgressHandler: Debug + Send + Sync + 'static {
+    /// `sent` bytes of the request body have been written, of `total` if its length is known.
+    ///
+    /// A request sent again, on a redirect or retry, starts over from 0.
+    fn upload(&self, sent: u64, total: Option<u64>) {
+        let _ = (sent, total);
+    }
+
+    /// `received` bytes of the response body have been read, of `total` if its length is
+    /// known. These are the bytes as received, before they're decompressed.
+    fn download(&self, received: u64, total: Option<u64>) {
+        let _ = (received, total);
+    }
+}
+
+/// The `ProgressHandler` of a request, when inserted into its extensions.
+#[derive(Clone, Debug)]
+pub struct OnProgress(pub Arc<dyn ProgressHandler>);
+
+impl OnProgress {
+    /// Report the progress of the request to `handler`.
+    pub fn new(handler: impl ProgressHandler) -> Self {
+        Self(Arc::new(handler))
+    }
+}
+
+/// Counts the bytes of a request body as they're written.
+#[derive(Debug, Default)]
+pub(crate) struct Upload {
+    handler: Option<OnProgress>,
+    throttle: Throttle,
+    sent: u64,
+    total: Option<u64>,
+}
+
+impl Upload {
+    pub(crate) fn new(req: &Request, total: Option<usize>) -> Self {
+        Self {
+            handler: req.ext().get::<OnProgress>().cloned(),
+            throttle: req.ext().get::<Throttle>().cloned().unwrap_or_default(),
+            sent: 0,
+            total: total.map(|total| total as u64),
+        }
+    }
+
+    /// Record that `n` more bytes were written, waiting as long as the throttle asks.
+    pub(crate) async fn sent(&mut self, n: usize) {
+        self.sent += n as u64;
+        if let Some(OnProgress(handler)) = &self.handler {
+            handler.upload(self.sent, self.total);
+        }
+        let delay = self.throttle.sent(n);
+        if delay > Duration::ZERO {
+            runtime::sleep(delay).await;
+        }
+    }
+}
+
+/// Report reading the body of `res` to the `handler` of its request, and pace it to `throttle`.
+pub(crate) fn download(
+    mut res: Response,
+    handler: Option<OnProgress>,
+    throttle: Throttle,
+) -> Response {
+    if handler.is_none() && throttle.is_unlimited() {
+        return res;
+    }
+    let body = res.take_body();
+    let len = body.len();
+    let mime = body.mime().clone();
+    let reader = Download {
+        body,
+        handler: handler.map(|OnProgress(handler)| handler),
+        throttle,
+        delay: None,
+        buf: Buffer::zeroed(BUFFER_SIZE),
+        pos: 0,
+        filled: 0,
+        received: 0,
+        total: len.map(|len| len as u64),
+    };
+    let mut body = Body::from_reader(reader, len);
+    body.set_mime(mime);
+    res.set_body(body);
+    res
+}
+
+/// A response body which reports how much of it was read, and holds what it received back as
+/// long as its throttle asks.
+struct Download {
+    body: Body,
+    handler: Option<Arc<dyn ProgressHandler>>,
+    throttle: Throttle,
+    delay: Option<Sleep>,
+    buf: Buffer,
+    pos: usize,
+    filled: usize,
+    received: u64,
+    total: Option<u64>,
+}
+
+impl BufRead for Download {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos == this.filled {
+            let n = ready!(Pin::new(&mut this.body).poll_read(cx, &mut this.buf))?;
+            this.pos = 0;
+            this.filled = n;
+            let delay = this.throttle.received(n);
+            if delay > Duration::ZERO {
+                this.delay = Some(runtime::sleep(delay));
+            }
+        }
+        if let Some(delay) = &mut this.delay {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.pos += amt;
+        self.received += amt as u64;
+        if let (Some(handler), true) = (&self.handler, amt > 0) {
+            handler.download(self.received, self.total);
+        }
+    }
+}
+
+impl Read for Download {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+/// Clone `req` to send it again, keeping its `OnProgress` and `Bandwidth`, which
+/// `Request::clone` leaves out.
+pub(crate) fn clone_request(req: &Request) -> Request {
+    let mut clone = req.clone();
+    if let Some(handler) = req.ext().get::<OnProgress>() {
+        clone.ext_mut().insert(handler.clone());
+    }
+    if let Some(bandwidth) = req.ext().get::<Bandwidth>() {
+        clone.ext_mut().insert(bandwidth.clone());
+    }
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct Downloads(Mutex<Vec<(u64, Option<u64>)>>);
+
+    impl ProgressHandler for Downloads {
+        fn download(&self, received: u64, total: Option<u64>) {
+            self.0.lock().unwrap().push((received, total));
+        }
+    }
+
+    #[async_std::test]
+    async fn reports_downloads() -> Result<(), Error> {
+        let downloads = Arc::new(Downloads::default());
+        let mut res = Response::new(200);
+        res.set_body("hello");
+        let handler = OnProgress(downloads.clone());
+        let mut res = download(res, Some(handler), Throttle::default());
+        assert_eq!(res.len(), Some(5));
+
+        assert_eq!(res.body_string().await?, "hello");
+        assert_eq!(*downloads.0.lock().unwrap(), [(5, Some(5))]);
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Limiting the rate requests are sent at, as configured by `Config::rate_limit` and
+//! `Config::per_host_rate_limit`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use super::pool::Origin;
+use super::runtime;
+use super::ClientError;
+use crate::{Config, Error};
+
+/// How many requests may be sent in a period of time, as a token bucket.
+///
+/// The bucket holds up to `burst` tokens, refilled at `requests` tokens per period, and each
+/// request takes one. A request finding the bucket empty waits until a token is refilled for it,
+/// in the order they arrived, rather than failing. If `max_queued` requests are already waiting,
+/// it fails with `ClientError::RateLimitQueueFull` instead.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    max_queued: Option<usize>,
+}
+
+impl RateLimit {
+    /// Allow `requests` requests, all at once or spread out, in each `period`. At least one
+    /// request is allowed, in a period of at least a millisecond.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let requests = f64::from(requests.max(1));
+        let period = period.max(Duration::from_millis(1));
+        Self {
+            per_second: requests / period.as_secs_f64(),
+            burst: requests,
+            max_queued: None,
+        }
+    }
+
+    /// Set how many requests may be sent at once after a lull, `requests` by default.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = f64::from(burst.max(1));
+        self
+    }
+
+    /// Set how many requests may wait for their turn at once, unlimited by default.
+    pub fn max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = Some(max_queued);
+        self
+    }
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+    queued: usize,
+}
+
+/// A token bucket, as configured by a `RateLimit`.
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    state: Mutex<BucketState>,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        let state = BucketState {
+            tokens: limit.burst,
+            updated: Instant::now(),
+            queued: 0,
+        };
+        Self {
+            limit,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Take a token, waiting for it to be refilled if the bucket is empty.
+    async fn acquire(&self) -> Result<(), Error> {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let refilled = now.duration_since(state.updated).as_secs_f64() * self.limit.per_second;
+            state.tokens = (state.tokens + refilled).min(self.limit.burst);
+            state.updated = now;
+            let full = self.limit.max_queued.is_some_and(|max| state.queued >= max);
+            if state.tokens < 1.0 && full {
+                return Err(ClientError::RateLimitQueueFull.into_error());
+            }
+            // Tokens go negative while requests are waiting, so each waits its turn.
+            state.tokens -= 1.0;
+            if state.tokens >= 0.0 {
+                return Ok(());
+            }
+            state.queued += 1;
+            Duration::from_secs_f64(-state.tokens / self.limit.per_second)
+        };
+
+        let mut queued = Queued {
+            bucket: self,
+            waited: false,
+        };
+        runtime::sleep(wait).await;
+        queued.waited = true;
+        Ok(())
+    }
+}
+
+/// A request waiting for a token, which is given back if it stops waiting early.
+struct Queued<'a> {
+    bucket: &'a Bucket,
+    waited: bool,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        let mut state = self.bucket.state.lock().unwrap();
+        state.queued -= 1;
+        if !self.waited {
+            state.tokens += 1.0;
+        }
+    }
+}
+
+/// The buckets requests take tokens from: one shared by all requests, and one for each host.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    global: Option<Bucket>,
+    per_host: Option<RateLimit>,
+    host_limits: HashMap<String, RateLimit>,
+    hosts: DashMap<Origin, Arc<Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            global: config.rate_limit.clone().map(Bucket::new),
+            per_host: config.per_host_rate_limit.clone(),
+            host_limits: config.host_rate_limits.clone(),
+            hosts: DashMap::new(),
+        }
+    }
+
+    /// Wait for a request to `origin` to be allowed.
+    pub(crate) async fn acquire(&self, origin: Origin) -> Result<(), Error> {
+        if let Some(bucket) = self.host_bucket(origin) {
+            bucket.acquire().await?;
+        }
+        if let Some(bucket) = &self.global {
+            bucket.acquire().await?;
+        }
+        Ok(())
+    }
+
+    fn host_bucket(&self, origin: Origin) -> Option<Arc<Bucket>> {
+        if let Some(bucket) = self.hosts.get(&origin) {
+            return Some(bucket.clone());
+        }
+        let limit = self
+            .host_limits
+            .get(&origin.host.to_ascii_lowercase())
+            .or(self.per_host.as_ref())?;
+        let bucket = Arc::new(Bucket::new(limit.clone()));
+        // Clone the bucket out, so the map isn't locked while waiting for it.
+        Some(self.hosts.entry(origin).or_insert(bucket).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    #[async_std::test]
+    async fn waits_for_tokens() -> Result<(), Error> {
+        let bucket = Bucket::new(RateLimit::new(20, Duration::from_secs(1)).burst(2));
+        let start = Instant::now();
+        for _ in 0..4 {
+            bucket.acquire().await?;
+        }
+        // Two requests are sent at once, then one every 50 milliseconds.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+
+        let limit = RateLimit::new(1, Duration::from_secs(60)).max_queued(1);
+        let bucket = Arc::new(Bucket::new(limit));
+        bucket.acquire().await?;
+        let queued = task::spawn({
+            let bucket = bucket.clone();
+            async move { bucket.acquire().await }
+        });
+        task::sleep(Duration::from_millis(10)).await;
+        let err = bucket.acquire().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ClientError>(),
+            Some(&ClientError::RateLimitQueueFull)
+        );
+
+        // A request which stops waiting leaves its place in the queue.
+        queued.cancel().await;
+        {
+            let state = bucket.state.lock().unwrap();
+            assert_eq!(state.queued, 0);
+            assert!(state.tokens < 0.1, "{}", state.tokens);
+        }
+
+        // Limits which would never let a request through are clamped.
+        let bucket = Bucket::new(RateLimit::new(0, Duration::ZERO));
+        bucket.acquire().await?;
+        bucket.acquire().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+//! Following redirects, as configured by `Config::redirect_policy`.
+
+use async_std::io;
+use bytes1::Bytes;
+use http_types::{Method, StatusCode};
+
+use super::body::{bytes_body, read_bytes, set_request_body, take_request_body};
+use super::progress;
+use crate::{Body, Error, Request, Response};
+
+/// The largest request body buffered to be sent again when following a redirect which keeps the
+/// method.
+/// Redirects of requests with larger bodies, or bodies of unknown length, are returned as the
+/// response instead.
+const MAX_REPLAYED_BODY: usize = 64 * 1024;
+
+/// Headers carrying credentials, which aren't sent on to another origin.
+const SENSITIVE_HEADERS: [&str; 3] = ["Authorization", "Cookie", "Proxy-Authorization"];
+
+/// Which redirects are followed, rather than returned as the response. Inserted into a request's
+/// extensions, it overrides `Config::redirect_policy` for that request.
+///
+/// Redirects are followed on a connection to the host they point to, from that host's pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    max_redirects: usize,
+    cross_origin: bool,
+    strip_sensitive_headers: bool,
+}
+
+impl RedirectPolicy {
+    /// Don't follow redirects.
+    pub fn none() -> Self {
+        Self {
+            max_redirects: 0,
+            cross_origin: false,
+            strip_sensitive_headers: true,
+        }
+    }
+
+    /// Follow up to `max_redirects` redirects in a row, including to other origins, failing the
+    /// request with `ClientError::TooManyRedirects` if there are more.
+    ///
+    /// The `Authorization`, `Cookie` and `Proxy-Authorization` headers are dropped when
+    /// following a redirect to another origin, so credentials aren't leaked to it.
+    pub fn limited(max_redirects: usize) -> Self {
+        Self {
+            max_redirects,
+            cross_origin: true,
+            strip_sensitive_headers: true,
+        }
+    }
+
+    /// Set whether redirects to another scheme, host or port are followed.
+    pub fn follow_cross_origin(mut self, follow: bool) -> Self {
+        self.cross_origin = follow;
+        self
+    }
+
+    /// Set whether the `Authorization`, `Cookie` and `Proxy-Authorization` headers are dropped
+    /// when following a redirect to another scheme, host or port. Only disable this if every
+    /// origin the requests may be redirected to is trusted with the credentials.
+    pub fn strip_sensitive_headers(mut self, strip: bool) -> Self {
+        self.strip_sensitive_headers = strip;
+        self
+    }
+
+    /// The maximum number of redirects followed in a row.
+    pub fn max_redirects(&self) -> usize {
+        self.max_redirects
+    }
+
+    /// Whether redirects to another scheme, host or port are followed.
+    pub fn follows_cross_origin(&self) -> bool {
+        self.cross_origin
+    }
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Take the body of `req`, buffered to be sent again for each redirect, if it's small enough.
+pub(crate) async fn replayable_body(req: &mut Request) -> Result<Option<Bytes>, Error> {
+    let body = take_request_body(req);
+    match body.len() {
+        Some(len) if len <= MAX_REPLAYED_BODY => Ok(Some(read_bytes(body).await?)),
+        _ => {
+            set_request_body(req, body);
+            Ok(None)
+        }
+    }
+}
+
+/// Set `body`, as taken by `replayable_body`, on `req` to send it again, sharing rather than
+/// copying it. An empty body is left out, so a request without one is sent as it was.
+pub(crate) fn replay_body(req: &mut Request, body: &Bytes) {
+    if !body.is_empty() {
+        set_request_body(req, bytes_body(body.clone()));
+    }
+}
+
+/// The request following the redirect `res` to `req`, if it's one the policy follows.
+///
+/// `req` is the request as sent, without its body, which should be sent again if the returned
+/// request's method is the same. As per RFC 7231, `303 See Other` is followed with a `GET`, as
+/// are `POST` requests redirected with `301` or `302`, like browsers do. Other redirects keep the
+/// method and body, so aren't followed if the body isn't `replayable`.
+pub(crate) fn follow(
+    policy: &RedirectPolicy,
+    req: &Request,
+    res: &Response,
+    replayable: bool,
+) -> Option<Request> {
+    let to_get = match res.status() {
+        StatusCode::SeeOther => req.method() != Method::Head,
+        StatusCode::MovedPermanently | StatusCode::Found if req.method() == Method::Post => true,
+        StatusCode::MovedPermanently
+        | StatusCode::Found
+        | StatusCode::TemporaryRedirect
+        | StatusCode::PermanentRedirect
+            if replayable =>
+        {
+            false
+        }
+        _ => return None,
+    };
+    let location = res.header("Location")?.last().as_str();
+    let url = req.url().join(location).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let cross_origin = url.origin() != req.url().origin();
+    if cross_origin && !policy.cross_origin {
+        return None;
+    }
+
+    let mut next = progress::clone_request(req);
+    *next.url_mut() = url;
+    if cross_origin && policy.strip_sensitive_headers {
+        for name in SENSITIVE_HEADERS {
+            next.remove_header(name);
+        }
+    }
+    if to_get {
+        next.set_method(Method::Get);
+        for name in [
+            "Content-Type",
+            "Content-Length",
+            "Content-Encoding",
+            "Transfer-Encoding",
+        ] {
+            next.remove_header(name);
+        }
+    }
+    Some(next)
+}
+
+/// Read the rest of a redirect's body, so its connection can be reused.
+pub(crate) async fn drain(mut res: Response) {
+    let body: Body = res.take_body();
+    if let Err(e) = io::copy(body, io::sink()).await {
+        log::debug!("failed to read the body of a redirect: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_types::Url;
+
+    fn redirect(location: &str) -> Response {
+        let mut res = Response::new(StatusCode::Found);
+        res.insert_header("Location", location);
+        res
+    }
+
+    #[async_std::test]
+    async fn replays_bodies_as_they_were() -> Result<(), Error> {
+        let url = Url::parse("https://example.test/").unwrap();
+        let mut req = Request::new(Method::Get, url.clone());
+        let body = replayable_body(&mut req).await?.unwrap();
+        let mut sent = req.clone();
+        replay_body(&mut sent, &body);
+        assert_eq!(sent.len(), Some(0));
+        assert!(sent.header("Content-Type").is_none());
+
+        let mut req = Request::new(Method::Put, url);
+        req.set_body("hello");
+        req.remove_header("Content-Type");
+        let body = replayable_body(&mut req).await?.unwrap();
+        let mut sent = req.clone();
+        replay_body(&mut sent, &body);
+        assert!(sent.header("Content-Type").is_none());
+        assert_eq!(sent.body_string().await?, "hello");
+        Ok(())
+    }
+
+    #[test]
+    fn strips_sensitive_headers_across_origins() {
+        let mut req = Request::new(Method::Get, Url::parse("https://example.test/").unwrap());
+        req.insert_header("Authorization", "Bearer secret");
+        req.insert_header("Cookie", "session=secret");
+        req.insert_header("Accept", "text/plain");
+        let policy = RedirectPolicy::limited(1);
+
+        let next = follow(&policy, &req, &redirect("/other"), true).unwrap();
+        assert!(next.header("Authorization").is_some());
+        assert!(next.header("Cookie").is_some());
+
+        // A different port is another origin, as is a different scheme or host.
+        for location in [
+            "https://example.test:8443/",
+            "http://example.test/",
+            "https://evil.test/",
+        ] {
+            let next = follow(&policy, &req, &redirect(location), true).unwrap();
+            assert!(next.header("Authorization").is_none());
+            assert!(next.header("Cookie").is_none());
+            assert!(next.header("Accept").is_some());
+        }
+
+        let policy = policy.strip_sensitive_headers(false);
+        let next = follow(&policy, &req, &redirect("https://evil.test/"), true).unwrap();
+        assert!(next.header("Authorization").is_some());
+    }
+}
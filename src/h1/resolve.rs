@@ -0,0 +1,186 @@
+//! Host name resolution for new connections.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::io;
+use async_std::net::ToSocketAddrs;
+use dashmap::DashMap;
+
+use crate::{async_trait, Config};
+
+/// Resolves a host name to the addresses to connect to, each time a connection is established.
+///
+/// Set a custom resolver with `Config::set_resolver`, e.g. to use trust-dns, service discovery,
+/// or a static map of hosts.
+#[async_trait]
+pub trait Resolver: Debug + Send + Sync + 'static {
+    /// Resolve `host` to socket addresses with the given `port`, in order of preference.
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+
+    /// Resolve `host` like `resolve`, along with how long the addresses may be cached for, if
+    /// the resolver knows the records' TTL.
+    ///
+    /// The default implementation doesn't know the TTL.
+    async fn resolve_with_ttl(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> io::Result<(Vec<SocketAddr>, Option<Duration>)> {
+        Ok((self.resolve(host, port).await?, None))
+    }
+}
+
+/// The default resolver, using the system's resolver through `async-std`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs().await?.collect())
+    }
+}
+
+/// Caches the addresses of each host, as resolved by `Config::resolver`, for the TTL configured
+/// by `Config::dns_cache_min_ttl` and `Config::dns_cache_max_ttl`. Shared by all pools of a
+/// client.
+///
+/// Hosts in `Config::resolve_overrides` are never resolved nor cached.
+#[derive(Debug)]
+pub(crate) struct DnsCache {
+    resolver: Arc<dyn Resolver>,
+    overrides: HashMap<String, Vec<SocketAddr>>,
+    min_ttl: Duration,
+    // `None` disables the cache.
+    max_ttl: Option<Duration>,
+    entries: DashMap<String, CacheEntry>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    ips: Vec<IpAddr>,
+    expires: Instant,
+}
+
+impl DnsCache {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            resolver: config.resolver.clone(),
+            overrides: config.resolve_overrides.clone(),
+            min_ttl: config.dns_cache_min_ttl,
+            max_ttl: config.dns_cache_max_ttl,
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Whether resolved addresses are cached, rather than resolved for every connection.
+    pub(crate) fn enabled(&self) -> bool {
+        self.max_ttl.is_some()
+    }
+
+    /// Resolve `host`, from the cache while its entry hasn't expired.
+    pub(crate) async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addrs) = self.overrides.get(host) {
+            return Ok(addrs.clone());
+        }
+        let max_ttl = match self.max_ttl {
+            Some(max_ttl) => max_ttl,
+            None => return self.resolver.resolve(host, port).await,
+        };
+        if let Some(ips) = self.cached(host) {
+            return Ok(ips
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect());
+        }
+
+        let (addrs, ttl) = self.resolver.resolve_with_ttl(host, port).await?;
+        let ttl = ttl.unwrap_or(max_ttl).max(self.min_ttl).min(max_ttl);
+        let entry = CacheEntry {
+            ips: addrs.iter().map(SocketAddr::ip).collect(),
+            expires: Instant::now() + ttl,
+        };
+        self.entries.insert(host.to_string(), entry);
+        Ok(addrs)
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let entry = self.entries.get(host)?;
+        if entry.expires > Instant::now() {
+            Some(entry.ips.clone())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug)]
+    struct CountingResolver {
+        ttl: Option<Duration>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Resolver for CountingResolver {
+        async fn resolve(&self, _host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![([127, 0, 0, 1], port).into()])
+        }
+
+        async fn resolve_with_ttl(
+            &self,
+            host: &str,
+            port: u16,
+        ) -> io::Result<(Vec<SocketAddr>, Option<Duration>)> {
+            Ok((self.resolve(host, port).await?, self.ttl))
+        }
+    }
+
+    #[async_std::test]
+    async fn caches_within_ttl() -> io::Result<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let resolver = CountingResolver {
+            ttl: Some(Duration::from_secs(3600)),
+            calls: calls.clone(),
+        };
+        let config = Config::new()
+            .set_resolver(resolver)
+            .set_dns_cache_max_ttl(Some(Duration::from_millis(50)));
+        let cache = DnsCache::new(&config);
+
+        let addrs = cache.resolve("example.test", 80).await?;
+        assert_eq!(addrs, [SocketAddr::from(([127, 0, 0, 1], 80))]);
+        let addrs = cache.resolve("example.test", 443).await?;
+        assert_eq!(addrs, [SocketAddr::from(([127, 0, 0, 1], 443))]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The record's TTL is clamped to the configured maximum.
+        async_std::task::sleep(Duration::from_millis(100)).await;
+        cache.resolve("example.test", 80).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn disabled_by_default() -> io::Result<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let resolver = CountingResolver {
+            ttl: None,
+            calls: calls.clone(),
+        };
+        let cache = DnsCache::new(&Config::new().set_resolver(resolver));
+        cache.resolve("example.test", 80).await?;
+        cache.resolve("example.test", 80).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+}
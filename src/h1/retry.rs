@@ -0,0 +1,345 @@
+//! Retrying failed requests, as configured by `Config::retry_policy`.
+
+use std::collections::hash_map::RandomState;
+use std::error::Error as StdError;
+use std::fmt::{self, Debug, Display};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_std::io;
+use http_types::headers::RETRY_AFTER;
+use http_types::other::RetryAfter;
+use http_types::{Method, StatusCode};
+
+use super::{ErrorExt, RequestError};
+use crate::{Error, Response};
+
+type Predicate = dyn Fn(&Result<Response, Error>) -> bool + Send + Sync;
+
+/// Which failed requests are sent again, and how long to back off before each retry.
+///
+/// Only requests with idempotent methods are retried, unless `retry_non_idempotent` is set or
+/// they failed before any of them was sent, as `ErrorExt::is_unsent` tells, and only if their
+/// body is small enough to be buffered to be sent again, like a redirected body.
+///
+/// Whatever the policy, a request failing on a pooled connection which the server closed before
+/// responding, as a server may when it closes an idle connection just as it's reused, is sent
+/// again once on a new connection.
+///
+/// A retried `429 Too Many Requests` or `503 Service Unavailable` response with a `Retry-After`
+/// header is retried after the delay it asks for instead of the backoff, up to
+/// `max_retry_after`.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_retry_after: Duration,
+    retry_non_idempotent: bool,
+    predicate: Option<Arc<Predicate>>,
+}
+
+impl RetryPolicy {
+    /// Don't retry requests.
+    pub fn none() -> Self {
+        Self::exponential(0)
+    }
+
+    /// Retry up to `max_retries` times, backing off exponentially from 100 milliseconds up to
+    /// 10 seconds, with jitter.
+    ///
+    /// By default requests are retried if they fail to connect or the connection is lost, as
+    /// `ErrorExt::is_retryable` tells, or if the response is a `429 Too Many Requests`,
+    /// `502 Bad Gateway`, `503 Service Unavailable` or `504 Gateway Timeout`.
+    pub fn exponential(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_retry_after: Duration::from_secs(60),
+            retry_non_idempotent: false,
+            predicate: None,
+        }
+    }
+
+    /// Set the backoff before the first retry, which doubles for each retry after it up to
+    /// `max`. Each backoff is then randomized to between half and all of it, so clients which
+    /// failed together don't retry together.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set the longest delay a `Retry-After` header is honored up to, 60 seconds by default.
+    /// Longer delays are cut short.
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Set whether requests with methods which aren't idempotent, like `POST`, are retried. Only
+    /// enable this if the server can deal with receiving such a request twice.
+    pub fn retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.retry_non_idempotent = retry;
+        self
+    }
+
+    /// Decide which results are retried with `predicate`, instead of the default.
+    pub fn retry_if(
+        mut self,
+        predicate: impl Fn(&Result<Response, Error>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// The maximum number of times a request is retried.
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    /// Whether `method` requests may be retried.
+    pub(crate) fn allows(&self, method: Method) -> bool {
+        self.retry_non_idempotent || is_idempotent(method)
+    }
+
+    /// Whether to retry after the `retries`th retry ended with `result`.
+    pub(crate) fn should_retry(&self, retries: usize, result: &Result<Response, Error>) -> bool {
+        if retries >= self.max_retries {
+            return false;
+        }
+        match &self.predicate {
+            Some(predicate) => predicate(result),
+            None => is_retryable(result),
+        }
+    }
+
+    /// How long to wait before the `retry`th retry, counting from 0, of a request ending with
+    /// `result`.
+    pub(crate) fn delay_for(&self, retry: usize, result: &Result<Response, Error>) -> Duration {
+        let retry_after = match result {
+            Ok(res)
+                if res.status() == StatusCode::TooManyRequests
+                    || res.status() == StatusCode::ServiceUnavailable =>
+            {
+                retry_after(res)
+            }
+            _ => None,
+        };
+        match retry_after {
+            Some(delay) => delay.min(self.max_retry_after),
+            None => self.backoff_for(retry),
+        }
+    }
+
+    /// How long to back off before the `retry`th retry, counting from 0.
+    pub(crate) fn backoff_for(&self, retry: usize) -> Duration {
+        let factor = 2u32.saturating_pow(retry.min(31) as u32);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        let half = backoff / 2;
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        half + half.mul_f64(jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_retries", &self.max_retries)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("max_retry_after", &self.max_retry_after)
+            .field("retry_non_idempotent", &self.retry_non_idempotent)
+            .field("predicate", &self.predicate.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+/// How long `res` asks to wait before the request is sent again, per its `Retry-After` header,
+/// given either as a number of seconds or as a date. A date in the past asks for no delay.
+///
+/// Returns `None` without a header, or if it can't be parsed.
+pub fn retry_after(res: &Response) -> Option<Duration> {
+    let header = res.header(RETRY_AFTER)?.last();
+    if let Ok(seconds) = header.as_str().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = SystemTime::from(RetryAfter::from_headers(res).ok()??);
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Whether requests with `method` may be sent more than once with the same effect, per
+/// RFC 7231.
+pub(crate) fn is_idempotent(method: Method) -> bool {
+    matches!(
+        method,
+        Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options | Method::Trace
+    )
+}
+
+/// Whether `result` is worth retrying by default: a transient status, or a failure to establish
+/// or keep hold of the connection.
+fn is_retryable(result: &Result<Response, Error>) -> bool {
+    let error = match result {
+        Ok(res) => {
+            return matches!(
+                res.status(),
+                StatusCode::TooManyRequests
+                    | StatusCode::BadGateway
+                    | StatusCode::ServiceUnavailable
+                    | StatusCode::GatewayTimeout
+            )
+        }
+        Err(error) => error,
+    };
+    error.is_retryable()
+}
+
+/// A pooled connection failed before any of the response was received, likely because the
+/// server closed it as it was reused.
+#[derive(Debug)]
+pub(crate) struct StaleConnection(RequestError);
+
+impl StaleConnection {
+    /// Mark `error`, from sending a request over a pooled connection which was `reused`, if it
+    /// looks like the connection was stale.
+    pub(crate) fn mark(error: Error, reused: bool) -> Error {
+        if !reused {
+            return error;
+        }
+        let stale = error
+            .downcast_ref::<RequestError>()
+            .and_then(RequestError::io_error)
+            .is_some_and(|error| {
+                matches!(
+                    error.kind(),
+                    io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::BrokenPipe
+                )
+            });
+        if !stale {
+            return error;
+        }
+        let status = error.status();
+        match error.downcast::<RequestError>() {
+            Ok(error) => Error::new(status, StaleConnection(error)),
+            Err(error) => error,
+        }
+    }
+
+    /// Undo `mark`, for errors which aren't retried.
+    pub(crate) fn unmark(error: Error) -> Error {
+        let status = error.status();
+        match error.downcast::<StaleConnection>() {
+            Ok(StaleConnection(error)) => Error::new(status, error),
+            Err(error) => error,
+        }
+    }
+}
+
+impl Display for StaleConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for StaleConnection {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h1::{ClientError, Phase};
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy::exponential(10)
+            .backoff(Duration::from_millis(100), Duration::from_millis(1000));
+        for (retry, max) in [
+            (0, 100),
+            (1, 200),
+            (2, 400),
+            (3, 800),
+            (4, 1000),
+            (30, 1000),
+        ] {
+            let backoff = policy.backoff_for(retry);
+            let max = Duration::from_millis(max);
+            assert!(backoff >= max / 2 && backoff <= max, "{:?}", backoff);
+        }
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        let policy = RetryPolicy::exponential(2);
+        let status = |status| Ok(Response::new(status));
+        let io_error = |kind| Err(Error::from(io::Error::new(kind, "failed")));
+        assert!(policy.should_retry(0, &status(StatusCode::ServiceUnavailable)));
+        assert!(!policy.should_retry(2, &status(StatusCode::ServiceUnavailable)));
+        assert!(!policy.should_retry(0, &status(StatusCode::InternalServerError)));
+        assert!(policy.should_retry(0, &io_error(io::ErrorKind::ConnectionRefused)));
+        assert!(!policy.should_retry(0, &io_error(io::ErrorKind::InvalidData)));
+
+        let timeout = |phase| Err(ClientError::Timeout { phase }.into_error());
+        assert!(policy.should_retry(0, &timeout(Phase::Connect)));
+        assert!(!policy.should_retry(0, &timeout(Phase::Body)));
+
+        assert!(policy.allows(Method::Put));
+        assert!(!policy.allows(Method::Post));
+        let policy = policy
+            .retry_non_idempotent(true)
+            .retry_if(|result| result.is_err());
+        assert!(policy.allows(Method::Post));
+        assert!(!policy.should_retry(0, &status(StatusCode::ServiceUnavailable)));
+    }
+
+    #[test]
+    fn honors_retry_after() {
+        let policy = RetryPolicy::exponential(2)
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .max_retry_after(Duration::from_secs(30));
+        let response = |status, retry_after: &str| {
+            let mut res = Response::new(status);
+            res.insert_header("Retry-After", retry_after);
+            Ok(res)
+        };
+
+        let res = response(StatusCode::TooManyRequests, "5");
+        assert_eq!(
+            retry_after(res.as_ref().unwrap()),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(policy.delay_for(0, &res), Duration::from_secs(5));
+        let res = response(StatusCode::ServiceUnavailable, "3600");
+        assert_eq!(policy.delay_for(0, &res), Duration::from_secs(30));
+
+        let res = response(
+            StatusCode::ServiceUnavailable,
+            "Wed, 21 Oct 2015 07:28:00 GMT",
+        );
+        assert_eq!(retry_after(res.as_ref().unwrap()), Some(Duration::ZERO));
+        assert_eq!(policy.delay_for(0, &res), Duration::ZERO);
+
+        // Other statuses, and unparseable headers, back off as usual.
+        let res = response(StatusCode::BadGateway, "5");
+        assert!(policy.delay_for(0, &res) <= Duration::from_secs(1));
+        let res = response(StatusCode::TooManyRequests, "soon");
+        assert_eq!(retry_after(res.as_ref().unwrap()), None);
+        assert!(policy.delay_for(0, &res) <= Duration::from_secs(1));
+    }
+}
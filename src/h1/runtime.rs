@@ -0,0 +1,157 @@
+//! The runtime the client's timers, background tasks and sockets run on: async-std's, or with the
+//! `tokio` feature, tokio's when used from within a tokio runtime, so timeouts and the idle
+//! connection reaper behave the same on either.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_std::io;
+use futures_lite::future;
+
+use super::net::{self, LocalBind, TcpStream};
+
+/// A timer, which completes once its duration has elapsed.
+pub(crate) type Sleep = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// A task run in the background.
+pub(crate) type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A connection being established.
+pub(crate) type Connecting = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>;
+
+/// What the client needs from an async runtime.
+pub(crate) trait Runtime: Debug + Send + Sync {
+    /// Run `task` in the background, detached.
+    fn spawn(&self, task: Task);
+
+    /// A timer for `duration`.
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Open a TCP connection to `addr`, bound locally as `bind` has it, registered with the
+    /// runtime's reactor.
+    fn connect(&self, addr: SocketAddr, bind: &LocalBind) -> Connecting;
+}
+
+/// async-std's global executor and reactor, started on first use.
+#[derive(Debug)]
+pub(crate) struct AsyncStd;
+
+impl Runtime for AsyncStd {
+    fn spawn(&self, task: Task) {
+        async_std::task::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async_std::task::sleep(duration))
+    }
+
+    fn connect(&self, addr: SocketAddr, bind: &LocalBind) -> Connecting {
+        let socket = bind.is_set().then(|| bind.socket(addr));
+        Box::pin(async move {
+            let socket = match socket {
+                Some(socket) => socket?,
+                None => {
+                    let stream = async_std::net::TcpStream::connect(addr).await?;
+                    return Ok(TcpStream::AsyncStd(stream));
+                }
+            };
+            net::start_connect(&socket, addr)?;
+            let stream = async_io::Async::new(std::net::TcpStream::from(socket))?;
+            stream.writable().await?;
+            if let Some(e) = stream.get_ref().take_error()? {
+                return Err(e);
+            }
+            Ok(TcpStream::AsyncStd(stream.into_inner()?.into()))
+        })
+    }
+}
+
+/// The tokio runtime the client is used from, which needs its IO and time drivers enabled.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(crate) struct Tokio(tokio1::runtime::Handle);
+
+#[cfg(feature = "tokio")]
+impl Runtime for Tokio {
+    fn spawn(&self, task: Task) {
+        self.0.spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let _guard = self.0.enter();
+        Box::pin(tokio1::time::sleep(duration))
+    }
+
+    fn connect(&self, addr: SocketAddr, bind: &LocalBind) -> Connecting {
+        use tokio_util::compat::TokioAsyncReadCompatExt;
+
+        let socket = bind.is_set().then(|| bind.socket(addr));
+        let _guard = self.0.enter();
+        Box::pin(async move {
+            let stream = match socket {
+                Some(socket) => {
+                    let socket = std::net::TcpStream::from(socket?);
+                    tokio1::net::TcpSocket::from_std_stream(socket)
+                        .connect(addr)
+                        .await?
+                }
+                None => tokio1::net::TcpStream::connect(addr).await?,
+            };
+            Ok(TcpStream::Tokio(stream.compat()))
+        })
+    }
+}
+
+/// The runtime to use from here: tokio's if within a tokio runtime, and async-std's otherwise.
+pub(crate) fn current() -> Box<dyn Runtime> {
+    #[cfg(feature = "tokio")]
+    if let Ok(handle) = tokio1::runtime::Handle::try_current() {
+        return Box::new(Tokio(handle));
+    }
+    Box::new(AsyncStd)
+}
+
+/// Run `task` in the background on the current runtime.
+pub(crate) fn spawn(task: impl Future<Output = ()> + Send + 'static) {
+    current().spawn(Box::pin(task));
+}
+
+/// A timer for `duration` on the current runtime.
+pub(crate) fn sleep(duration: Duration) -> Sleep {
+    current().sleep(duration)
+}
+
+/// Open a TCP connection to `addr`, bound locally as `bind` has it, on the current runtime.
+pub(crate) fn connect(addr: SocketAddr, bind: &LocalBind) -> Connecting {
+    current().connect(addr, bind)
+}
+
+/// The error of a future that didn't complete in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Elapsed;
+
+/// Await `fut`, failing if it doesn't complete within `duration`.
+pub(crate) async fn timeout<F: Future>(duration: Duration, fut: F) -> Result<F::Output, Elapsed> {
+    let timer = sleep(duration);
+    future::or(async { Ok(fut.await) }, async {
+        timer.await;
+        Err(Elapsed)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn times_out_futures() {
+        let slow = future::pending::<()>();
+        assert_eq!(timeout(Duration::from_millis(10), slow).await, Err(Elapsed));
+        let fast = async { 1 };
+        assert_eq!(timeout(Duration::from_secs(5), fast).await, Ok(1));
+    }
+}
@@ -0,0 +1,202 @@
+//! The SOCKS5 handshake, per RFC 1928, with username and password authentication, per
+//! RFC 1929.
+
+use std::net::{IpAddr, SocketAddr};
+
+use async_std::io::{self, Read, ReadExt, Write, WriteExt};
+
+use super::proxy::Credentials;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 1;
+const IPV4: u8 = 1;
+const DOMAIN: u8 = 3;
+const IPV6: u8 = 4;
+
+/// Where the proxy is asked to connect to.
+#[derive(Debug)]
+pub(crate) enum Target<'a> {
+    /// An address resolved by the client.
+    Addr(SocketAddr),
+    /// A host for the proxy to resolve, and a port.
+    Domain(&'a str, u16),
+}
+
+/// Ask the SOCKS5 proxy at the other end of `stream` to connect to `target`, authenticating
+/// with `credentials` if it asks for them.
+pub(crate) async fn connect<S>(
+    stream: &mut S,
+    target: Target<'_>,
+    credentials: Option<&Credentials>,
+) -> io::Result<()>
+where
+    S: Read + Write + Unpin,
+{
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[VERSION, 2, NO_AUTH, USERNAME_PASSWORD],
+        None => &[VERSION, 1, NO_AUTH],
+    };
+    stream.write_all(greeting).await?;
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != VERSION {
+        return Err(invalid("proxy doesn't speak SOCKS5"));
+    }
+    match (choice[1], credentials) {
+        (NO_AUTH, _) => {}
+        (USERNAME_PASSWORD, Some(credentials)) => authenticate(stream, credentials).await?,
+        (NO_ACCEPTABLE_METHODS, _) | (USERNAME_PASSWORD, None) => {
+            return Err(denied("SOCKS5 proxy requires authentication"));
+        }
+        _ => return Err(invalid("proxy chose an unsupported authentication method")),
+    }
+
+    stream.write_all(&encode_connect(&target)?).await?;
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(invalid("proxy doesn't speak SOCKS5"));
+    }
+    if reply[1] != 0 {
+        return Err(reply_error(reply[1]));
+    }
+    // The address the proxy connected from, which isn't needed.
+    let len = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN => {
+            let mut len = [0];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(invalid("proxy replied with an invalid address type")),
+    };
+    let mut bound = vec![0; len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+async fn authenticate<S>(stream: &mut S, credentials: &Credentials) -> io::Result<()>
+where
+    S: Read + Write + Unpin,
+{
+    let username = credentials.username.as_bytes();
+    let password = credentials.password.as_bytes();
+    if username.len() > 255 || password.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "SOCKS5 username or password longer than 255 bytes",
+        ));
+    }
+    let mut request = vec![1, username.len() as u8];
+    request.extend_from_slice(username);
+    request.push(password.len() as u8);
+    request.extend_from_slice(password);
+    stream.write_all(&request).await?;
+
+    let mut status = [0; 2];
+    stream.read_exact(&mut status).await?;
+    if status[1] != 0 {
+        return Err(denied("SOCKS5 proxy rejected the username and password"));
+    }
+    Ok(())
+}
+
+/// Encode the request asking the proxy to connect to `target`.
+fn encode_connect(target: &Target<'_>) -> io::Result<Vec<u8>> {
+    let mut request = vec![VERSION, CONNECT, 0];
+    let port = match target {
+        Target::Addr(addr) => {
+            match addr.ip() {
+                IpAddr::V4(ip) => {
+                    request.push(IPV4);
+                    request.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    request.push(IPV6);
+                    request.extend_from_slice(&ip.octets());
+                }
+            }
+            addr.port()
+        }
+        Target::Domain(host, port) => {
+            if host.len() > 255 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "host too long for SOCKS5",
+                ));
+            }
+            request.push(DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+            *port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+/// The error for a SOCKS5 reply code other than success, of the kind connecting directly would
+/// have failed with.
+fn reply_error(code: u8) -> io::Error {
+    let (kind, message) = match code {
+        2 => (
+            io::ErrorKind::PermissionDenied,
+            "SOCKS5 proxy doesn't allow the connection",
+        ),
+        3 => (
+            io::ErrorKind::Other,
+            "network unreachable from the SOCKS5 proxy",
+        ),
+        4 => (
+            io::ErrorKind::Other,
+            "host unreachable from the SOCKS5 proxy",
+        ),
+        5 => (
+            io::ErrorKind::ConnectionRefused,
+            "connection refused through the SOCKS5 proxy",
+        ),
+        6 => (io::ErrorKind::TimedOut, "SOCKS5 proxy timed out connecting"),
+        7 => (
+            io::ErrorKind::Unsupported,
+            "SOCKS5 proxy doesn't support CONNECT",
+        ),
+        8 => (
+            io::ErrorKind::Unsupported,
+            "SOCKS5 proxy doesn't support the address type",
+        ),
+        _ => (io::ErrorKind::Other, "SOCKS5 proxy failed"),
+    };
+    io::Error::new(kind, message)
+}
+
+fn denied(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_connect_requests() -> io::Result<()> {
+        let addr = Target::Addr(([10, 0, 0, 1], 8080).into());
+        assert_eq!(
+            encode_connect(&addr)?,
+            [5, 1, 0, IPV4, 10, 0, 0, 1, 0x1f, 0x90]
+        );
+        let domain = Target::Domain("a.test", 443);
+        assert_eq!(
+            encode_connect(&domain)?,
+            [5, 1, 0, DOMAIN, 6, b'a', b'.', b't', b'e', b's', b't', 1, 0xbb]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! Server-Sent Events, as `text/event-stream` responses stream them, per the HTML standard.
+//!
+//! ```no_run
+//! # async fn watch() -> http_types::Result<()> {
+//! use http_client::h1::sse::EventSource;
+//! use http_client::h1::H1Client;
+//! use http_client::Request;
+//!
+//! let client = H1Client::new();
+//! let mut events = EventSource::new(&client, Request::get("https://example.com/updates"));
+//! while let Some(event) = events.next_event().await {
+//!     let event = event?;
+//!     println!("{}: {}", event.event, event.data);
+//! }
+//! # Ok(()) }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use async_std::io::ReadExt;
+use http_types::StatusCode;
+
+use super::runtime;
+use crate::{Body, Error, HttpClient, Request};
+
+/// How long to wait before reconnecting, until the server sets it with a `retry` field.
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+/// An event received from the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// The ID of the event, or the last one set before it, to resume after with
+    /// `Last-Event-ID`. Empty if none was set.
+    pub id: String,
+    /// The type of the event, `message` unless set with an `event` field.
+    pub event: String,
+    /// The data of the event, its `data` fields joined with newlines.
+    pub data: String,
+}
+
+/// A stream of events from the server, sending a request with `Accept: text/event-stream` and
+/// reading its response as it arrives.
+///
+/// When the response ends or the connection fails, the request is sent again after the
+/// reconnection time, with the `Last-Event-ID` of the last event received, so the server can
+/// carry on where it left off. The error a connection failed with is returned by
+/// `next_event`, and the one after reconnects. The stream ends when the server answers with
+/// `204 No Content`, and fails for good for other statuses than `200 OK`, or responses which
+/// aren't `text/event-stream`.
+#[derive(Debug)]
+pub struct EventSource<'a, C: ?Sized> {
+    client: &'a C,
+    // Sent again, without a body, to reconnect.
+    req: Request,
+    body: Option<Body>,
+    parser: Parser,
+    reconnect: bool,
+    done: bool,
+}
+
+impl<'a, C: HttpClient + ?Sized> EventSource<'a, C> {
+    /// Receive the events streamed in response to `req`, sent with `client`.
+    ///
+    /// The request is sent only once `next_event` is first called. Its body and extensions
+    /// aren't sent again when reconnecting.
+    pub fn new(client: &'a C, mut req: Request) -> Self {
+        req.insert_header("Accept", "text/event-stream");
+        req.insert_header("Cache-Control", "no-store");
+        Self {
+            client,
+            req,
+            body: None,
+            parser: Parser::default(),
+            reconnect: false,
+            done: false,
+        }
+    }
+
+    /// The ID of the last event received, sent as `Last-Event-ID` when reconnecting.
+    pub fn last_event_id(&self) -> &str {
+        &self.parser.last_event_id
+    }
+
+    /// How long to wait before reconnecting, 3 seconds unless the server set it with a `retry`
+    /// field.
+    pub fn retry(&self) -> Duration {
+        self.parser.retry
+    }
+
+    /// Wait for the next event, reconnecting as needed. Returns `None` once the stream has
+    /// ended, and the error a connection failed with before reconnecting.
+    pub async fn next_event(&mut self) -> Option<Result<Event, Error>> {
+        let mut buf = vec![0; 8 * 1024];
+        loop {
+            if let Some(event) = self.parser.events.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            let body = match &mut self.body {
+                Some(body) => body,
+                None => match self.connect().await {
+                    Ok(Some(body)) => self.body.insert(body),
+                    Ok(None) => return None,
+                    Err(e) => return Some(Err(e)),
+                },
+            };
+            match body.read(&mut buf).await {
+                Ok(0) => self.disconnect(),
+                Ok(n) => self.parser.feed(&buf[..n]),
+                Err(e) => {
+                    self.disconnect();
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+
+    /// Send the request, after waiting the reconnection time if this is a reconnection, and
+    /// return the body of the stream, or `None` if the server ended it.
+    async fn connect(&mut self) -> Result<Option<Body>, Error> {
+        if self.reconnect {
+            runtime::sleep(self.parser.retry).await;
+        }
+        self.reconnect = true;
+        let mut req = self.req.clone();
+        if !self.parser.last_event_id.is_empty() {
+            req.insert_header("Last-Event-ID", self.parser.last_event_id.as_str());
+        }
+
+        let mut res = self.client.send(req).await?;
+        let status = res.status();
+        if status == StatusCode::NoContent {
+            self.done = true;
+            return Ok(None);
+        }
+        if status != StatusCode::Ok {
+            self.done = true;
+            return Err(Error::from_str(
+                status,
+                format!("event stream failed with status {}", status),
+            ));
+        }
+        let event_stream = res
+            .content_type()
+            .is_some_and(|mime| mime.essence() == "text/event-stream");
+        if !event_stream {
+            self.done = true;
+            return Err(Error::from_str(
+                StatusCode::BadGateway,
+                "event stream answered without text/event-stream",
+            ));
+        }
+        Ok(Some(res.take_body()))
+    }
+
+    fn disconnect(&mut self) {
+        self.body = None;
+        self.parser.restart();
+    }
+}
+
+/// Parses the event stream incrementally, as it arrives.
+#[derive(Debug)]
+struct Parser {
+    // Received but not yet parsed, as it doesn't end a line yet.
+    buf: Vec<u8>,
+    // Whether the stream is yet to start, so a byte order mark is skipped.
+    start: bool,
+    // Whether the last line ended with a CR, so an LF right after it is skipped.
+    cr: bool,
+    data: String,
+    event: String,
+    last_event_id: String,
+    retry: Duration,
+    events: VecDeque<Event>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            start: true,
+            cr: false,
+            data: String::new(),
+            event: String::new(),
+            last_event_id: String::new(),
+            retry: DEFAULT_RETRY,
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl Parser {
+    /// Parse the lines `bytes` completes, queueing the events they dispatch.
+    fn feed(&mut self, mut bytes: &[u8]) {
+        if self.cr && bytes.first() == Some(&b'\n') {
+            bytes = &bytes[1..];
+        }
+        self.cr = false;
+        self.buf.extend_from_slice(bytes);
+        if self.start {
+            if self.buf.len() < 3 && b"\xef\xbb\xbf".starts_with(&self.buf) {
+                return;
+            }
+            if self.buf.starts_with(b"\xef\xbb\xbf") {
+                self.buf.drain(..3);
+            }
+            self.start = false;
+        }
+
+        let mut pos = 0;
+        while let Some(len) = self.buf[pos..]
+            .iter()
+            .position(|&b| b == b'\r' || b == b'\n')
+        {
+            let end = pos + len;
+            let line = String::from_utf8_lossy(&self.buf[pos..end]).into_owned();
+            self.line(&line);
+            pos = end + 1;
+            if self.buf[end] == b'\r' {
+                match self.buf.get(pos) {
+                    Some(b'\n') => pos += 1,
+                    Some(_) => {}
+                    None => self.cr = true,
+                }
+            }
+        }
+        self.buf.drain(..pos);
+    }
+
+    fn line(&mut self, line: &str) {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        let (field, value) = match line.find(':') {
+            // A comment, often sent to keep the connection alive.
+            Some(0) => return,
+            Some(i) => {
+                let value = &line[i + 1..];
+                (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Duration::from_millis(millis);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let event = std::mem::take(&mut self.event);
+        if self.data.is_empty() {
+            return;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        self.events.push_back(Event {
+            id: self.last_event_id.clone(),
+            event: if event.is_empty() {
+                "message".to_string()
+            } else {
+                event
+            },
+            data,
+        });
+    }
+
+    /// Discard the event being received, as its stream ended before it did.
+    fn restart(&mut self) {
+        self.buf.clear();
+        self.start = true;
+        self.cr = false;
+        self.data.clear();
+        self.event.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&[u8]]) -> Vec<Event> {
+        let mut parser = Parser::default();
+        for chunk in chunks {
+            parser.feed(chunk);
+        }
+        parser.events.into()
+    }
+
+    fn event(id: &str, event: &str, data: &str) -> Event {
+        Event {
+            id: id.to_string(),
+            event: event.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_events() {
+        let events = parse(&[
+            b"\xef\xbb\xbf: comment\n",
+            b"data: first\ndata:second\n\n",
+            b"id: 1\nevent: update\ndata\ndata:  x\n\n",
+            b"event: ignored\n\ndata: cut off",
+        ]);
+        assert_eq!(
+            events,
+            [
+                event("", "message", "first\nsecond"),
+                event("1", "update", "\n x")
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let events = parse(&[b"data: a\r", b"\ndata: b\r\r", b"id: 2\rdata: c\n", b"\n"]);
+        assert_eq!(
+            events,
+            [event("", "message", "a\nb"), event("2", "message", "c")]
+        );
+    }
+
+    #[test]
+    fn sets_the_reconnection_time() {
+        let mut parser = Parser::default();
+        parser.feed(b"retry: 1500\nretry: soon\nid: a\0b\n");
+        assert_eq!(parser.retry, Duration::from_millis(1500));
+        assert_eq!(parser.last_event_id, "");
+    }
+}
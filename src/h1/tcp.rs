@@ -0,0 +1,412 @@
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use async_std::io::{self, IoSlice, Read, Write};
+use async_std::task::{Context, Poll};
+use deadpool::managed::{Manager, Object, RecycleError, RecycleResult};
+
+use super::balance::{Balance, Lease};
+use super::connect::{Connect, Connection};
+use super::events::ConnectionInfo;
+use super::happy_eyeballs;
+use super::net::{LocalBind, TcpStream};
+use super::pool::{ConnectionLimit, CountingManager, Exchange, Origin, PoolCounters, Pooled};
+use super::proxy::{self, Proxy};
+use super::resolve::DnsCache;
+use super::runtime;
+use super::timeout::{self, Phase};
+use super::tls::{self, TlsStream};
+use super::upgrade::Upgradable;
+use crate::{async_trait, Config, Error};
+
+/// A connection to a server, or to the proxy leading to it.
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    /// A connection to an HTTPS proxy, in TLS with the proxy. A connection to an HTTPS server
+    /// through it is in TLS again inside it, with the server.
+    Tls(Box<TlsStream>),
+    /// A connection established by `Config::connector`.
+    Custom(Box<dyn Connection>),
+}
+
+impl Stream {
+    /// The TCP connection underneath, unless it was established by `Config::connector`.
+    pub(crate) fn tcp(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Tcp(stream) => Some(stream),
+            Self::Tls(stream) => Some(tls::inner(stream)),
+            Self::Custom(_) => None,
+        }
+    }
+
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().ok_or_else(not_tcp)?.peer_addr()
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().ok_or_else(not_tcp)?.local_addr()
+    }
+}
+
+fn not_tcp() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "not a TCP connection")
+}
+
+impl Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(stream) => f.debug_tuple("Tcp").field(stream).finish(),
+            Self::Tls(stream) => {
+                let tcp: &TcpStream = tls::inner(stream);
+                f.debug_tuple("Tls").field(tcp).finish()
+            }
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Custom(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Custom(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Self::Tls(stream) => tls::poll_write_coalesced(Pin::new(stream), cx, bufs),
+            Self::Custom(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Custom(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_close(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_close(cx),
+            Self::Custom(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
+
+/// Resolves an origin's host when establishing a connection, and remembers the addresses it
+/// last resolved to, so that connections to addresses which have since disappeared from DNS
+/// aren't reused.
+///
+/// Connections to an origin behind a proxy are established to the proxy instead, and lead
+/// through it to the origin as the proxy calls for. With `Config::connector` set, connections
+/// are established by it, and neither resolved nor proxied.
+#[derive(Debug)]
+pub(crate) struct Connector {
+    origin: Origin,
+    custom: Option<Arc<dyn Connect>>,
+    proxy: Option<Proxy>,
+    dns: Arc<DnsCache>,
+    resolved: Mutex<Vec<SocketAddr>>,
+    balance: Option<Balance>,
+}
+
+impl Connector {
+    pub(crate) fn new(origin: Origin, dns: Arc<DnsCache>, config: &Config) -> Self {
+        Self {
+            custom: config.connector.clone(),
+            proxy: proxy::select(config, &origin).cloned(),
+            origin,
+            dns,
+            resolved: Mutex::new(Vec::new()),
+            balance: config.load_balancer.clone().map(Balance::new),
+        }
+    }
+
+    pub(crate) fn origin(&self) -> &Origin {
+        &self.origin
+    }
+
+    /// The host and port connections are established to.
+    fn addr(&self) -> (&str, u16) {
+        match &self.proxy {
+            Some(proxy) => proxy.addr(),
+            None => (&self.origin.host, self.origin.port),
+        }
+    }
+
+    /// Resolve the origin's host, and race connections to the addresses it resolves to, in the
+    /// order `Config::load_balancer` picks them in, if set.
+    pub(crate) async fn connect(&self, config: &Config) -> io::Result<Stream> {
+        if let Some(custom) = &self.custom {
+            timeout::enter(Phase::Connect);
+            let stream = custom.connect(&self.origin.host, self.origin.port).await?;
+            return Ok(Stream::Custom(stream));
+        }
+        let (host, port) = self.addr();
+        timeout::enter(Phase::Resolve);
+        let addrs = self.dns.resolve(host, port).await?;
+        *self.resolved.lock().unwrap() = addrs.clone();
+        timeout::enter(Phase::Connect);
+        let bind = LocalBind::new(config);
+        let addrs = addrs
+            .into_iter()
+            .filter(|addr| bind.reaches(addr))
+            .collect();
+        let stream = match &self.balance {
+            Some(balance) => {
+                let addrs = balance.order(addrs);
+                // Counted until it connects, so connections established at the same time are
+                // spread out too.
+                let _lease = addrs.first().map(|&addr| balance.lease(addr));
+                happy_eyeballs::connect(addrs, &bind, |addr| balance.failed(addr)).await?
+            }
+            None => happy_eyeballs::connect(addrs, &bind, |_| {}).await?,
+        };
+        stream.configure(config)?;
+        let proxy = match &self.proxy {
+            Some(proxy) => proxy,
+            None => return Ok(Stream::Tcp(stream)),
+        };
+        let mut stream = if proxy.is_tls() {
+            // Only the server's protocols are negotiated, not the proxy's.
+            timeout::enter(Phase::Tls);
+            let stream = tls::add_tls(host, stream, config, false)
+                .await
+                .map_err(into_io_error)?;
+            timeout::enter(Phase::Connect);
+            Stream::Tls(Box::new(stream))
+        } else {
+            Stream::Tcp(stream)
+        };
+        proxy.open(&mut stream, &self.origin, &self.dns).await?;
+        Ok(stream)
+    }
+
+    /// Count a connection to `peer_addr` against it, for `Config::load_balancer`, marking it
+    /// healthy again.
+    pub(crate) fn lease(&self, peer_addr: Option<SocketAddr>) -> Option<Lease> {
+        Some(self.balance.as_ref()?.connected(peer_addr?))
+    }
+
+    /// Check that a pooled connection's peer is still among the addresses the host last
+    /// resolved to. With the DNS cache enabled, the host is resolved again once its cached
+    /// addresses expire. Connections established by `Config::connector` aren't checked.
+    pub(crate) async fn check_peer(&self, stream: &Stream) -> io::Result<()> {
+        let peer_addr = match stream.tcp() {
+            Some(stream) => stream.peer_addr()?,
+            None => return Ok(()),
+        };
+        if self.dns.enabled() {
+            let (host, port) = self.addr();
+            match self.dns.resolve(host, port).await {
+                Ok(addrs) => *self.resolved.lock().unwrap() = addrs,
+                Err(e) => log::debug!("failed to resolve {}: {}", host, e),
+            }
+        }
+        if self.resolved.lock().unwrap().contains(&peer_addr) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "connection's peer no longer resolves from the host",
+            ))
+        }
+    }
+}
+
+/// The `io::Error` behind `error`, or one wrapping it.
+fn into_io_error(error: Error) -> io::Error {
+    match error.downcast::<io::Error>() {
+        Ok(error) => error,
+        Err(error) => io::Error::other(error.into_inner()),
+    }
+}
+
+/// Bound `connect` by `Config::connect_timeout`, if set.
+pub(crate) async fn with_connect_timeout<T, E, F>(config: &Config, connect: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<io::Error>,
+{
+    match config.connect_timeout {
+        Some(timeout) => match runtime::timeout(timeout, connect).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out").into()),
+        },
+        None => connect.await,
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TcpConnection {
+    connector: Connector,
+    config: Arc<Config>,
+    limit: Arc<ConnectionLimit>,
+    counters: PoolCounters,
+}
+
+impl TcpConnection {
+    pub(crate) fn new(
+        origin: Origin,
+        config: Arc<Config>,
+        limit: Arc<ConnectionLimit>,
+        dns: Arc<DnsCache>,
+    ) -> Self {
+        Self {
+            counters: PoolCounters::new(&origin, &config),
+            connector: Connector::new(origin, dns, &config),
+            config,
+            limit,
+        }
+    }
+}
+
+pub(crate) struct TcpConnWrapper {
+    // Only `None` once dropped.
+    conn: Option<Object<TcpConnection>>,
+    exchange: Exchange,
+}
+
+impl TcpConnWrapper {
+    pub(crate) fn new(conn: Object<TcpConnection>) -> Self {
+        Self {
+            conn: Some(conn),
+            exchange: Exchange::default(),
+        }
+    }
+
+    /// A handle to record that the response has been read to its end, so the connection can be
+    /// returned to the pool once dropped.
+    pub(crate) fn exchange(&self) -> Exchange {
+        self.exchange.clone()
+    }
+
+    fn stream(&mut self) -> Pin<&mut Pooled<Stream>> {
+        Pin::new(&mut **self.conn.as_mut().expect("connection used after drop"))
+    }
+}
+
+impl Drop for TcpConnWrapper {
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            conn.mark_used();
+            if conn.contended() || conn.timed_out() || !self.exchange.reusable() {
+                drop(Object::take(conn));
+            } else {
+                conn.notify(|observer, info| observer.recycled(info));
+            }
+        }
+    }
+}
+
+impl Upgradable for TcpConnWrapper {
+    fn upgrade(mut self) -> Box<dyn Connection> {
+        let conn = self.conn.take().expect("connection used after drop");
+        Object::take(conn).upgrade()
+    }
+}
+
+impl Read for TcpConnWrapper {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.stream().poll_read(cx, buf)
+    }
+}
+
+impl Write for TcpConnWrapper {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.stream().poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.stream().poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_close(cx)
+    }
+}
+
+#[async_trait]
+impl Manager for TcpConnection {
+    type Type = Pooled<Stream>;
+    type Error = io::Error;
+
+    async fn create(&self) -> Result<Pooled<Stream>, io::Error> {
+        let permit = self.limit.acquire().await?;
+        let stream =
+            with_connect_timeout(&self.config, self.connector.connect(&self.config)).await?;
+        let info = ConnectionInfo::new(
+            self.connector.origin(),
+            stream.peer_addr().ok(),
+            stream.local_addr().ok(),
+            None,
+        );
+        let lease = self.connector.lease(info.peer_addr);
+        let conn = Pooled::new(stream, info, permit, &self.config).with_lease(lease);
+        self.counters.created(&conn);
+        Ok(conn)
+    }
+
+    async fn recycle(&self, conn: &mut Pooled<Stream>) -> RecycleResult<io::Error> {
+        let result = match self.connector.check_peer(conn).await {
+            Ok(()) => conn.check(&self.config),
+            Err(e) => Err(e),
+        };
+        self.counters
+            .recycle(conn, result)
+            .map_err(RecycleError::Backend)
+    }
+}
+
+impl CountingManager for TcpConnection {
+    fn counters(&self) -> &PoolCounters {
+        &self.counters
+    }
+}
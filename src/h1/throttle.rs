@@ -0,0 +1,172 @@
+//! Limiting the bandwidth bodies are transferred with, as configured by
+//! `Config::upload_bandwidth` and `Config::download_bandwidth`, and by a request's `Bandwidth`.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Config, Request};
+
+/// The bandwidth a request's body may be sent with, and its response's body received with, in
+/// bytes per second, when inserted into its extensions.
+///
+/// These apply on top of the client's `Config::upload_bandwidth` and
+/// `Config::download_bandwidth`, which are shared by all its requests. Uploads are only limited
+/// for requests sent over HTTP/1.1.
+#[derive(Clone, Debug, Default)]
+pub struct Bandwidth {
+    upload: Option<u64>,
+    download: Option<u64>,
+}
+
+impl Bandwidth {
+    /// Transfer bodies as fast as possible, unless limited by the client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many bytes of the request body may be sent per second.
+    pub fn upload(mut self, bytes_per_second: u64) -> Self {
+        self.upload = Some(bytes_per_second);
+        self
+    }
+
+    /// Set how many bytes of the response body may be received per second.
+    pub fn download(mut self, bytes_per_second: u64) -> Self {
+        self.download = Some(bytes_per_second);
+        self
+    }
+}
+
+#[derive(Debug)]
+struct PacerState {
+    bytes: f64,
+    updated: Instant,
+}
+
+/// A token bucket of bytes, holding up to a second's worth.
+#[derive(Debug)]
+pub(crate) struct Pacer {
+    per_second: f64,
+    state: Mutex<PacerState>,
+}
+
+impl Pacer {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        let per_second = bytes_per_second.max(1) as f64;
+        let state = PacerState {
+            bytes: per_second,
+            updated: Instant::now(),
+        };
+        Self {
+            per_second,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Take `n` bytes which were just transferred, and return how long to wait before
+    /// transferring more.
+    fn take(&self, n: usize) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let refilled = now.duration_since(state.updated).as_secs_f64() * self.per_second;
+        state.bytes = (state.bytes + refilled).min(self.per_second);
+        state.updated = now;
+        // The bucket goes negative when a transfer overdraws it, so whichever transfers next
+        // waits for it to be paid back.
+        state.bytes -= n as f64;
+        if state.bytes >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-state.bytes / self.per_second)
+    }
+}
+
+/// The pacers a request's body is sent with, and its response's body received with.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Throttle {
+    upload: Vec<Arc<Pacer>>,
+    download: Vec<Arc<Pacer>>,
+}
+
+impl Throttle {
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.upload.is_empty() && self.download.is_empty()
+    }
+
+    /// How long to wait after sending `n` bytes.
+    pub(crate) fn sent(&self, n: usize) -> Duration {
+        take(&self.upload, n)
+    }
+
+    /// How long to wait after receiving `n` bytes.
+    pub(crate) fn received(&self, n: usize) -> Duration {
+        take(&self.download, n)
+    }
+}
+
+fn take(pacers: &[Arc<Pacer>], n: usize) -> Duration {
+    pacers
+        .iter()
+        .map(|pacer| pacer.take(n))
+        .max()
+        .unwrap_or(Duration::ZERO)
+}
+
+/// The pacers shared by all of a client's requests.
+#[derive(Debug)]
+pub(crate) struct Throttles {
+    upload: Option<Arc<Pacer>>,
+    download: Option<Arc<Pacer>>,
+}
+
+impl Throttles {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            upload: config
+                .upload_bandwidth
+                .map(|rate| Arc::new(Pacer::new(rate))),
+            download: config
+                .download_bandwidth
+                .map(|rate| Arc::new(Pacer::new(rate))),
+        }
+    }
+
+    /// The pacers for `req`: the client's, and those of its `Bandwidth`, if it has one.
+    pub(crate) fn for_request(&self, req: &Request) -> Throttle {
+        let mut throttle = Throttle {
+            upload: self.upload.iter().cloned().collect(),
+            download: self.download.iter().cloned().collect(),
+        };
+        if let Some(bandwidth) = req.ext().get::<Bandwidth>() {
+            let pacer = |rate| Arc::new(Pacer::new(rate));
+            throttle.upload.extend(bandwidth.upload.map(pacer));
+            throttle.download.extend(bandwidth.download.map(pacer));
+        }
+        throttle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paces_transfers() {
+        let pacer = Pacer::new(1000);
+        // A second's worth goes at once, and more waits for it to be paid back.
+        assert_eq!(pacer.take(600), Duration::ZERO);
+        assert_eq!(pacer.take(400), Duration::ZERO);
+        let wait = pacer.take(500);
+        assert!(wait > Duration::from_millis(450), "{:?}", wait);
+        assert!(wait <= Duration::from_millis(500), "{:?}", wait);
+
+        let throttle = Throttle {
+            upload: vec![Arc::new(Pacer::new(100)), Arc::new(Pacer::new(10))],
+            download: Vec::new(),
+        };
+        // The slowest pacer decides.
+        let wait = throttle.sent(20);
+        assert!(wait > Duration::from_millis(900), "{:?}", wait);
+        assert_eq!(throttle.received(1 << 20), Duration::ZERO);
+    }
+}
@@ -0,0 +1,189 @@
+//! The overall timeout of requests, and the phases of a request it's attributed to.
+//!
+//! The phase a request is in is recorded by the code handling each of them, including the
+//! connection managers which establish new connections while the pool is checked out. Those are
+//! called by deadpool, with no way of passing the request along, so the request's tracker is
+//! made the current one while the request is polled, like a task-local.
+
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_std::io::{self, BufReader, Read};
+use http_types::Body;
+
+use super::runtime::{self, Sleep};
+use super::timings::Timings;
+use super::ClientError;
+use crate::Response;
+
+/// What a request is doing, to which its timeout is attributed.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Waiting for a pooled connection, or for the client's connection limit.
+    Checkout,
+    /// Resolving the host of a new connection.
+    Resolve,
+    /// Establishing the TCP connection.
+    Connect,
+    /// Performing the TLS handshake.
+    Tls,
+    /// Writing the request and waiting for the response's headers.
+    Request,
+    /// Reading the response body.
+    Body,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Checkout => "waiting for a connection",
+            Phase::Resolve => "resolving the host",
+            Phase::Connect => "connecting",
+            Phase::Tls => "performing the TLS handshake",
+            Phase::Request => "sending the request",
+            Phase::Body => "reading the response body",
+        })
+    }
+}
+
+/// The overall timeout of a request, overriding `Config::timeout` when inserted into its
+/// extensions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestTimeout(pub Duration);
+
+/// Records the phase of a request, and how long it spent in each.
+#[derive(Debug)]
+pub(crate) struct PhaseTracker(Mutex<Progress>);
+
+#[derive(Debug)]
+struct Progress {
+    phase: Phase,
+    entered: Instant,
+    spent: Timings,
+}
+
+impl Progress {
+    /// Enter `phase`, adding the time since the current one was entered to it.
+    fn enter(&mut self, phase: Phase) {
+        let now = Instant::now();
+        let spent = now - self.entered;
+        match self.phase {
+            Phase::Checkout => self.spent.pool_wait += spent,
+            Phase::Resolve => self.spent.dns += spent,
+            Phase::Connect => self.spent.connect += spent,
+            Phase::Tls => self.spent.tls += spent,
+            Phase::Request => self.spent.ttfb += spent,
+            Phase::Body => {}
+        }
+        self.phase = phase;
+        self.entered = now;
+    }
+}
+
+impl PhaseTracker {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self(Mutex::new(Progress {
+            phase: Phase::Checkout,
+            entered: Instant::now(),
+            spent: Timings::default(),
+        })))
+    }
+
+    pub(crate) fn phase(&self) -> Phase {
+        self.0.lock().unwrap().phase
+    }
+
+    /// How long the request spent in each phase so far, the response head having arrived.
+    pub(crate) fn timings(&self) -> Timings {
+        let mut progress = self.0.lock().unwrap();
+        progress.enter(Phase::Body);
+        progress.spent
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<PhaseTracker>>> = const { RefCell::new(None) };
+}
+
+/// Record that the request currently being polled, if it's tracked, entered `phase`.
+pub(crate) fn enter(phase: Phase) {
+    CURRENT.with(|current| {
+        if let Some(tracker) = &*current.borrow() {
+            let mut progress = tracker.0.lock().unwrap();
+            #[cfg(feature = "tracing")]
+            if progress.phase != phase {
+                super::trace::enter(phase);
+            }
+            progress.enter(phase);
+        }
+    });
+}
+
+/// The phase of the request currently being polled, if it's tracked.
+pub(crate) fn current() -> Option<Phase> {
+    CURRENT.with(|current| current.borrow().as_ref().map(|tracker| tracker.phase()))
+}
+
+/// Polls a request's future with its tracker as the current one.
+pub(crate) struct Tracked<F> {
+    tracker: Arc<PhaseTracker>,
+    future: F,
+}
+
+impl<F> Tracked<F> {
+    pub(crate) fn new(tracker: Arc<PhaseTracker>, future: F) -> Self {
+        Self { tracker, future }
+    }
+}
+
+impl<F: Future + Unpin> Future for Tracked<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let previous = CURRENT.with(|current| current.replace(Some(self.tracker.clone())));
+        let poll = Pin::new(&mut self.future).poll(cx);
+        CURRENT.with(|current| current.replace(previous));
+        poll
+    }
+}
+
+/// Make reading the body of `res` fail once `deadline` passes.
+pub(crate) fn limit_body(mut res: Response, deadline: Instant) -> Response {
+    let body = res.take_body();
+    let len = body.len();
+    let mime = body.mime().clone();
+    let reader = DeadlineBody {
+        body,
+        timer: runtime::sleep(deadline.saturating_duration_since(Instant::now())),
+    };
+    let mut body = Body::from_reader(BufReader::new(reader), len);
+    body.set_mime(mime);
+    res.set_body(body);
+    res
+}
+
+/// A response body which fails to be read once its timer elapses.
+struct DeadlineBody {
+    body: Body,
+    timer: Sleep,
+}
+
+impl Read for DeadlineBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.timer.as_mut().poll(cx).is_ready() {
+            let error = ClientError::Timeout { phase: Phase::Body };
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, error)));
+        }
+        Pin::new(&mut self.body).poll_read(cx, buf)
+    }
+}
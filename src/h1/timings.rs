@@ -0,0 +1,103 @@
+//! How long requests spent in each of their phases, as `ResponseExt::timings` tells.
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::io::{self, BufReader, Read};
+use async_std::task::{Context, Poll};
+use http_types::Body;
+
+use crate::Response;
+
+/// How long a request spent in each phase, like curl's `--write-out` timing variables, but each
+/// for its phase only rather than since the start.
+///
+/// A request reusing a pooled connection spends no time resolving, connecting or in the TLS
+/// handshake, and the time of each phase adds up across the redirects followed.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Waiting for a pooled connection, or for the client's connection limit.
+    pub pool_wait: Duration,
+    /// Resolving the host of a new connection.
+    pub dns: Duration,
+    /// Establishing the TCP connection, including any proxy tunnel.
+    pub connect: Duration,
+    /// Performing the TLS handshake.
+    pub tls: Duration,
+    /// Writing the request and waiting for the response head: the time to first byte.
+    pub ttfb: Duration,
+    /// Reading the response body, once it has been read to its end.
+    pub body: Option<Duration>,
+}
+
+impl Timings {
+    /// The time the request took until the response head arrived, and until its body was read if
+    /// it was.
+    pub fn total(&self) -> Duration {
+        self.pool_wait
+            + self.dns
+            + self.connect
+            + self.tls
+            + self.ttfb
+            + self.body.unwrap_or_default()
+    }
+}
+
+/// The timings of a response, updated once its body has been read, as a response extension.
+#[derive(Clone, Debug)]
+pub(crate) struct RecordedTimings(Arc<Mutex<Timings>>);
+
+impl RecordedTimings {
+    pub(crate) fn get(&self) -> Timings {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Attach `timings`, measured up to the arrival of its head, to `res`, and time reading its body.
+pub(crate) fn record(mut res: Response, timings: Timings) -> Response {
+    let timings = RecordedTimings(Arc::new(Mutex::new(timings)));
+    let body = res.take_body();
+    let len = body.len();
+    let mime = body.mime().clone();
+    let reader = TimedBody {
+        body,
+        timings: timings.clone(),
+        len: len.map(|len| len as u64),
+        read: 0,
+        start: Instant::now(),
+    };
+    let mut body = Body::from_reader(BufReader::new(reader), len);
+    body.set_mime(mime);
+    res.set_body(body);
+    res.ext_mut().insert(timings);
+    res
+}
+
+/// A response body recording how long it took to read, once it's read to its end, or its length,
+/// which is as far as a `Body` of a known length reads it.
+struct TimedBody {
+    body: Body,
+    timings: RecordedTimings,
+    len: Option<u64>,
+    read: u64,
+    start: Instant,
+}
+
+impl Read for TimedBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let n = futures_lite::ready!(Pin::new(&mut this.body).poll_read(cx, buf))?;
+        this.read += n as u64;
+        if (n == 0 && !buf.is_empty()) || this.len == Some(this.read) {
+            let mut timings = this.timings.0.lock().unwrap();
+            timings.body.get_or_insert_with(|| this.start.elapsed());
+        }
+        Poll::Ready(Ok(n))
+    }
+}
@@ -0,0 +1,657 @@
+//! TLS support for the h1 client.
+
+use std::fmt::{self, Debug};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_std::io::{self, IoSlice, Read, Write};
+use async_std::task::{Context, Poll};
+use deadpool::managed::{Manager, Object, RecycleError, RecycleResult};
+use http_types::StatusCode;
+use sha2::{Digest, Sha256};
+
+use super::buffers::Buffer;
+use super::connect::Connection;
+use super::events::ConnectionInfo;
+use super::net::TcpStream;
+use super::pool::{ConnectionLimit, CountingManager, Exchange, Origin, PoolCounters, Pooled};
+use super::resolve::DnsCache;
+use super::runtime;
+use super::tcp::{with_connect_timeout, Connector, Stream};
+use super::timeout::{self, Phase};
+use super::upgrade::Upgradable;
+use super::ClientError;
+use crate::{async_trait, Config, Error};
+
+#[cfg(not(feature = "h1_client_rustls"))]
+pub(crate) type TlsStream<S = TcpStream> = async_native_tls::TlsStream<S>;
+
+#[cfg(feature = "h1_client_rustls")]
+pub(crate) type TlsStream<S = TcpStream> = futures_rustls::client::TlsStream<S>;
+
+/// The application protocol negotiated with the server over ALPN.
+///
+/// Inserted into the extensions of responses received over connections which negotiated a
+/// protocol, and can be retrieved with `response.ext().get::<NegotiatedProtocol>()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegotiatedProtocol(Vec<u8>);
+
+impl NegotiatedProtocol {
+    #[cfg(feature = "h2_client")]
+    pub(crate) fn new(protocol: Vec<u8>) -> Self {
+        Self(protocol)
+    }
+
+    /// The protocol identifier, as sent on the wire (e.g. `b"http/1.1"`).
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Get the protocol negotiated over ALPN for an established connection.
+#[cfg(not(feature = "h1_client_rustls"))]
+pub(crate) fn negotiated_protocol<S: Read + Write + Unpin>(
+    _stream: &TlsStream<S>,
+) -> Option<NegotiatedProtocol> {
+    // `async-native-tls` doesn't expose the negotiated protocol, so ALPN is never requested.
+    None
+}
+
+/// Get the protocol negotiated over ALPN for an established connection.
+#[cfg(feature = "h1_client_rustls")]
+pub(crate) fn negotiated_protocol<S: Read + Write + Unpin>(
+    stream: &TlsStream<S>,
+) -> Option<NegotiatedProtocol> {
+    use rustls::Session;
+
+    let (_, session) = stream.get_ref();
+    session
+        .get_alpn_protocol()
+        .map(|protocol| NegotiatedProtocol(protocol.to_vec()))
+}
+
+/// Get the stream a TLS stream is layered on.
+pub(crate) fn inner<S: Read + Write + Unpin>(stream: &TlsStream<S>) -> &S {
+    #[cfg(not(feature = "h1_client_rustls"))]
+    let inner = stream.get_ref();
+    #[cfg(feature = "h1_client_rustls")]
+    let (inner, _) = stream.get_ref();
+    inner
+}
+
+/// The most plaintext a TLS record holds.
+const MAX_RECORD_LEN: usize = 16 * 1024;
+
+/// Write `bufs` to `stream`, a TLS stream. Those write only the first buffer of a vectored
+/// write, and encrypt each write into records of its own, so `bufs` are copied into one pooled
+/// buffer, up to the length of a record, to go out in one record rather than several small ones.
+pub(crate) fn poll_write_coalesced<W: Write>(
+    stream: Pin<&mut W>,
+    cx: &mut Context<'_>,
+    bufs: &[IoSlice<'_>],
+) -> Poll<io::Result<usize>> {
+    let len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+    let mut coalesced = Buffer::new(len.min(MAX_RECORD_LEN));
+    for buf in bufs {
+        let n = buf.len().min(MAX_RECORD_LEN - coalesced.len());
+        coalesced.extend_from_slice(&buf[..n]);
+    }
+    stream.poll_write(cx, &coalesced)
+}
+
+/// A root certificate to trust in addition to the default trust store.
+///
+/// Added to a client with `Config::add_root_certificate`.
+#[derive(Clone)]
+pub struct Certificate {
+    der: Vec<u8>,
+}
+
+impl Certificate {
+    /// Create a certificate from DER encoded bytes.
+    pub fn from_der(der: &[u8]) -> Self {
+        Self { der: der.to_vec() }
+    }
+
+    /// Create a certificate from PEM encoded bytes.
+    ///
+    /// If the PEM contains more than one certificate only the first is used.
+    #[cfg(not(feature = "h1_client_rustls"))]
+    pub fn from_pem(pem: &[u8]) -> Result<Self, Error> {
+        let cert = async_native_tls::Certificate::from_pem(pem)?;
+        Ok(Self {
+            der: cert.to_der()?,
+        })
+    }
+
+    /// Create a certificate from PEM encoded bytes.
+    ///
+    /// If the PEM contains more than one certificate only the first is used.
+    #[cfg(feature = "h1_client_rustls")]
+    pub fn from_pem(pem: &[u8]) -> Result<Self, Error> {
+        let certs = rustls::internal::pemfile::certs(&mut &pem[..]).map_err(|_| {
+            Error::from_str(StatusCode::InternalServerError, "invalid PEM certificate")
+        })?;
+        let cert = certs.into_iter().next().ok_or_else(|| {
+            Error::from_str(
+                StatusCode::InternalServerError,
+                "no certificate found in PEM",
+            )
+        })?;
+        Ok(Self { der: cert.0 })
+    }
+
+    #[cfg(feature = "h3_client")]
+    pub(crate) fn der(&self) -> &[u8] {
+        &self.der
+    }
+}
+
+impl Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Certificate")
+            .field("len", &self.der.len())
+            .finish()
+    }
+}
+
+/// A client identity (certificate chain and private key), presented to servers which
+/// require client authentication (mTLS).
+///
+/// Set on a client with `Config::set_identity`.
+#[derive(Clone)]
+pub struct Identity {
+    #[cfg(not(feature = "h1_client_rustls"))]
+    inner: async_native_tls::Identity,
+    #[cfg(feature = "h1_client_rustls")]
+    inner: (Vec<rustls::Certificate>, rustls::PrivateKey),
+}
+
+impl Identity {
+    /// Create an identity from a PEM encoded certificate chain and a PEM encoded PKCS#8 private key.
+    #[cfg(not(feature = "h1_client_rustls"))]
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let inner = async_native_tls::Identity::from_pkcs8(cert_chain, key)?;
+        Ok(Self { inner })
+    }
+
+    /// Create an identity from a PEM encoded certificate chain and a PEM encoded PKCS#8 or RSA
+    /// private key.
+    #[cfg(feature = "h1_client_rustls")]
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<Self, Error> {
+        use rustls::internal::pemfile;
+
+        let certs = pemfile::certs(&mut &cert_chain[..]).map_err(|_| {
+            Error::from_str(StatusCode::InternalServerError, "invalid PEM certificate")
+        })?;
+        if certs.is_empty() {
+            return Err(Error::from_str(
+                StatusCode::InternalServerError,
+                "no certificate found in PEM",
+            ));
+        }
+
+        let mut keys = pemfile::pkcs8_private_keys(&mut &key[..]).map_err(|_| {
+            Error::from_str(StatusCode::InternalServerError, "invalid PEM private key")
+        })?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut &key[..]).map_err(|_| {
+                Error::from_str(StatusCode::InternalServerError, "invalid PEM private key")
+            })?;
+        }
+        let key = keys.into_iter().next().ok_or_else(|| {
+            Error::from_str(
+                StatusCode::InternalServerError,
+                "no private key found in PEM",
+            )
+        })?;
+
+        Ok(Self {
+            inner: (certs, key),
+        })
+    }
+
+    /// Create an identity from a DER encoded PKCS#12 archive, protected by `password`.
+    ///
+    /// Note: Not supported with `h1_client_rustls`, use `Identity::from_pem` instead.
+    #[cfg(not(feature = "h1_client_rustls"))]
+    pub fn from_pkcs12(der: &[u8], password: &str) -> Result<Self, Error> {
+        let inner = async_native_tls::Identity::from_pkcs12(der, password)?;
+        Ok(Self { inner })
+    }
+}
+
+impl Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity").finish()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TlsConnection {
+    connector: Connector,
+    config: Arc<Config>,
+    limit: Arc<ConnectionLimit>,
+    counters: PoolCounters,
+}
+
+impl TlsConnection {
+    pub(crate) fn new(
+        origin: Origin,
+        config: Arc<Config>,
+        limit: Arc<ConnectionLimit>,
+        dns: Arc<DnsCache>,
+    ) -> Self {
+        Self {
+            counters: PoolCounters::new(&origin, &config),
+            connector: Connector::new(origin, dns, &config),
+            config,
+            limit,
+        }
+    }
+}
+
+pub(crate) struct TlsConnWrapper {
+    // Only `None` once dropped.
+    conn: Option<Object<TlsConnection>>,
+    exchange: Exchange,
+}
+
+impl TlsConnWrapper {
+    pub(crate) fn new(conn: Object<TlsConnection>) -> Self {
+        Self {
+            conn: Some(conn),
+            exchange: Exchange::default(),
+        }
+    }
+
+    /// A handle to record that the response has been read to its end, so the connection can be
+    /// returned to the pool once dropped.
+    pub(crate) fn exchange(&self) -> Exchange {
+        self.exchange.clone()
+    }
+
+    fn stream(&mut self) -> Pin<&mut Pooled<TlsStream<Stream>>> {
+        Pin::new(&mut **self.conn.as_mut().expect("connection used after drop"))
+    }
+}
+
+impl Drop for TlsConnWrapper {
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            conn.mark_used();
+            if conn.contended() || conn.timed_out() || !self.exchange.reusable() {
+                drop(Object::take(conn));
+            } else {
+                conn.notify(|observer, info| observer.recycled(info));
+            }
+        }
+    }
+}
+
+impl Upgradable for TlsConnWrapper {
+    fn upgrade(mut self) -> Box<dyn Connection> {
+        let conn = self.conn.take().expect("connection used after drop");
+        Object::take(conn).upgrade()
+    }
+}
+
+impl Read for TlsConnWrapper {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.stream().poll_read(cx, buf)
+    }
+}
+
+impl Write for TlsConnWrapper {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.stream().poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        poll_write_coalesced(self.stream(), cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_close(cx)
+    }
+}
+
+#[async_trait]
+impl Manager for TlsConnection {
+    type Type = Pooled<TlsStream<Stream>>;
+    type Error = Error;
+
+    async fn create(&self) -> Result<Pooled<TlsStream<Stream>>, Error> {
+        let permit = self.limit.acquire().await?;
+        let host = &self.connector.origin().host;
+        let tls_stream = with_connect_timeout(&self.config, async {
+            let raw_stream = self.connector.connect(&self.config).await?;
+            timeout::enter(Phase::Tls);
+            let handshake = add_tls(host, raw_stream, &self.config, true);
+            match self.config.tls_handshake_timeout {
+                Some(timeout) => runtime::timeout(timeout, handshake)
+                    .await
+                    .map_err(|_| ClientError::TlsHandshakeTimeout.into_error())?,
+                None => handshake.await,
+            }
+        })
+        .await?;
+        #[cfg(not(feature = "h1_client_rustls"))]
+        if let Some(pins) = self.config.certificate_pins.get(host) {
+            let leaf = tls_stream
+                .peer_certificate()?
+                .ok_or_else(|| Error::from_str(StatusCode::BadGateway, "no peer certificate"))?;
+            if !matches_pin(&leaf.to_der()?, pins) {
+                return Err(Error::from_str(
+                    StatusCode::BadGateway,
+                    format!("certificate pin mismatch for {}", host),
+                ));
+            }
+        }
+        match negotiated_protocol(&tls_stream) {
+            None => {}
+            Some(protocol) if protocol.as_bytes() == b"http/1.1" => {}
+            #[cfg(feature = "h2_client")]
+            Some(protocol) if protocol.as_bytes() == b"h2" => {}
+            Some(protocol) => {
+                return Err(Error::from_str(
+                    StatusCode::BadGateway,
+                    format!(
+                        "server negotiated unsupported protocol '{}'",
+                        String::from_utf8_lossy(protocol.as_bytes())
+                    ),
+                ))
+            }
+        }
+        let tcp = inner(&tls_stream);
+        let info = ConnectionInfo::new(
+            self.connector.origin(),
+            tcp.peer_addr().ok(),
+            tcp.local_addr().ok(),
+            negotiated_protocol(&tls_stream),
+        );
+        let lease = self.connector.lease(info.peer_addr);
+        let conn = Pooled::new(tls_stream, info, permit, &self.config).with_lease(lease);
+        self.counters.created(&conn);
+        Ok(conn)
+    }
+
+    async fn recycle(&self, conn: &mut Pooled<TlsStream<Stream>>) -> RecycleResult<Error> {
+        let result = match self.connector.check_peer(inner(conn)).await {
+            Ok(()) => conn.check(&self.config),
+            Err(e) => Err(e),
+        };
+        self.counters
+            .recycle(conn, result)
+            .map_err(|e| RecycleError::Backend(e.into()))
+    }
+}
+
+impl CountingManager for TlsConnection {
+    fn counters(&self) -> &PoolCounters {
+        &self.counters
+    }
+}
+
+/// Wrap `stream` in TLS with `host`, offering the configured ALPN protocols if `alpn` is set.
+#[cfg(not(feature = "h1_client_rustls"))]
+pub(crate) async fn add_tls<S>(
+    host: &str,
+    stream: S,
+    config: &Config,
+    alpn: bool,
+) -> Result<TlsStream<S>, Error>
+where
+    S: Read + Write + Unpin,
+{
+    if alpn && !config.alpn_protocols.is_empty() {
+        return Err(Error::from_str(
+            StatusCode::InternalServerError,
+            "ALPN is only supported with the h1_client_rustls feature",
+        ));
+    }
+
+    let mut connector = async_native_tls::TlsConnector::new()
+        .danger_accept_invalid_certs(config.danger_accept_invalid_certs);
+    for cert in &config.root_certificates {
+        connector =
+            connector.add_root_certificate(async_native_tls::Certificate::from_der(&cert.der)?);
+    }
+    if let Some(identity) = &config.identity {
+        connector = connector.identity(identity.inner.clone());
+    }
+    Ok(connector.connect(host, stream).await?)
+}
+
+/// Wrap `stream` in TLS with `host`, offering the configured ALPN protocols if `alpn` is set.
+#[cfg(feature = "h1_client_rustls")]
+pub(crate) async fn add_tls<S>(
+    host: &str,
+    stream: S,
+    config: &Config,
+    alpn: bool,
+) -> Result<TlsStream<S>, Error>
+where
+    S: Read + Write + Unpin,
+{
+    let tls_config = rustls_config(host, config, alpn)?;
+    let connector = futures_rustls::TlsConnector::from(Arc::new(tls_config));
+    let domain = webpki::DNSNameRef::try_from_ascii_str(host)
+        .map_err(|_| Error::from_str(StatusCode::BadRequest, "invalid DNS name"))?;
+    Ok(connector.connect(domain, stream).await?)
+}
+
+#[cfg(feature = "h1_client_rustls")]
+fn rustls_config(host: &str, config: &Config, alpn: bool) -> Result<rustls::ClientConfig, Error> {
+    let mut tls_config = rustls::ClientConfig::new();
+    tls_config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    for cert in &config.root_certificates {
+        tls_config
+            .root_store
+            .add(&rustls::Certificate(cert.der.clone()))
+            .map_err(|e| {
+                Error::from_str(
+                    StatusCode::InternalServerError,
+                    format!("invalid root certificate: {:?}", e),
+                )
+            })?;
+    }
+
+    if let Some(identity) = &config.identity {
+        let (certs, key) = identity.inner.clone();
+        tls_config
+            .set_single_client_cert(certs, key)
+            .map_err(|e| Error::from_str(StatusCode::InternalServerError, e))?;
+    }
+
+    if alpn {
+        let alpn_protocols = config
+            .alpn_protocols
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        tls_config.set_protocols(&alpn_protocols);
+    }
+
+    if config.danger_accept_invalid_certs {
+        tls_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification));
+    }
+
+    if let Some(pins) = config.certificate_pins.get(host) {
+        let verifier = PinnedCertificateVerifier {
+            inner: if config.danger_accept_invalid_certs {
+                Arc::new(NoCertificateVerification)
+            } else {
+                Arc::new(rustls::WebPKIVerifier::new())
+            },
+            pins: pins.clone(),
+        };
+        tls_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(verifier));
+    }
+
+    Ok(tls_config)
+}
+
+/// A certificate verifier which accepts anything. Only installed when
+/// `Config::danger_accept_invalid_certs` is set.
+#[cfg(feature = "h1_client_rustls")]
+struct NoCertificateVerification;
+
+#[cfg(feature = "h1_client_rustls")]
+impl rustls::ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        _presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef<'_>,
+        _ocsp: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// A certificate verifier which requires the leaf certificate to match one of the configured pins,
+/// in addition to passing the `inner` verifier.
+#[cfg(feature = "h1_client_rustls")]
+struct PinnedCertificateVerifier {
+    inner: Arc<dyn rustls::ServerCertVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+#[cfg(feature = "h1_client_rustls")]
+impl rustls::ServerCertVerifier for PinnedCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        dns_name: webpki::DNSNameRef<'_>,
+        ocsp: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let verified = self
+            .inner
+            .verify_server_cert(roots, presented_certs, dns_name, ocsp)?;
+        let leaf = presented_certs
+            .first()
+            .ok_or(rustls::TLSError::NoCertificatesPresented)?;
+        if matches_pin(&leaf.0, &self.pins) {
+            Ok(verified)
+        } else {
+            Err(rustls::TLSError::General(
+                "certificate pin mismatch".to_string(),
+            ))
+        }
+    }
+}
+
+/// Check whether the SHA-256 hash of a DER encoded certificate's `SubjectPublicKeyInfo` is one of
+/// `pins`.
+fn matches_pin(cert: &[u8], pins: &[[u8; 32]]) -> bool {
+    match subject_public_key_info(cert) {
+        Some(spki) => {
+            let hash = Sha256::digest(spki);
+            pins.iter().any(|pin| pin[..] == hash[..])
+        }
+        None => false,
+    }
+}
+
+/// Extract the DER encoded `SubjectPublicKeyInfo` from a DER encoded X.509 certificate.
+///
+/// ```text
+/// Certificate ::= SEQUENCE { tbsCertificate TBSCertificate, .. }
+/// TBSCertificate ::= SEQUENCE {
+///     version [0] EXPLICIT Version DEFAULT v1,
+///     serialNumber, signature, issuer, validity, subject,
+///     subjectPublicKeyInfo SubjectPublicKeyInfo,
+///     ..
+/// }
+/// ```
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (tag, header, len) = der_header(cert)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, header_tbs, _) = der_header(cert.get(header..header.checked_add(len)?)?)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let mut tbs = cert.get(header + header_tbs..)?;
+
+    if tbs.first() == Some(&VERSION) {
+        tbs = der_skip(tbs)?;
+    }
+    for _ in 0..5 {
+        tbs = der_skip(tbs)?;
+    }
+
+    let (tag, header, len) = der_header(tbs)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    tbs.get(..header.checked_add(len)?)
+}
+
+/// Skip over one DER element, returning the remaining input.
+fn der_skip(input: &[u8]) -> Option<&[u8]> {
+    let (_, header, len) = der_header(input)?;
+    input.get(header.checked_add(len)?..)
+}
+
+/// Parse a DER element header into its tag, header length, and content length, failing if the
+/// lengths overflow, as they may in a malformed certificate.
+fn der_header(input: &[u8]) -> Option<(u8, usize, usize)> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+    if first < 0x80 {
+        return Some((tag, 2, first));
+    }
+
+    let num_bytes = first & 0x7f;
+    if num_bytes == 0 || num_bytes > std::mem::size_of::<usize>() {
+        return None;
+    }
+    let len = input
+        .get(2..2 + num_bytes)?
+        .iter()
+        .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+    Some((tag, 2 + num_bytes, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_overlong_der_lengths() {
+        let mut cert = vec![0x30, 0x88];
+        cert.extend_from_slice(&[0xff; 8]);
+        assert_eq!(der_skip(&cert), None);
+        assert_eq!(subject_public_key_info(&cert), None);
+        assert!(!matches_pin(&cert, &[[0; 32]]));
+    }
+}
@@ -0,0 +1,204 @@
+//! Connections over Unix domain sockets, as configured by `Config::unix_sockets`.
+
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_std::io::{self, IoSlice, Read, Write};
+use async_std::os::unix::net::UnixStream;
+use async_std::task::{Context, Poll};
+use deadpool::managed::{Manager, Object, RecycleError, RecycleResult};
+
+use super::connect::Connection;
+use super::events::ConnectionInfo;
+use super::pool::{ConnectionLimit, CountingManager, Exchange, Origin, PoolCounters, Pooled};
+use super::tcp::with_connect_timeout;
+use super::timeout::{self, Phase};
+use super::upgrade::Upgradable;
+use crate::{async_trait, Config};
+
+/// The socket requests to `origin` are sent over, if any: the one for its host and port, or
+/// else the one for its host.
+pub(crate) fn socket_path<'a>(config: &'a Config, origin: &Origin) -> Option<&'a Path> {
+    let authority = format!("{}:{}", origin.host, origin.port);
+    config
+        .unix_sockets
+        .get(&authority)
+        .or_else(|| config.unix_sockets.get(&origin.host))
+        .map(PathBuf::as_path)
+}
+
+#[derive(Debug)]
+pub(crate) struct UnixConnection {
+    path: PathBuf,
+    origin: Origin,
+    config: Arc<Config>,
+    limit: Arc<ConnectionLimit>,
+    counters: PoolCounters,
+}
+
+impl UnixConnection {
+    pub(crate) fn new(
+        path: PathBuf,
+        origin: &Origin,
+        config: Arc<Config>,
+        limit: Arc<ConnectionLimit>,
+    ) -> Self {
+        Self {
+            path,
+            origin: origin.clone(),
+            counters: PoolCounters::new(origin, &config),
+            config,
+            limit,
+        }
+    }
+
+    /// The path of the socket connections are made to.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+pub(crate) struct UnixConnWrapper {
+    // Only `None` once dropped.
+    conn: Option<Object<UnixConnection>>,
+    exchange: Exchange,
+}
+
+impl UnixConnWrapper {
+    pub(crate) fn new(conn: Object<UnixConnection>) -> Self {
+        Self {
+            conn: Some(conn),
+            exchange: Exchange::default(),
+        }
+    }
+
+    /// A handle to record that the response has been read to its end, so the connection can be
+    /// returned to the pool once dropped.
+    pub(crate) fn exchange(&self) -> Exchange {
+        self.exchange.clone()
+    }
+
+    fn stream(&mut self) -> Pin<&mut Pooled<UnixStream>> {
+        Pin::new(&mut **self.conn.as_mut().expect("connection used after drop"))
+    }
+}
+
+impl Drop for UnixConnWrapper {
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            conn.mark_used();
+            if conn.contended() || conn.timed_out() || !self.exchange.reusable() {
+                drop(Object::take(conn));
+            } else {
+                conn.notify(|observer, info| observer.recycled(info));
+            }
+        }
+    }
+}
+
+impl Upgradable for UnixConnWrapper {
+    fn upgrade(mut self) -> Box<dyn Connection> {
+        let conn = self.conn.take().expect("connection used after drop");
+        Object::take(conn).upgrade()
+    }
+}
+
+impl Read for UnixConnWrapper {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.stream().poll_read(cx, buf)
+    }
+}
+
+impl Write for UnixConnWrapper {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.stream().poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.stream().poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_close(cx)
+    }
+}
+
+#[async_trait]
+impl Manager for UnixConnection {
+    type Type = Pooled<UnixStream>;
+    type Error = io::Error;
+
+    async fn create(&self) -> Result<Pooled<UnixStream>, io::Error> {
+        let permit = self.limit.acquire().await?;
+        let stream = with_connect_timeout(&self.config, async {
+            timeout::enter(Phase::Connect);
+            UnixStream::connect(&self.path).await
+        })
+        .await?;
+        let info = ConnectionInfo::new(&self.origin, None, None, None);
+        let conn = Pooled::new(stream, info, permit, &self.config);
+        self.counters.created(&conn);
+        Ok(conn)
+    }
+
+    async fn recycle(&self, conn: &mut Pooled<UnixStream>) -> RecycleResult<io::Error> {
+        let result = conn.check(&self.config);
+        self.counters
+            .recycle(conn, result)
+            .map_err(RecycleError::Backend)
+    }
+}
+
+impl CountingManager for UnixConnection {
+    fn counters(&self) -> &PoolCounters {
+        &self.counters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_sockets_by_authority() {
+        let config = Config::new()
+            .add_unix_socket("docker", "/var/run/docker.sock")
+            .add_unix_socket("localhost:8080", "/tmp/app.sock")
+            .add_unix_socket("App.Internal", "/tmp/internal.sock");
+        let origin = |host: &str, port| Origin {
+            scheme: "http",
+            host: host.to_string(),
+            port,
+        };
+        assert_eq!(
+            socket_path(&config, &origin("docker", 80)),
+            Some(Path::new("/var/run/docker.sock"))
+        );
+        assert_eq!(
+            socket_path(&config, &origin("localhost", 8080)),
+            Some(Path::new("/tmp/app.sock"))
+        );
+        assert_eq!(socket_path(&config, &origin("localhost", 80)), None);
+        assert_eq!(
+            socket_path(&config, &origin("app.internal", 80)),
+            Some(Path::new("/tmp/internal.sock"))
+        );
+    }
+}
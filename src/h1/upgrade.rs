@@ -0,0 +1,90 @@
+//! Handing the connection of a `101 Switching Protocols` response over to the protocol switched
+//! to.
+
+use std::fmt::{self, Debug};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_std::io::{self, Read, Write};
+
+use super::connect::Connection;
+use super::pool::Pooled;
+
+/// The connection a `101 Switching Protocols` response arrived on, for the protocol switched to
+/// to carry on over, as an extension of the response. Take it with
+/// `ResponseExt::into_upgraded`.
+///
+/// It's out of the pool for good. Reads start with whatever the server sent right after the
+/// response head, and neither reads nor writes time out.
+pub struct Upgraded {
+    // Read past the response head.
+    read: Vec<u8>,
+    pos: usize,
+    stream: Box<dyn Connection>,
+}
+
+impl Upgraded {
+    pub(crate) fn new(read: Vec<u8>, stream: Box<dyn Connection>) -> Self {
+        Self {
+            read,
+            pos: 0,
+            stream,
+        }
+    }
+}
+
+impl Debug for Upgraded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgraded")
+            .field("buffered", &(self.read.len() - self.pos))
+            .finish()
+    }
+}
+
+impl Read for Upgraded {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.pos < this.read.len() {
+            let n = (this.read.len() - this.pos).min(buf.len());
+            buf[..n].copy_from_slice(&this.read[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(n));
+        }
+        Pin::new(&mut this.stream).poll_read(cx, buf)
+    }
+}
+
+impl Write for Upgraded {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+/// A connection a request is sent over, which the protocol a response switches to can take.
+pub(crate) trait Upgradable: Connection + Sized {
+    /// Take the connection out of its pool, and stop timing out reads on it.
+    fn upgrade(self) -> Box<dyn Connection>;
+}
+
+// Connections established for a single request aren't in a pool to begin with.
+impl<S: Connection> Upgradable for Pooled<S> {
+    fn upgrade(self) -> Box<dyn Connection> {
+        Box::new(self.without_read_timeout())
+    }
+}
@@ -0,0 +1,128 @@
+//! The WebSocket opening handshake, per RFC 6455, handing over the connection for a WebSocket
+//! library to speak the protocol on.
+//!
+//! ```no_run
+//! # async fn chat() -> http_types::Result<()> {
+//! use http_client::h1::{websocket, H1Client};
+//! use http_client::Request;
+//!
+//! let client = H1Client::new();
+//! let req = Request::get("wss://example.com/chat");
+//! let (res, stream) = websocket::connect(&client, req).await?;
+//! // Speak the WebSocket protocol over `stream`.
+//! # Ok(()) }
+//! ```
+//!
+//! The handshake is an HTTP/1.1 request, so with `h2_client`, set the ALPN protocols to
+//! `http/1.1` only for `wss` URLs.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use http_types::StatusCode;
+
+use super::Upgraded;
+use crate::{Error, HttpClient, Request, Response};
+
+/// Appended to the key to compute the accept key with, per RFC 6455 section 1.3.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Open a WebSocket connection with `client`, sending `req` as the handshake, and return the
+/// server's `101 Switching Protocols` response and the connection.
+///
+/// `ws` and `wss` URLs are requested over `http` and `https`. The `Upgrade`, `Connection`,
+/// `Sec-WebSocket-Version` and `Sec-WebSocket-Key` headers are set; set others, such as
+/// `Sec-WebSocket-Protocol`, on `req`. Fails with `BadGateway` if the server doesn't switch
+/// protocols, or answers with the wrong `Sec-WebSocket-Accept`.
+pub async fn connect<C>(client: &C, mut req: Request) -> Result<(Response, Upgraded), Error>
+where
+    C: HttpClient + ?Sized,
+{
+    let scheme = match req.url().scheme() {
+        "ws" => Some("http"),
+        "wss" => Some("https"),
+        _ => None,
+    };
+    if let Some(scheme) = scheme {
+        // Both are special schemes, so the URL always accepts the other.
+        let _ = req.url_mut().set_scheme(scheme);
+    }
+
+    let key = key();
+    req.insert_header("Upgrade", "websocket");
+    req.insert_header("Connection", "Upgrade");
+    req.insert_header("Sec-WebSocket-Version", "13");
+    req.insert_header("Sec-WebSocket-Key", key.as_str());
+
+    let mut res = client.send(req).await?;
+    if res.status() != StatusCode::SwitchingProtocols {
+        return Err(handshake_error(format!(
+            "server answered the WebSocket handshake with {}",
+            res.status()
+        )));
+    }
+    if !has_token(&res, "Upgrade", "websocket") || !has_token(&res, "Connection", "upgrade") {
+        return Err(handshake_error("server didn't switch to WebSocket"));
+    }
+    let accept = res
+        .header("Sec-WebSocket-Accept")
+        .map(|values| values.last());
+    if accept.map(|value| value.as_str()) != Some(accept_key(&key).as_str()) {
+        return Err(handshake_error(
+            "server answered with the wrong Sec-WebSocket-Accept",
+        ));
+    }
+    let stream = res
+        .ext_mut()
+        .remove::<Upgraded>()
+        .ok_or_else(|| handshake_error("connection can't be upgraded"))?;
+    Ok((res, stream))
+}
+
+/// A random `Sec-WebSocket-Key`: 16 bytes, base64-encoded.
+fn key() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&random().to_le_bytes());
+    bytes[8..].copy_from_slice(&random().to_le_bytes());
+    base64::encode(bytes)
+}
+
+/// The `Sec-WebSocket-Accept` the server answers `key` with.
+fn accept_key(key: &str) -> String {
+    let mut sha1 = sha1_smol::Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(GUID.as_bytes());
+    base64::encode(sha1.digest().bytes())
+}
+
+/// Whether the comma-separated `header` of `res` lists `token`, ignoring case.
+fn has_token(res: &Response, header: &str, token: &str) -> bool {
+    res.header(header).is_some_and(|values| {
+        values.iter().any(|value| {
+            value
+                .as_str()
+                .split(',')
+                .any(|option| option.trim().eq_ignore_ascii_case(token))
+        })
+    })
+}
+
+fn handshake_error(message: impl Into<String>) -> Error {
+    Error::from_str(StatusCode::BadGateway, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_accept_keys() {
+        // The example from RFC 6455 section 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64::decode(key()).unwrap().len(), 16);
+    }
+}
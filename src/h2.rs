@@ -0,0 +1,291 @@
+//! HTTP/2 support for the h1 client, used for TLS connections which negotiate `h2` over ALPN,
+//! and for plain TCP connections when `Config::http2_prior_knowledge` is set.
+//!
+//! Unlike HTTP/1.1 connections, which are checked out of the pool for exclusive use, a single
+//! HTTP/2 connection multiplexes any number of concurrent requests as separate streams.
+
+use std::convert::TryFrom;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use ::h2::client::{self, SendRequest};
+use ::h2::RecvStream;
+use async_std::future::poll_fn;
+use async_std::io::{self, Read, ReadExt, Write};
+use bytes::Bytes;
+use http_types::headers::{HeaderName, HeaderValue, CONTENT_LENGTH};
+use http_types::{StatusCode, Version};
+
+use crate::h1::runtime;
+use crate::{Body, Error, Request, Response};
+
+/// HTTP/1.1 connection-specific headers, which are forbidden in HTTP/2.
+const CONNECTION_HEADERS: [&str; 6] = [
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A handle to an established HTTP/2 connection. Cheap to clone, and each clone may send
+/// requests concurrently.
+#[derive(Clone, Debug)]
+pub(crate) struct H2Connection {
+    sender: SendRequest<Bytes>,
+}
+
+impl H2Connection {
+    /// Perform the HTTP/2 handshake on `stream`, spawning a task to drive the connection.
+    pub(crate) async fn handshake<S>(stream: S) -> Result<Self, Error>
+    where
+        S: Read + Write + Unpin + Send + 'static,
+    {
+        let (sender, connection) = client::handshake(Compat(stream)).await?;
+        runtime::spawn(async move {
+            if let Err(e) = connection.await {
+                log::debug!("h2 connection closed with error: {}", e);
+            }
+        });
+        Ok(Self { sender })
+    }
+
+    /// Wait until the connection can open a new stream, returning whether it's still usable.
+    pub(crate) async fn ready(&mut self) -> bool {
+        poll_fn(|cx| self.sender.poll_ready(cx)).await.is_ok()
+    }
+
+    /// Send a request as a new stream on this connection.
+    pub(crate) async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        let mut builder = http::Request::builder()
+            .method(http::Method::from_bytes(req.method().as_ref().as_bytes())?)
+            .uri(req.url().as_str())
+            .version(http::Version::HTTP_2);
+        for (name, values) in req.iter() {
+            if CONNECTION_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            for value in values {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+        }
+        let request = builder.body(())?;
+
+        let mut body = req.take_body();
+        let end_of_stream = body.len() == Some(0);
+        let mut sender = self.sender.clone();
+        poll_fn(|cx| sender.poll_ready(cx)).await?;
+        let (response, mut stream) = sender.send_request(request, end_of_stream)?;
+
+        if !end_of_stream {
+            let mut buf = vec![0; 16 * 1024];
+            loop {
+                let len = body.read(&mut buf).await?;
+                if len == 0 {
+                    stream.send_data(Bytes::new(), true)?;
+                    break;
+                }
+
+                let mut chunk = Bytes::copy_from_slice(&buf[..len]);
+                while !chunk.is_empty() {
+                    stream.reserve_capacity(chunk.len());
+                    let capacity =
+                        poll_fn(|cx| stream.poll_capacity(cx))
+                            .await
+                            .ok_or_else(|| {
+                                Error::from_str(StatusCode::BadGateway, "h2 stream closed")
+                            })??;
+                    let part = chunk.split_to(capacity.min(chunk.len()));
+                    stream.send_data(part, false)?;
+                }
+            }
+        }
+
+        let (parts, body) = response.await?.into_parts();
+        let mut res = Response::new(StatusCode::try_from(parts.status.as_u16())?);
+        res.set_version(Some(Version::Http2_0));
+        for (name, value) in &parts.headers {
+            let name = HeaderName::from_str(name.as_str())?;
+            let value = HeaderValue::from_bytes(value.as_bytes().to_vec())?;
+            res.append_header(name, value);
+        }
+
+        let len = res
+            .header(CONTENT_LENGTH)
+            .and_then(|len| len.last().as_str().parse().ok());
+        let body = H2Body {
+            stream: body,
+            chunk: Bytes::new(),
+        };
+        res.set_body(Body::from_reader(io::BufReader::new(body), len));
+        Ok(res)
+    }
+}
+
+/// The body of an HTTP/2 response, read as its data frames arrive.
+struct H2Body {
+    stream: RecvStream,
+    chunk: Bytes,
+}
+
+impl Read for H2Body {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.chunk.is_empty() {
+            match self.stream.poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    // Let the server know it may send more data on this stream.
+                    let _ = self.stream.flow_control().release_capacity(chunk.len());
+                    self.chunk = chunk;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(io::Error::other(e))),
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Poll::Ready(Ok(len))
+    }
+}
+
+/// Adapts a `futures` IO stream to the `tokio` IO traits used by `h2`.
+struct Compat<S>(S);
+
+impl<S: Read + Unpin> tokio::io::AsyncRead for Compat<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<S: Write + Unpin> tokio::io::AsyncWrite for Compat<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h1::{Certificate, H1Client};
+    use crate::{Config, HttpClient};
+    use async_std::net::TcpListener;
+    use async_std::prelude::*;
+    use async_std::task;
+    use http_types::{Method, Url};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[async_std::test]
+    async fn multiplexes_requests() -> http_types::Result<()> {
+        use rustls::internal::pemfile;
+
+        let certs = pemfile::certs(&mut &std::fs::read(fixture("server.pem"))?[..]).unwrap();
+        let key = pemfile::pkcs8_private_keys(&mut &std::fs::read(fixture("server.key"))?[..])
+            .unwrap()
+            .remove(0);
+        let mut server_config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        server_config.set_single_cert(certs, key)?;
+        server_config.set_protocols(&[b"h2".to_vec()]);
+        let acceptor = futures_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+        let listener = TcpListener::bind("localhost:0").await?;
+        let port = listener.local_addr()?.port();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(stream)) = incoming.next().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let tls = acceptor.accept(stream).await.unwrap();
+                task::spawn(async move {
+                    let mut conn = ::h2::server::handshake(Compat(tls)).await.unwrap();
+                    while let Some(Ok((req, mut respond))) = conn.accept().await {
+                        let mut send = respond.send_response(http::Response::new(()), false)?;
+                        send.send_data(Bytes::from(req.uri().path().to_string()), true)?;
+                    }
+                    Ok::<(), ::h2::Error>(())
+                });
+            }
+        });
+
+        let ca = Certificate::from_pem(&std::fs::read(fixture("ca.pem"))?)?;
+        let client = H1Client::with_config(Config::new().add_root_certificate(ca));
+        let get = |path: &str| {
+            let url = Url::parse(&format!("https://localhost:{}{}", port, path)).unwrap();
+            client.send(Request::new(Method::Get, url))
+        };
+
+        let mut res = get("/first").await?;
+        assert_eq!(res.version(), Some(Version::Http2_0));
+        assert_eq!(res.body_string().await?, "/first");
+
+        let (a, b) = get("/a").join(get("/b")).await;
+        assert_eq!(a?.body_string().await?, "/a");
+        assert_eq!(b?.body_string().await?, "/b");
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn prior_knowledge() -> http_types::Result<()> {
+        let listener = TcpListener::bind("localhost:0").await?;
+        let port = listener.local_addr()?.port();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(stream)) = incoming.next().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                task::spawn(async move {
+                    let mut conn = ::h2::server::handshake(Compat(stream)).await.unwrap();
+                    while let Some(Ok((req, mut respond))) = conn.accept().await {
+                        let mut send = respond.send_response(http::Response::new(()), false)?;
+                        send.send_data(Bytes::from(req.uri().path().to_string()), true)?;
+                    }
+                    Ok::<(), ::h2::Error>(())
+                });
+            }
+        });
+
+        let client = H1Client::with_config(Config::new().set_http2_prior_knowledge(true));
+        for path in &["/a", "/b"] {
+            let url = Url::parse(&format!("http://localhost:{}{}", port, path))?;
+            let mut res = client.send(Request::new(Method::Get, url)).await?;
+            assert_eq!(res.version(), Some(Version::Http2_0));
+            assert_eq!(res.body_string().await?, *path);
+        }
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+}
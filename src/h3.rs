@@ -0,0 +1,349 @@
+//! http-client implementation for HTTP/3, using quinn as the QUIC transport.
+//!
+//! This backend is experimental. It only supports `https` URLs, and sends every request over
+//! HTTP/3 without first discovering support through `Alt-Svc`. Each host gets a single QUIC
+//! connection, which multiplexes all requests to it.
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use ::h3::client::SendRequest;
+use async_std::future::poll_fn;
+use async_std::io::{self, ReadExt};
+use bytes1::{Buf, Bytes};
+use dashmap::DashMap;
+use futures_util::TryStreamExt;
+use http_types::headers::{HeaderName, HeaderValue, CONTENT_LENGTH};
+use http_types::{StatusCode, Version};
+
+use super::{async_trait, Body, Config, Error, HttpClient, Request, Response};
+use crate::h1::runtime;
+
+/// HTTP/1.1 connection-specific headers, which are forbidden in HTTP/3.
+const CONNECTION_HEADERS: [&str; 6] = [
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// HTTP/3 Client, sending requests over QUIC.
+pub struct H3Client {
+    connections: ConnectionManager,
+}
+
+impl Debug for H3Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("H3Client")
+            .field("connections", &self.connections.connections.len())
+            .field("config", &self.connections.config)
+            .finish()
+    }
+}
+
+impl Default for H3Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl H3Client {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    /// Create a new instance with the given configuration.
+    ///
+    /// Only `root_certificates` are supported from the TLS options, sending requests fails if any
+    /// of the others are set.
+    pub fn with_config(config: Config) -> Self {
+        Self {
+            connections: ConnectionManager {
+                connections: DashMap::new(),
+                config: Arc::new(config),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl HttpClient for H3Client {
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        if req.url().scheme() != "https" {
+            return Err(Error::from_str(
+                StatusCode::BadRequest,
+                format!("invalid url scheme '{}'", req.url().scheme()),
+            ));
+        }
+
+        let host = req
+            .url()
+            .host_str()
+            .ok_or_else(|| Error::from_str(StatusCode::BadRequest, "missing hostname"))?
+            .to_string();
+        let addrs = req.url().socket_addrs(|| Some(443))?;
+
+        let max_addrs_idx = addrs.len().saturating_sub(1);
+        for (idx, addr) in addrs.into_iter().enumerate() {
+            let has_another_addr = idx != max_addrs_idx;
+
+            let (quic, sender) = match self.connections.get(&host, addr).await {
+                Ok(conn) => conn,
+                Err(_) if has_another_addr => continue,
+                Err(e) => return Err(e),
+            };
+
+            req.set_peer_addr(Some(quic.remote_address()));
+            return send(sender, req).await;
+        }
+
+        Err(Error::from_str(
+            StatusCode::BadRequest,
+            "missing valid address",
+        ))
+    }
+}
+
+/// Keeps one QUIC connection per address, establishing a new one when it's missing or closed.
+struct ConnectionManager {
+    // `SendRequest` isn't `Sync`, so each is kept behind a lock and cloned for every request.
+    connections: DashMap<SocketAddr, (quinn::Connection, Mutex<Sender>)>,
+    config: Arc<Config>,
+}
+
+type Sender = SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+impl ConnectionManager {
+    async fn get(
+        &self,
+        host: &str,
+        addr: SocketAddr,
+    ) -> Result<(quinn::Connection, Sender), Error> {
+        let existing = self.connections.get(&addr).map(|conn| {
+            let (quic, sender) = &*conn;
+            (quic.clone(), sender.lock().unwrap().clone())
+        });
+        if let Some((quic, sender)) = existing {
+            if quic.close_reason().is_none() {
+                return Ok((quic, sender));
+            }
+            self.connections.remove(&addr);
+        }
+
+        let (quic, sender) = self.create(host, addr).await?;
+        self.connections
+            .insert(addr, (quic.clone(), Mutex::new(sender.clone())));
+        Ok((quic, sender))
+    }
+
+    async fn create(
+        &self,
+        host: &str,
+        addr: SocketAddr,
+    ) -> Result<(quinn::Connection, Sender), Error> {
+        let bind_addr = if addr.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let mut endpoint = quinn::Endpoint::client(bind_addr.parse()?)?;
+        endpoint.set_default_client_config(quic_config(&self.config)?);
+
+        let quic = endpoint.connect(addr, host)?.await?;
+        let (mut driver, sender) =
+            ::h3::client::new(h3_quinn::Connection::new(quic.clone())).await?;
+        runtime::spawn(async move {
+            if let Err(e) = poll_fn(|cx| driver.poll_close(cx)).await {
+                log::debug!("h3 connection closed with error: {}", e);
+            }
+        });
+        Ok((quic, sender))
+    }
+}
+
+/// Send a request as a new stream on an HTTP/3 connection.
+async fn send(mut sender: Sender, mut req: Request) -> Result<Response, Error> {
+    let mut builder = http::Request::builder()
+        .method(http::Method::from_bytes(req.method().as_ref().as_bytes())?)
+        .uri(req.url().as_str())
+        .version(http::Version::HTTP_3);
+    for (name, values) in req.iter() {
+        if CONNECTION_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        for value in values {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+    let request = builder.body(())?;
+
+    let mut stream = sender.send_request(request).await?;
+    let mut body = req.take_body();
+    let mut buf = vec![0; 16 * 1024];
+    loop {
+        let len = body.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        stream
+            .send_data(Bytes::copy_from_slice(&buf[..len]))
+            .await?;
+    }
+    stream.finish().await?;
+
+    let response = stream.recv_response().await?;
+    let mut res = Response::new(StatusCode::try_from(response.status().as_u16())?);
+    res.set_version(Some(Version::Http3_0));
+    for (name, value) in response.headers() {
+        let name = HeaderName::from_str(name.as_str())?;
+        let value = HeaderValue::from_bytes(value.as_bytes().to_vec())?;
+        res.append_header(name, value);
+    }
+
+    let len = res
+        .header(CONTENT_LENGTH)
+        .and_then(|len| len.last().as_str().parse().ok());
+
+    // The response stream isn't `Sync` as required by `Body`, so its data is forwarded from a
+    // separate task, which stops once the body is dropped.
+    let (chunks, body) = async_std::channel::bounded(1);
+    runtime::spawn(async move {
+        loop {
+            let chunk = match stream.recv_data().await {
+                Ok(Some(mut data)) => Ok(data.copy_to_bytes(data.remaining())),
+                Ok(None) => break,
+                Err(e) => Err(io::Error::other(e)),
+            };
+            let failed = chunk.is_err();
+            if chunks.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    res.set_body(Body::from_reader(
+        io::BufReader::new(body.into_async_read()),
+        len,
+    ));
+    Ok(res)
+}
+
+/// Build the QUIC client configuration, trusting the webpki roots and `config.root_certificates`.
+fn quic_config(config: &Config) -> Result<quinn::ClientConfig, Error> {
+    if config.danger_accept_invalid_certs
+        || config.identity.is_some()
+        || !config.certificate_pins.is_empty()
+    {
+        return Err(Error::from_str(
+            StatusCode::InternalServerError,
+            "only root certificates are supported with the h3_client feature",
+        ));
+    }
+
+    let mut roots = rustls020::RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots022::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+        rustls020::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    for cert in &config.root_certificates {
+        roots
+            .add(&rustls020::Certificate(cert.der().to_vec()))
+            .map_err(|e| {
+                Error::from_str(
+                    StatusCode::InternalServerError,
+                    format!("invalid root certificate: {:?}", e),
+                )
+            })?;
+    }
+
+    let mut tls_config = rustls020::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+    Ok(quinn::ClientConfig::new(Arc::new(tls_config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h1::Certificate;
+    use async_std::task;
+    use http_types::{Method, Url};
+    use std::net::ToSocketAddrs;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[async_std::test]
+    async fn get_and_post() -> http_types::Result<()> {
+        use rustls::internal::pemfile;
+
+        let certs = pemfile::certs(&mut &std::fs::read(fixture("server.pem"))?[..])
+            .unwrap()
+            .into_iter()
+            .map(|cert| rustls020::Certificate(cert.0))
+            .collect();
+        let key = pemfile::pkcs8_private_keys(&mut &std::fs::read(fixture("server.key"))?[..])
+            .unwrap()
+            .remove(0);
+        let mut tls_config = rustls020::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, rustls020::PrivateKey(key.0))?;
+        tls_config.alpn_protocols = vec![b"h3".to_vec()];
+        let server_config = quinn::ServerConfig::with_crypto(Arc::new(tls_config));
+
+        let addr = ("localhost", 0).to_socket_addrs()?.next().unwrap();
+        let endpoint = quinn::Endpoint::server(server_config, addr)?;
+        let port = endpoint.local_addr()?.port();
+
+        task::spawn(async move {
+            while let Some(connecting) = endpoint.accept().await {
+                task::spawn(async move {
+                    let conn = h3_quinn::Connection::new(connecting.await.unwrap());
+                    let mut conn = ::h3::server::Connection::<_, Bytes>::new(conn)
+                        .await
+                        .unwrap();
+                    while let Ok(Some((req, mut stream))) = conn.accept().await {
+                        // Respond with the request path followed by the request body.
+                        let mut body = req.uri().path().as_bytes().to_vec();
+                        while let Some(mut data) = stream.recv_data().await? {
+                            body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                        }
+                        stream.send_response(http::Response::new(())).await?;
+                        stream.send_data(Bytes::from(body)).await?;
+                        stream.finish().await?;
+                    }
+                    Ok::<(), ::h3::Error>(())
+                });
+            }
+        });
+
+        let ca = Certificate::from_pem(&std::fs::read(fixture("ca.pem"))?)?;
+        let client = H3Client::with_config(Config::new().add_root_certificate(ca));
+        let url = Url::parse(&format!("https://localhost:{}/echo", port))?;
+
+        let mut res = client.send(Request::new(Method::Get, url.clone())).await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(res.version(), Some(Version::Http3_0));
+        assert_eq!(res.body_string().await?, "/echo");
+
+        let mut req = Request::new(Method::Post, url);
+        req.set_body(" hello");
+        let mut res = client.send(req).await?;
+        assert_eq!(res.body_string().await?, "/echo hello");
+
+        assert_eq!(client.connections.connections.len(), 1);
+        Ok(())
+    }
+}
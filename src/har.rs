@@ -0,0 +1,405 @@
+//! Capturing the exchanges of a session to an HTTP Archive (HAR) file, to inspect them in the
+//! network panel of browser devtools, or any other HAR viewer.
+//!
+//! ```no_run
+//! # async fn fetch() -> http_types::Result<()> {
+//! use http_client::har::HarRecorder;
+//! use http_client::{Client, HttpClient, Request};
+//!
+//! let har = HarRecorder::new().redact_header("Authorization");
+//! let client = Client::builder().with(har.clone()).build();
+//! let res = client.send(Request::get("https://api.example.com/users")).await?;
+//! har.save("session.har")?;
+//! # Ok(()) }
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use http_types::headers::{HeaderName, Headers};
+use serde_json::{json, Value};
+
+use crate::middleware::{read_body, Middleware, Next};
+use crate::{async_trait, Error, Request, Response};
+
+/// What the recorded value of redacted headers is replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+/// Middleware capturing each exchange, with its timings, as an entry of an HTTP Archive.
+///
+/// Bodies are read whole, to capture them. Requests which fail are captured too, with a status
+/// of 0 and their error, as browsers do. With the h1 client, the time spent waiting for a
+/// connection, resolving, connecting and in the TLS handshake is told apart, as
+/// `ResponseExt::timings` tells it; otherwise it's all counted as waiting for the response.
+///
+/// Clones share the entries captured, so one can be handed to a `ClientBuilder` and the other
+/// kept to save them.
+#[derive(Clone, Debug, Default)]
+pub struct HarRecorder {
+    entries: Arc<Mutex<Vec<Value>>>,
+    redacted: Vec<HeaderName>,
+}
+
+impl HarRecorder {
+    /// Capture exchanges, without any yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capture the values of `name` headers, on requests and responses, as `[REDACTED]`, e.g.
+    /// to keep credentials out of archives. Redacted `Cookie` and `Set-Cookie` headers leave the
+    /// cookies out too.
+    pub fn redact_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.redacted.push(name.into());
+        self
+    }
+
+    /// The number of exchanges captured.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether no exchange has been captured yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget the exchanges captured so far.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The archive of the exchanges captured so far, in the HAR 1.2 format.
+    pub fn to_har(&self) -> Value {
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": self.entries.lock().unwrap().clone(),
+            }
+        })
+    }
+
+    /// Write the archive of the exchanges captured so far to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.to_har())?;
+        fs::write(path, json)
+    }
+
+    fn headers(&self, headers: &Headers) -> Value {
+        let mut captured = Vec::new();
+        for (name, values) in headers.iter() {
+            let redacted = self.redacted.contains(name);
+            for value in values {
+                let value = if redacted { REDACTED } else { value.as_str() };
+                captured.push(json!({ "name": name.as_str(), "value": value }));
+            }
+        }
+        Value::Array(captured)
+    }
+
+    /// The cookies of the `name` headers, `Cookie` or `Set-Cookie`, unless they're redacted.
+    fn cookies(&self, headers: &Headers, name: &str) -> Value {
+        let name = HeaderName::from(name);
+        if self.redacted.contains(&name) {
+            return Value::Array(Vec::new());
+        }
+        let set_cookie = name == "set-cookie";
+        let values = headers.get(&name).into_iter().flatten();
+        values
+            .flat_map(|value| {
+                let value = value.as_str();
+                // A `Set-Cookie` header sets one cookie, followed by its attributes.
+                let pairs: Vec<&str> = if set_cookie {
+                    value.split(';').take(1).collect()
+                } else {
+                    value.split(';').collect()
+                };
+                pairs.into_iter().filter_map(|pair| {
+                    let (name, value) = pair.trim().split_once('=')?;
+                    Some(json!({ "name": name, "value": value }))
+                })
+            })
+            .collect()
+    }
+
+    fn request(&self, req: &Request, body: &[u8]) -> Value {
+        let query: Vec<Value> = req
+            .url()
+            .query_pairs()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        let mut captured = json!({
+            "method": req.method().to_string(),
+            "url": req.url().as_str(),
+            "httpVersion": version(req.version()),
+            "cookies": self.cookies(req.as_ref(), "cookie"),
+            "headers": self.headers(req.as_ref()),
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": body.len(),
+        });
+        if !body.is_empty() {
+            let mut post_data = content(body);
+            post_data["mimeType"] = Value::from(mime(req.as_ref()));
+            captured["postData"] = post_data;
+        }
+        captured
+    }
+
+    fn response(&self, res: &Response, body: &[u8]) -> Value {
+        let mut content = content(body);
+        content["size"] = Value::from(body.len());
+        content["mimeType"] = Value::from(mime(res.as_ref()));
+        json!({
+            "status": u16::from(res.status()),
+            "statusText": res.status().canonical_reason(),
+            "httpVersion": version(res.version()),
+            "cookies": self.cookies(res.as_ref(), "set-cookie"),
+            "headers": self.headers(res.as_ref()),
+            "content": content,
+            "redirectURL": res.header("location").map(|values| values.as_str()).unwrap_or(""),
+            "headersSize": -1,
+            "bodySize": body.len(),
+        })
+    }
+
+    fn capture(&self, started: SystemTime, request: Value, response: Value, timings: Value) {
+        let time: f64 = ["blocked", "dns", "connect", "send", "wait", "receive"]
+            .iter()
+            .filter_map(|phase| timings[phase].as_f64())
+            .filter(|ms| *ms > 0.0)
+            .sum();
+        self.entries.lock().unwrap().push(json!({
+            "startedDateTime": iso8601(started),
+            "time": time,
+            "request": request,
+            "response": response,
+            "cache": {},
+            "timings": timings,
+        }));
+    }
+}
+
+#[async_trait]
+impl Middleware for HarRecorder {
+    async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+        let started = SystemTime::now();
+        let (body, request_body) = read_body(req.take_body()).await?;
+        req.set_body(body);
+        let request = self.request(&req, &request_body);
+
+        let start = Instant::now();
+        let mut res = match next.run(req).await {
+            Ok(res) => res,
+            Err(e) => {
+                let response = json!({
+                    "status": 0,
+                    "statusText": "",
+                    "httpVersion": "",
+                    "cookies": [],
+                    "headers": [],
+                    "content": { "size": 0, "mimeType": "" },
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": -1,
+                    "_error": e.to_string(),
+                });
+                self.capture(started, request, response, timings(start.elapsed(), None));
+                return Err(e);
+            }
+        };
+        let head = start.elapsed();
+        let start = Instant::now();
+        let (body, response_body) = read_body(res.take_body()).await?;
+        res.set_body(body);
+        let response = self.response(&res, &response_body);
+
+        #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+        let timings = match crate::ResponseExt::timings(&res) {
+            Some(timings) => h1_timings(&timings, start.elapsed()),
+            None => self::timings(head, Some(start.elapsed())),
+        };
+        #[cfg(not(any(feature = "h1_client", feature = "h1_client_rustls")))]
+        let timings = timings(head, Some(start.elapsed()));
+        self.capture(started, request, response, timings);
+        Ok(res)
+    }
+}
+
+/// The `content` or `postData` of `body`: its text, or its bytes in base64 if it isn't UTF-8.
+fn content(body: &[u8]) -> Value {
+    match std::str::from_utf8(body) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "text": base64::encode(body), "encoding": "base64" }),
+    }
+}
+
+fn mime(headers: &Headers) -> &str {
+    headers
+        .get("content-type")
+        .map(|values| values.as_str())
+        .unwrap_or("")
+}
+
+fn version(version: Option<http_types::Version>) -> String {
+    version.map_or_else(|| "HTTP/1.1".to_string(), |version| version.to_string())
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The timings of an exchange only known to have waited `head` for the response head, and
+/// `receive` reading the body, if it got that far. The phases not told apart are all `-1`.
+fn timings(head: Duration, receive: Option<Duration>) -> Value {
+    json!({
+        "blocked": -1,
+        "dns": -1,
+        "connect": -1,
+        "ssl": -1,
+        "send": 0,
+        "wait": ms(head),
+        "receive": receive.map_or(-1.0, ms),
+    })
+}
+
+/// The timings of an exchange sent by the h1 client. Phases of a new connection which a request
+/// reusing one didn't go through are `-1`, and `connect` includes `ssl`, as HAR has it.
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+fn h1_timings(timings: &crate::h1::Timings, receive: Duration) -> Value {
+    let phase = |duration: Duration| {
+        if duration.is_zero() {
+            -1.0
+        } else {
+            ms(duration)
+        }
+    };
+    json!({
+        "blocked": ms(timings.pool_wait),
+        "dns": phase(timings.dns),
+        "connect": phase(timings.connect + timings.tls),
+        "ssl": phase(timings.tls),
+        "send": 0,
+        "wait": ms(timings.ttfb),
+        "receive": ms(timings.body.unwrap_or(receive)),
+    })
+}
+
+/// `time` as an ISO 8601 date and time in UTC, with milliseconds.
+fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+    // Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Client, HttpClient};
+    use http_types::StatusCode;
+
+    /// Answers requests to `/fail` with an error, and others with their body, and a cookie.
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl HttpClient for Echo {
+        async fn send(&self, mut req: Request) -> Result<Response, Error> {
+            if req.url().path() == "/fail" {
+                return Err(Error::from_str(StatusCode::BadGateway, "connection reset"));
+            }
+            let mut res = Response::new(StatusCode::Created);
+            res.insert_header("Set-Cookie", "session=abc; HttpOnly");
+            res.set_body(req.take_body().into_bytes().await?);
+            Ok(res)
+        }
+    }
+
+    #[async_std::test]
+    async fn captures_exchanges() -> Result<(), Error> {
+        let har = HarRecorder::new().redact_header("Authorization");
+        let client = Client::builder().with(har.clone()).build_with(Echo);
+
+        let mut req = Request::post("http://example.test/items?draft=true");
+        req.insert_header("Authorization", "Bearer secret");
+        req.insert_header("Cookie", "a=1; b=2");
+        req.set_body(vec![0xff, 0x00]);
+        let mut res = client.send(req).await?;
+        assert_eq!(res.body_bytes().await?, [0xff, 0x00]);
+        assert!(client
+            .send(Request::get("http://example.test/fail"))
+            .await
+            .is_err());
+        assert_eq!(har.len(), 2);
+
+        let log = har.to_har();
+        assert_eq!(log["log"]["version"], "1.2");
+        let entry = &log["log"]["entries"][0];
+        let request = &entry["request"];
+        assert_eq!(request["method"], "POST");
+        assert_eq!(
+            request["queryString"],
+            json!([{ "name": "draft", "value": "true" }])
+        );
+        assert_eq!(
+            request["cookies"],
+            json!([{ "name": "a", "value": "1" }, { "name": "b", "value": "2" }])
+        );
+        assert!(!log.to_string().contains("secret"), "{}", log);
+        assert_eq!(request["postData"]["text"], "/wA=");
+        let response = &entry["response"];
+        assert_eq!(response["status"], 201);
+        assert_eq!(response["statusText"], "Created");
+        assert_eq!(
+            response["cookies"],
+            json!([{ "name": "session", "value": "abc" }])
+        );
+        assert_eq!(response["content"]["encoding"], "base64");
+        assert_eq!(response["content"]["size"], 2);
+        assert_eq!(entry["timings"]["dns"], -1);
+        assert!(entry["timings"]["wait"].as_f64().unwrap() >= 0.0);
+
+        let failed = &log["log"]["entries"][1]["response"];
+        assert_eq!(failed["status"], 0);
+        assert_eq!(failed["_error"], "connection reset");
+
+        har.clear();
+        assert!(har.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn formats_dates() {
+        let time = UNIX_EPOCH + Duration::from_millis(951_782_400_123);
+        assert_eq!(iso8601(time), "2000-02-29T00:00:00.123Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_735_689_599);
+        assert_eq!(iso8601(time), "2024-12-31T23:59:59.000Z");
+    }
+}
@@ -0,0 +1,76 @@
+//! Sending `http` crate requests, for code built on the types of `hyper` and `tower`.
+
+use std::convert::TryFrom;
+
+use http_types::StatusCode;
+
+use crate::{async_trait, Body, Error, HttpClient, Request, Response};
+
+/// Send requests of the `http` crate with any `HttpClient`, and get its responses back.
+///
+/// `Request` and `Response` convert to and from them too, with `TryFrom` and `From`.
+///
+/// ```no_run
+/// # async fn fetch() -> http_types::Result<()> {
+/// use http_client::h1::H1Client;
+/// use http_client::HttpClientExt;
+///
+/// let req = http::Request::get("https://example.com/").body("")?;
+/// let res = H1Client::new().send_http(req).await?;
+/// assert!(res.status().is_success());
+/// # Ok(()) }
+/// ```
+#[async_trait]
+pub trait HttpClientExt: HttpClient {
+    /// Send `req`, whose URI must be absolute, and return the response with its body streamed
+    /// as it arrives. Fails with a `400 Bad Request` error if the URI isn't a valid URL.
+    async fn send_http<B>(&self, req: http::Request<B>) -> Result<http::Response<Body>, Error>
+    where
+        B: Into<Body> + Send + 'static,
+    {
+        let req = req.map(Into::into);
+        let req = Request::try_from(req).map_err(|e| Error::new(StatusCode::BadRequest, e))?;
+        let res: Response = self.send(req).await?;
+        Ok(res.into())
+    }
+}
+
+impl<C: HttpClient + ?Sized> HttpClientExt for C {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every request with its method and URL, and echoes its `X-Echo` header.
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl HttpClient for Echo {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let mut res = Response::new(StatusCode::Created);
+            if let Some(values) = req.header("X-Echo") {
+                res.insert_header("X-Echo", values);
+            }
+            res.set_body(format!("{} {}", req.method(), req.url()));
+            Ok(res)
+        }
+    }
+
+    #[async_std::test]
+    async fn sends_http_requests() -> Result<(), Error> {
+        let req = http::Request::post("http://example.test/items?id=1")
+            .header("X-Echo", "hello")
+            .body("body")?;
+        let res = Echo.send_http(req).await?;
+        assert_eq!(res.status(), http::StatusCode::CREATED);
+        assert_eq!(res.headers()["x-echo"], "hello");
+        let body = res.into_body().into_string().await?;
+        assert_eq!(body, "POST http://example.test/items?id=1");
+
+        let req = http::Request::get("/relative").body("")?;
+        let err = Echo.send_http(req).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BadRequest);
+        Ok(())
+    }
+}
@@ -30,10 +30,61 @@ pub mod native;
 #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
 pub mod h1;
 
+#[cfg(feature = "h2_client")]
+mod h2;
+
+#[cfg_attr(feature = "docs", doc(cfg(h3_client)))]
+#[cfg(feature = "h3_client")]
+pub mod h3;
+
 #[cfg_attr(feature = "docs", doc(cfg(hyper_client)))]
 #[cfg(feature = "hyper_client")]
 pub mod hyper;
 
+#[cfg_attr(feature = "docs", doc(cfg(h1_client)))]
+#[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+pub mod blocking;
+
+mod auth;
+pub use auth::Credentials;
+
+mod client;
+pub use client::{Client, ClientBuilder};
+
+mod conditional;
+pub use conditional::Conditional;
+
+mod config;
+pub use config::Config;
+
+#[cfg_attr(feature = "docs", doc(cfg(http_compat)))]
+#[cfg(feature = "http_compat")]
+mod http_compat;
+#[cfg(feature = "http_compat")]
+pub use http_compat::HttpClientExt;
+
+#[cfg(feature = "tower")]
+mod tower;
+
+pub mod middleware;
+pub use middleware::{Middleware, Next};
+
+mod request_builder;
+pub use request_builder::RequestBuilder;
+
+mod response_ext;
+pub use response_ext::{ResponseExt, ResponseUrl, StatusError};
+
+pub mod testing;
+
+#[cfg_attr(feature = "docs", doc(cfg(vcr)))]
+#[cfg(feature = "vcr")]
+pub mod vcr;
+
+#[cfg_attr(feature = "docs", doc(cfg(har)))]
+#[cfg(feature = "har")]
+pub mod har;
+
 /// An HTTP Request type with a streaming body.
 pub type Request = http_types::Request;
 
@@ -73,3 +124,12 @@ impl HttpClient for Box<dyn HttpClient> {
         self.as_ref().send(req).await
     }
 }
+
+/// A client shared behind an `Arc`, e.g. by `surf`, which keeps its backend in an
+/// `Arc<dyn HttpClient>`, and other code sending requests with the same connection pools.
+#[async_trait]
+impl<C: HttpClient + ?Sized> HttpClient for std::sync::Arc<C> {
+    async fn send(&self, req: Request) -> Result<Response, Error> {
+        self.as_ref().send(req).await
+    }
+}
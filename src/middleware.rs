@@ -0,0 +1,980 @@
+//! Middleware wrapping each request a `Client` sends, to add behavior around the send path.
+//!
+//! ```no_run
+//! # async fn fetch() -> http_types::Result<()> {
+//! use http_client::middleware::{InjectHeader, Logger};
+//! use http_client::{Client, HttpClient, Request};
+//!
+//! let client = Client::builder()
+//!     .with(Logger::new())
+//!     .with(InjectHeader::new("X-Request-Id", || Some(next_request_id())))
+//!     .build();
+//! let res = client.send(Request::get("https://example.com/")).await?;
+//! # Ok(()) }
+//! # fn next_request_id() -> String { String::new() }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::Instant;
+
+use futures_lite::{future, io, AsyncReadExt};
+use http_types::headers::{HeaderName, Headers};
+use http_types::{Method, Mime, StatusCode, Version};
+
+use crate::{async_trait, Body, Error, HttpClient, Request, Response};
+
+/// Handles a request on its way to the client which sends it, e.g. to change it, look at the
+/// response, try again, or answer it without sending it at all.
+///
+/// Add them with `ClientBuilder::with`. The middleware added first handles requests first,
+/// and responses last.
+#[async_trait]
+pub trait Middleware: Debug + Send + Sync + 'static {
+    /// Handle `req`, passing it on to the rest of the chain with `next`, if at all.
+    async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error>;
+}
+
+/// The rest of the middleware chain, ending with the client which sends the request.
+#[derive(Clone, Copy, Debug)]
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    client: &'a dyn HttpClient,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middleware: &'a [Arc<dyn Middleware>], client: &'a dyn HttpClient) -> Self {
+        Self { middleware, client }
+    }
+
+    /// Pass `req` on to the next middleware, or send it if there are no more. A `Next` can be
+    /// run more than once, e.g. to retry.
+    pub async fn run(self, req: Request) -> Result<Response, Error> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(req, Next::new(rest, self.client)).await,
+            None => self.client.send(req).await,
+        }
+    }
+}
+
+/// Logs each request with its status and how long it took until the response head arrived,
+/// except on `wasm32`, and failed requests with their error, as a `warn`.
+///
+/// In verbose mode, the head of each request and response is logged too, like `curl -v` does,
+/// and optionally the start of their bodies, with the values of the `Authorization`,
+/// `Proxy-Authorization`, `Cookie` and `Set-Cookie` headers, and of any others configured,
+/// redacted. Requests are logged as they reach the logger, so without the headers added by
+/// middleware after it, or by the client itself, such as `Host`.
+#[derive(Clone, Debug)]
+pub struct Logger {
+    level: log::Level,
+    verbose: bool,
+    body_limit: Option<usize>,
+    redact: Vec<HeaderName>,
+}
+
+impl Logger {
+    /// Log requests at the `info` level.
+    pub fn new() -> Self {
+        Self {
+            level: log::Level::Info,
+            verbose: false,
+            body_limit: None,
+            redact: [
+                "authorization",
+                "proxy-authorization",
+                "cookie",
+                "set-cookie",
+            ]
+            .iter()
+            .map(|name| HeaderName::from(*name))
+            .collect(),
+        }
+    }
+
+    /// Log requests which didn't fail at `level`.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Log the heads of requests and responses too, at the same level.
+    pub fn verbose(mut self) -> Self {
+        self.verbose = true;
+        self
+    }
+
+    /// Log the heads of requests and responses, and up to `limit` bytes of their bodies.
+    ///
+    /// Only the start of each body is read ahead to be logged, when logging at the level is
+    /// enabled, then sent or returned ahead of the rest, which is streamed as usual.
+    pub fn log_bodies(mut self, limit: usize) -> Self {
+        self.verbose = true;
+        self.body_limit = Some(limit);
+        self
+    }
+
+    /// Redact the values of `name` headers too.
+    pub fn redact_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.redact.push(name.into());
+        self
+    }
+
+    /// The lines logging `headers`, each starting with `prefix`, with their values redacted if
+    /// configured.
+    fn headers(&self, prefix: char, headers: &Headers) -> String {
+        let mut lines = String::new();
+        for (name, values) in headers.iter() {
+            for value in values.iter() {
+                let value = if self.redact.contains(name) {
+                    "[REDACTED]"
+                } else {
+                    value.as_str()
+                };
+                lines.push_str(&format!("\n{} {}: {}", prefix, name, value));
+            }
+        }
+        lines
+    }
+
+    /// The line logging the `start` of a body of length `len`, if bodies are logged: its first
+    /// bytes up to the limit.
+    fn body(&self, prefix: char, start: &[u8], len: Option<usize>) -> String {
+        match self.body_limit {
+            Some(limit) if !start.is_empty() => {
+                let shown = &start[..start.len().min(limit)];
+                let rest = match len {
+                    Some(len) if len > shown.len() => {
+                        format!("... ({} more bytes)", len - shown.len())
+                    }
+                    None if start.len() > shown.len() => "...".to_string(),
+                    _ => String::new(),
+                };
+                let shown = String::from_utf8_lossy(shown);
+                format!("\n{}\n{} {}{}", prefix, prefix, shown, rest)
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Read the start of `body` to log, one byte more than the limit so it's known whether
+    /// there's more, returning a body of it followed by the rest, with the same length and mime.
+    async fn peek(&self, mut body: Body) -> Result<(Body, Vec<u8>), Error> {
+        let limit = self.body_limit.unwrap_or_default();
+        let (len, mime) = (body.len(), body.mime().clone());
+        let mut start = Vec::new();
+        (&mut body)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut start)
+            .await?;
+        let mut peeked = Body::from_reader(io::Cursor::new(start.clone()).chain(body), len);
+        peeked.set_mime(mime);
+        Ok((peeked, start))
+    }
+
+    /// Log the head and body of `req`, reading the start of the body if it's logged.
+    async fn log_request(&self, req: &mut Request) -> Result<(), Error> {
+        let mut start = Vec::new();
+        let len = req.len();
+        if self.body_limit.is_some() {
+            let (peeked, bytes) = self.peek(req.take_body()).await?;
+            req.set_body(peeked);
+            start = bytes;
+        }
+        log::log!(
+            self.level,
+            "> {} {}{}{}",
+            req.method(),
+            req.url(),
+            self.headers('>', req.as_ref()),
+            self.body('>', &start, len)
+        );
+        Ok(())
+    }
+
+    /// Log the head and body of `res`, reading the start of the body if it's logged.
+    async fn log_response(&self, res: &mut Response) -> Result<(), Error> {
+        let mut start = Vec::new();
+        let len = res.len();
+        if self.body_limit.is_some() {
+            let (peeked, bytes) = self.peek(res.take_body()).await?;
+            res.set_body(peeked);
+            start = bytes;
+        }
+        log::log!(
+            self.level,
+            "< {}{}{}",
+            res.status(),
+            self.headers('<', res.as_ref()),
+            self.body('<', &start, len)
+        );
+        Ok(())
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read `body` into memory, returning a body of what was read, with the same mime, and the bytes.
+pub(crate) async fn read_body(body: Body) -> Result<(Body, Vec<u8>), Error> {
+    let mime = body.mime().clone();
+    let bytes = body.into_bytes().await?;
+    let mut read = Body::from(bytes.clone());
+    read.set_mime(mime);
+    Ok((read, bytes))
+}
+
+#[async_trait]
+impl Middleware for Logger {
+    async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+        let method = req.method();
+        let url = req.url().clone();
+        let verbose = self.verbose && log::log_enabled!(self.level);
+        if verbose {
+            self.log_request(&mut req).await?;
+        }
+        let start = started();
+        let mut result = next.run(req).await;
+        match &mut result {
+            Ok(res) => {
+                log::log!(
+                    self.level,
+                    "{} {} -> {}{}",
+                    method,
+                    url,
+                    res.status(),
+                    took(start)
+                );
+                if verbose {
+                    self.log_response(res).await?;
+                }
+            }
+            Err(e) => log::warn!("{} {} failed{}: {}", method, url, took(start), e),
+        }
+        result
+    }
+}
+
+/// When a request was sent, where there's a clock to tell, as `Instant::now` panics on
+/// `wasm32-unknown-unknown`.
+fn started() -> Option<Instant> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Some(Instant::now());
+    #[cfg(target_arch = "wasm32")]
+    return None;
+}
+
+/// How long a request `started` took, as logged, if it's known.
+fn took(started: Option<Instant>) -> String {
+    started
+        .map(|started| format!(" in {:?}", started.elapsed()))
+        .unwrap_or_default()
+}
+
+/// Sets a header to a value computed for each request, such as a token which is refreshed
+/// from time to time, unless the request sets the header itself.
+#[derive(Clone)]
+pub struct InjectHeader {
+    name: HeaderName,
+    value: Arc<dyn Fn() -> Option<String> + Send + Sync>,
+}
+
+impl InjectHeader {
+    /// Set the `name` header of each request to what `value` returns, leaving it unset when it
+    /// returns `None`.
+    ///
+    /// # Panics
+    ///
+    /// When sending a request, if `value` returns a value which isn't valid in a header.
+    pub fn new(
+        name: impl Into<HeaderName>,
+        value: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            value: Arc::new(value),
+        }
+    }
+}
+
+impl Debug for InjectHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InjectHeader")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Middleware for InjectHeader {
+    async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+        if req.header(&self.name).is_none() {
+            if let Some(value) = (self.value)() {
+                req.insert_header(&self.name, value);
+            }
+        }
+        next.run(req).await
+    }
+}
+
+/// The W3C trace context of a request: the trace it's part of, and its parent span in it.
+///
+/// Insert one into the extensions of a request to send it as part of that trace, as
+/// `PropagateTrace` does once it has picked the context of a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    /// The ID of the trace, never 0.
+    pub trace_id: u128,
+    /// The ID of the span, never 0.
+    pub span_id: u64,
+    /// Whether the trace is being recorded.
+    pub sampled: bool,
+    /// The vendor-specific `tracestate` of the trace, if any.
+    pub state: Option<String>,
+}
+
+impl TraceContext {
+    /// Start a new, sampled, trace.
+    pub fn new() -> Self {
+        Self {
+            trace_id: loop {
+                let id = (u128::from(random_u64()) << 64) | u128::from(random_u64());
+                if id != 0 {
+                    break id;
+                }
+            },
+            span_id: random_span_id(),
+            sampled: true,
+            state: None,
+        }
+    }
+
+    /// Parse a `traceparent` header value, and the `tracestate` going with it, as received
+    /// with a request being handled, returning `None` if it's invalid.
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next().filter(|v| v.len() == 2 && *v != "ff")?;
+        let trace_id = parts.next().filter(|id| id.len() == 32)?;
+        let span_id = parts.next().filter(|id| id.len() == 16)?;
+        let flags = parts.next().filter(|flags| flags.len() == 2)?;
+        // Later versions may append fields, but version 00 has none.
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+        let hex = |s: &str| s.bytes().all(|b| b.is_ascii_hexdigit());
+        if !hex(version) || !hex(trace_id) || !hex(span_id) {
+            return None;
+        }
+        let context = Self {
+            trace_id: u128::from_str_radix(trace_id, 16).ok()?,
+            span_id: u64::from_str_radix(span_id, 16).ok()?,
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1,
+            state: tracestate.map(str::to_string),
+        };
+        (context.trace_id != 0 && context.span_id != 0).then_some(context)
+    }
+
+    /// The context of a new span within the same trace, as a child of this one.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_span_id(),
+            ..self.clone()
+        }
+    }
+
+    /// The `traceparent` header value telling this context.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+impl Default for TraceContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn random_u64() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    RandomState::new().build_hasher().finish()
+}
+
+fn random_span_id() -> u64 {
+    loop {
+        let id = random_u64();
+        if id != 0 {
+            break id;
+        }
+    }
+}
+
+/// Propagates the trace context of each request with the W3C `traceparent` and `tracestate`
+/// headers, and optionally the B3 headers of Zipkin, so the services it's sent to can join the
+/// trace.
+///
+/// Each request is sent as a new span, a child of the `TraceContext` found in its extensions,
+/// or else of the current one, as told by the function given to `PropagateTrace::current`,
+/// e.g. taking it from the current OpenTelemetry context. A request without either starts a new
+/// trace. The context it's sent with replaces the one in its extensions, and with the `tracing`
+/// feature, its trace ID is recorded on the span of the request. Requests which have a
+/// `traceparent` header of their own are left alone.
+#[derive(Clone)]
+pub struct PropagateTrace {
+    current: Arc<dyn Fn() -> Option<TraceContext> + Send + Sync>,
+    b3: bool,
+}
+
+impl PropagateTrace {
+    /// Propagate the trace contexts of requests, starting a new trace for requests without one.
+    pub fn new() -> Self {
+        Self {
+            current: Arc::new(|| None),
+            b3: false,
+        }
+    }
+
+    /// Take the context of requests without a `TraceContext` of their own from `current`.
+    pub fn current(
+        mut self,
+        current: impl Fn() -> Option<TraceContext> + Send + Sync + 'static,
+    ) -> Self {
+        self.current = Arc::new(current);
+        self
+    }
+
+    /// Send the `X-B3-TraceId`, `X-B3-SpanId` and `X-B3-Sampled` headers too.
+    pub fn b3(mut self, b3: bool) -> Self {
+        self.b3 = b3;
+        self
+    }
+}
+
+impl Default for PropagateTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for PropagateTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropagateTrace")
+            .field("b3", &self.b3)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Middleware for PropagateTrace {
+    async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+        if req.header("traceparent").is_some() {
+            return next.run(req).await;
+        }
+        let context = match req.ext().get::<TraceContext>() {
+            Some(parent) => parent.child(),
+            None => match (self.current)() {
+                Some(parent) => parent.child(),
+                None => TraceContext::new(),
+            },
+        };
+        req.insert_header("traceparent", context.traceparent());
+        if let Some(state) = &context.state {
+            req.insert_header("tracestate", state.as_str());
+        }
+        if self.b3 {
+            req.insert_header("X-B3-TraceId", format!("{:032x}", context.trace_id));
+            req.insert_header("X-B3-SpanId", format!("{:016x}", context.span_id));
+            req.insert_header("X-B3-Sampled", if context.sampled { "1" } else { "0" });
+        }
+        req.ext_mut().insert(context);
+        next.run(req).await
+    }
+}
+
+/// Sends only one of the GET requests for the same URL, with the same headers, which are in
+/// flight at the same time, and answers the others with a copy of its response once it has
+/// been read into memory, rather than sending them too. Its error, if it fails, is shared too,
+/// and still tells what failed, as by `h1::ErrorExt`.
+///
+/// Meant for many tasks fetching the same few resources at once, like map tiles or metadata,
+/// whose responses are small enough to buffer. Requests with a body, or one of unknown length,
+/// aren't coalesced, nor those
+/// sent once the shared request has been answered. Should the shared request be cancelled, the
+/// requests waiting for it are sent themselves. Add it before middleware which sets headers
+/// different for each request, which keep requests from being coalesced.
+#[derive(Clone, Debug, Default)]
+pub struct Coalesce {
+    flights: Arc<Mutex<HashMap<String, Arc<Flight>>>>,
+}
+
+impl Coalesce {
+    /// Coalesce concurrent GET requests for the same URL.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The key of the requests `req` can share a response with.
+    fn key(req: &Request) -> String {
+        let mut headers = req
+            .iter()
+            .map(|(name, values)| format!("{}: {}", name, values))
+            .collect::<Vec<_>>();
+        headers.sort();
+        format!("{}\n{}", req.url(), headers.join("\n"))
+    }
+}
+
+/// A request in flight, with those waiting for its response.
+#[derive(Debug, Default)]
+struct Flight(Mutex<Landing>);
+
+#[derive(Debug)]
+enum Landing {
+    Pending(Vec<Waker>),
+    Landed(Result<Shared, Arc<Error>>),
+    Abandoned,
+}
+
+impl Default for Landing {
+    fn default() -> Self {
+        Self::Pending(Vec::new())
+    }
+}
+
+impl Flight {
+    /// Answer those waiting, unless they've been answered already.
+    fn land(&self, landing: Landing) {
+        let mut state = self.0.lock().unwrap();
+        if let Landing::Pending(wakers) = &mut *state {
+            let wakers = std::mem::take(wakers);
+            *state = landing;
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+
+    /// Wait for the response, or for `None` if the request was cancelled.
+    async fn wait(&self) -> Option<Result<Shared, Arc<Error>>> {
+        future::poll_fn(|cx| match &mut *self.0.lock().unwrap() {
+            Landing::Pending(wakers) => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            Landing::Landed(result) => Poll::Ready(Some(result.clone())),
+            Landing::Abandoned => Poll::Ready(None),
+        })
+        .await
+    }
+}
+
+/// Ends a flight once its request is answered or cancelled, so later requests are sent anew.
+struct Departure<'a> {
+    coalesce: &'a Coalesce,
+    key: &'a str,
+    flight: &'a Flight,
+}
+
+impl Drop for Departure<'_> {
+    fn drop(&mut self) {
+        self.coalesce.flights.lock().unwrap().remove(self.key);
+        self.flight.land(Landing::Abandoned);
+    }
+}
+
+/// A response read into memory, to be copied for each request it answers.
+#[derive(Clone, Debug)]
+struct Shared {
+    status: StatusCode,
+    version: Option<Version>,
+    headers: Headers,
+    mime: Mime,
+    body: Vec<u8>,
+}
+
+impl Shared {
+    fn response(&self) -> Response {
+        let mut res = Response::new(self.status);
+        res.set_version(self.version);
+        *AsMut::<Headers>::as_mut(&mut res) = self.headers.clone();
+        let mut body = Body::from(self.body.clone());
+        body.set_mime(self.mime.clone());
+        res.set_body(body);
+        res
+    }
+}
+
+/// The error of a request coalesced with others, shared by each of them as it was, so what
+/// failed can still be told from it.
+#[derive(Clone, Debug)]
+pub(crate) struct SharedError(pub(crate) Arc<Error>);
+
+impl SharedError {
+    fn error(error: Arc<Error>) -> Error {
+        let status = error.status();
+        Error::new(status, Self(error))
+    }
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for SharedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        AsRef::<dyn std::error::Error>::as_ref(&*self.0).source()
+    }
+}
+
+#[async_trait]
+impl Middleware for Coalesce {
+    async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error> {
+        if req.method() != Method::Get || req.is_empty() != Some(true) {
+            return next.run(req).await;
+        }
+        let key = Self::key(&req);
+        let (flight, waiting) = {
+            let mut flights = self.flights.lock().unwrap();
+            match flights.get(&key) {
+                Some(flight) => (flight.clone(), true),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(key.clone(), flight.clone());
+                    (flight, false)
+                }
+            }
+        };
+        if waiting {
+            return match flight.wait().await {
+                Some(Ok(shared)) => Ok(shared.response()),
+                Some(Err(error)) => Err(SharedError::error(error)),
+                None => next.run(req).await,
+            };
+        }
+
+        let _departure = Departure {
+            coalesce: self,
+            key: &key,
+            flight: &flight,
+        };
+        let result = async {
+            let mut res = next.run(req).await?;
+            let (body, bytes) = read_body(res.take_body()).await?;
+            let shared = Shared {
+                status: res.status(),
+                version: res.version(),
+                headers: res.as_ref().clone(),
+                mime: body.mime().clone(),
+                body: bytes,
+            };
+            res.set_body(body);
+            Ok::<_, Error>((res, shared))
+        }
+        .await;
+        match result {
+            Ok((res, shared)) => {
+                flight.land(Landing::Landed(Ok(shared)));
+                Ok(res)
+            }
+            Err(e) => {
+                let error = Arc::new(e);
+                flight.land(Landing::Landed(Err(error.clone())));
+                Err(SharedError::error(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Client;
+    use http_types::StatusCode;
+
+    /// Answers every request with its `X-Trail` header.
+    #[derive(Debug)]
+    struct Trail;
+
+    #[async_trait]
+    impl HttpClient for Trail {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let mut res = Response::new(StatusCode::Ok);
+            let trail = req.header("X-Trail").map(|values| values.as_str());
+            res.set_body(trail.unwrap_or_default());
+            Ok(res)
+        }
+    }
+
+    /// Appends its name to `X-Trail` on the way in, and to the body on the way out.
+    #[derive(Debug)]
+    struct Mark(&'static str);
+
+    #[async_trait]
+    impl Middleware for Mark {
+        async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+            let trail = req
+                .header("X-Trail")
+                .map(|values| values.as_str().to_string());
+            req.insert_header(
+                "X-Trail",
+                format!("{}{}", trail.unwrap_or_default(), self.0),
+            );
+            let mut res = next.run(req).await?;
+            let body = res.body_string().await?;
+            res.set_body(format!("{}<{}", body, self.0));
+            Ok(res)
+        }
+    }
+
+    /// Answers every request with its headers.
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl HttpClient for Echo {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let mut res = Response::new(StatusCode::Ok);
+            for (name, values) in req.iter() {
+                res.insert_header(name, values);
+            }
+            Ok(res)
+        }
+    }
+
+    /// Answers without sending requests.
+    #[derive(Debug)]
+    struct Offline;
+
+    #[async_trait]
+    impl Middleware for Offline {
+        async fn handle(&self, _: Request, _: Next<'_>) -> Result<Response, Error> {
+            Ok(Response::new(StatusCode::ServiceUnavailable))
+        }
+    }
+
+    /// Answers every request with how many it has been sent, slowly.
+    #[derive(Debug, Default)]
+    struct Count(std::sync::atomic::AtomicUsize);
+
+    #[async_trait]
+    impl HttpClient for Count {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let sent = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            async_std::task::sleep(std::time::Duration::from_millis(50)).await;
+            if req.url().path() == "/refused" {
+                let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+                return Err(refused.into());
+            }
+            let mut res = Response::new(StatusCode::Ok);
+            res.insert_header("X-Count", sent.to_string());
+            res.set_body(sent.to_string());
+            Ok(res)
+        }
+    }
+
+    #[async_std::test]
+    async fn runs_in_order() -> Result<(), Error> {
+        let client = Client::builder()
+            .with(Mark("a"))
+            .with(Mark("b"))
+            .build_with(Trail);
+        let mut res = client.get("http://example.test/").send().await?;
+        assert_eq!(res.body_string().await?, "ab<b<a");
+
+        let client = Client::builder()
+            .with(Mark("a"))
+            .with(Offline)
+            .with(Mark("b"))
+            .build_with(Trail);
+        let res = client.get("http://example.test/").send().await?;
+        assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn injects_headers() -> Result<(), Error> {
+        let client = Client::builder()
+            .with(InjectHeader::new("X-Trail", || Some("token".to_string())))
+            .build_with(Trail);
+        let mut res = client.get("http://example.test/").send().await?;
+        assert_eq!(res.body_string().await?, "token");
+
+        let mut res = client
+            .get("http://example.test/")
+            .header("X-Trail", "own")
+            .send()
+            .await?;
+        assert_eq!(res.body_string().await?, "own");
+        Ok(())
+    }
+
+    #[test]
+    fn redacts_headers() {
+        let logger = Logger::new().redact_header("X-Api-Key");
+        let mut req = Request::get("http://example.test/");
+        req.insert_header("Authorization", "Bearer secret");
+        req.insert_header("x-api-key", "secret");
+        req.insert_header("Accept", "*/*");
+        let lines = logger.headers('>', req.as_ref());
+        assert!(!lines.contains("secret"), "{}", lines);
+        assert!(lines.contains("\n> authorization: [REDACTED]"), "{}", lines);
+        assert!(lines.contains("\n> x-api-key: [REDACTED]"), "{}", lines);
+        assert!(lines.contains("\n> accept: */*"), "{}", lines);
+    }
+
+    #[async_std::test]
+    async fn logs_bodies() -> Result<(), Error> {
+        let logger = Logger::new().log_bodies(5);
+        assert_eq!(
+            logger.body('<', b"hello ", Some(11)),
+            "\n<\n< hello... (6 more bytes)"
+        );
+        assert_eq!(logger.body('<', b"hello ", None), "\n<\n< hello...");
+        assert_eq!(logger.body('<', b"hello", None), "\n<\n< hello");
+        assert_eq!(logger.body('<', b"", Some(0)), "");
+        assert_eq!(Logger::new().verbose().body('<', b"hello", Some(5)), "");
+
+        // Bodies read to be logged are sent and returned all the same.
+        let mut req = Request::post("http://example.test/");
+        req.set_body(Body::from_json(&"hello")?);
+        logger.log_request(&mut req).await?;
+        assert_eq!(req.content_type(), Some(http_types::mime::JSON));
+        assert_eq!(req.body_string().await?, r#""hello""#);
+
+        let mut res = Response::new(StatusCode::Ok);
+        res.set_body("hello world");
+        logger.log_response(&mut res).await?;
+        assert_eq!(res.body_string().await?, "hello world");
+
+        // Only as much of a response body is read as is logged, and the rest is streamed.
+        let endless = io::Cursor::new("hello world").chain(io::repeat(b'a'));
+        let mut res = Response::new(StatusCode::Ok);
+        res.set_body(Body::from_reader(io::BufReader::new(endless), None));
+        logger.log_response(&mut res).await?;
+        let mut start = Vec::new();
+        res.take_body().take(15).read_to_end(&mut start).await?;
+        assert_eq!(start, b"hello worldaaaa");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn propagates_trace_context() -> Result<(), Error> {
+        let parent = TraceContext::parse(
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            Some("congo=t61rcWkgMzE"),
+        )
+        .unwrap();
+        assert_eq!(parent.trace_id, 0x0af7651916cd43dd8448eb211c80319c);
+        assert!(parent.sampled);
+        assert_eq!(
+            TraceContext::parse(
+                "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+                None
+            ),
+            None
+        );
+        assert_eq!(
+            TraceContext::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331", None),
+            None
+        );
+
+        let current = parent.clone();
+        let client = Client::builder()
+            .with(
+                PropagateTrace::new()
+                    .current(move || Some(current.clone()))
+                    .b3(true),
+            )
+            .build_with(Echo);
+        let res = client.get("http://example.test/").send().await?;
+        let traceparent = res["traceparent"].as_str();
+        assert!(traceparent.starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+        assert!(traceparent.ends_with("-01"));
+        assert_ne!(traceparent, parent.traceparent());
+        assert_eq!(res["tracestate"], "congo=t61rcWkgMzE");
+        assert_eq!(res["x-b3-traceid"], "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(res["x-b3-sampled"], "1");
+
+        // A request's own context comes first, and its own header is left alone.
+        let own = TraceContext::new();
+        let mut req = Request::get("http://example.test/");
+        req.ext_mut().insert(own.clone());
+        let res = client.send(req).await?;
+        let sent = TraceContext::parse(res["traceparent"].as_str(), None).unwrap();
+        assert_eq!(sent.trace_id, own.trace_id);
+        assert_ne!(sent.span_id, own.span_id);
+
+        let res = client
+            .get("http://example.test/")
+            .header("traceparent", parent.traceparent())
+            .send()
+            .await?;
+        assert_eq!(res["traceparent"], parent.traceparent().as_str());
+        assert!(res.header("x-b3-traceid").is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn coalesces_requests() -> Result<(), Error> {
+        let client = Client::builder()
+            .with(Coalesce::new())
+            .build_with(Count::default());
+        let get = |url| {
+            let client = client.clone();
+            async move {
+                let mut res = client.get(url).send().await?;
+                Ok::<_, Error>((
+                    res["x-count"].as_str().to_string(),
+                    res.body_string().await?,
+                ))
+            }
+        };
+        let (a, (b, c)) = future::zip(
+            get("http://example.test/a"),
+            future::zip(get("http://example.test/a"), get("http://example.test/b")),
+        )
+        .await;
+        let (a, b, c) = (a?, b?, c?);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a.0, a.1);
+
+        // Requests sent once the shared one is answered, or with a body, are sent themselves.
+        let (d, _) = get("http://example.test/a").await?;
+        assert_eq!(d, "3");
+        let res = future::zip(
+            client.post("http://example.test/a").body("x").send(),
+            client.post("http://example.test/a").body("x").send(),
+        )
+        .await;
+        assert_ne!(res.0?["x-count"].as_str(), res.1?["x-count"].as_str());
+
+        // A shared error is the same error for each request.
+        let (e, f) = future::zip(
+            client.get("http://example.test/refused").send(),
+            client.get("http://example.test/refused").send(),
+        )
+        .await;
+        let (e, f) = (e.unwrap_err(), f.unwrap_err());
+        assert_eq!(e.to_string(), f.to_string());
+        #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+        {
+            use crate::h1::ErrorExt;
+            assert!(e.is_retryable() && f.is_retryable());
+        }
+        let (g, _) = get("http://example.test/a").await?;
+        assert_eq!(g, "7");
+        Ok(())
+    }
+}
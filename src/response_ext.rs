@@ -0,0 +1,268 @@
+//! Conveniences for reading responses.
+
+use std::fmt::{self, Display};
+
+use futures_lite::AsyncReadExt;
+use http_types::{StatusCode, Url};
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+
+use crate::{async_trait, Error, Response};
+
+/// How much of the body of a response with an error status `error_for_status` keeps.
+const PREVIEW_LEN: u64 = 512;
+
+/// The largest response body `ResponseExt::json` reads into memory to deserialize.
+#[cfg(feature = "json")]
+const MAX_JSON_LEN: u64 = 16 * 1024 * 1024;
+
+/// The URL a response came from, after any redirects, as a response extension.
+///
+/// The h1 client and `Client` insert it into the extensions of the responses they return.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResponseUrl(pub Url);
+
+/// A response with a `4xx` or `5xx` status, as the source of the `Error` returned by
+/// `ResponseExt::error_for_status`. Get at it with `downcast_ref::<StatusError>()`.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusError {
+    /// The status of the response, which is also the status of the `Error`.
+    pub code: StatusCode,
+    /// The URL the response came from, if it's known by way of its `ResponseUrl`.
+    pub url: Option<Url>,
+    /// The start of the response body, decoded as UTF-8 with invalid sequences replaced, as
+    /// servers often explain errors in it.
+    pub body_preview: String,
+}
+
+impl Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            u16::from(self.code),
+            self.code.canonical_reason()
+        )?;
+        if let Some(url) = &self.url {
+            write!(f, " from {}", url)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Conveniences for reading a `Response`, from any `HttpClient`.
+///
+/// ```no_run
+/// # async fn fetch() -> http_types::Result<()> {
+/// use http_client::h1::H1Client;
+/// use http_client::{HttpClient, Request, ResponseExt};
+///
+/// let mut res = H1Client::new()
+///     .send(Request::get("https://example.com/users/1"))
+///     .await?
+///     .error_for_status()
+///     .await?;
+/// let user = res.body_string().await?;
+/// # Ok(()) }
+/// ```
+#[async_trait]
+pub trait ResponseExt: Sized {
+    /// Fail with a `StatusError` if the response has a `4xx` or `5xx` status, and return it as
+    /// it is otherwise. The `Error` has the response's status, so `Error::status` tells what it
+    /// was, and only the start of the body is read for `StatusError::body_preview`.
+    async fn error_for_status(self) -> Result<Self, Error>;
+
+    /// Read the body into memory, and deserialize it from JSON. A body over 16 MiB fails with a
+    /// `413 Payload Too Large` error, without being read past that, and one which isn't valid
+    /// JSON for `T` with a `422 Unprocessable Entity` error.
+    #[cfg_attr(feature = "docs", doc(cfg(json)))]
+    #[cfg(feature = "json")]
+    async fn json<T: DeserializeOwned>(&mut self) -> Result<T, Error>;
+
+    /// Read the body to its end as `Bytes`, which can be handed on, cloned and split with
+    /// `Bytes::slice` without copying the body again.
+    #[cfg_attr(feature = "docs", doc(cfg(h1_client)))]
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    async fn read_bytes(&mut self) -> Result<crate::h1::Bytes, Error>;
+
+    /// Take the connection a `101 Switching Protocols` response arrived on, to speak the
+    /// protocol switched to over, such as HTTP/2 after an `h2c` upgrade. Send the request with
+    /// the `Upgrade` and `Connection: Upgrade` headers the protocol asks for.
+    ///
+    /// Fails with a `502 Bad Gateway` error if the server didn't switch protocols, or the
+    /// response didn't come from the h1 client.
+    #[cfg_attr(feature = "docs", doc(cfg(h1_client)))]
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    fn into_upgraded(self) -> Result<crate::h1::Upgraded, Error>;
+
+    /// How long the request spent in each of its phases, if the response came from the h1
+    /// client. The time spent reading the body is only known once it has been read.
+    #[cfg_attr(feature = "docs", doc(cfg(h1_client)))]
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    fn timings(&self) -> Option<crate::h1::Timings>;
+}
+
+#[async_trait]
+impl ResponseExt for Response {
+    async fn error_for_status(mut self) -> Result<Self, Error> {
+        let code = self.status();
+        if !code.is_client_error() && !code.is_server_error() {
+            return Ok(self);
+        }
+        let mut preview = Vec::new();
+        // The body isn't needed beyond the preview, so failing to read it doesn't matter.
+        let _ = self
+            .take_body()
+            .take(PREVIEW_LEN)
+            .read_to_end(&mut preview)
+            .await;
+        let error = StatusError {
+            code,
+            url: self
+                .ext()
+                .get::<ResponseUrl>()
+                .map(|ResponseUrl(url)| url.clone()),
+            body_preview: String::from_utf8_lossy(&preview).into_owned(),
+        };
+        Err(Error::new(code, error))
+    }
+
+    #[cfg(feature = "json")]
+    async fn json<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let too_large = || {
+            let message = format!("response body is larger than {} bytes", MAX_JSON_LEN);
+            Error::from_str(StatusCode::PayloadTooLarge, message)
+        };
+        if self.len().is_some_and(|len| len as u64 > MAX_JSON_LEN) {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        self.take_body()
+            .take(MAX_JSON_LEN + 1)
+            .read_to_end(&mut body)
+            .await?;
+        if body.len() as u64 > MAX_JSON_LEN {
+            return Err(too_large());
+        }
+        serde_json::from_slice(&body).map_err(|e| Error::new(StatusCode::UnprocessableEntity, e))
+    }
+
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    async fn read_bytes(&mut self) -> Result<crate::h1::Bytes, Error> {
+        Ok(crate::h1::read_bytes(self.take_body()).await?)
+    }
+
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    fn into_upgraded(mut self) -> Result<crate::h1::Upgraded, Error> {
+        if self.status() != StatusCode::SwitchingProtocols {
+            return Err(Error::from_str(
+                StatusCode::BadGateway,
+                format!(
+                    "server didn't switch protocols, answering {}",
+                    self.status()
+                ),
+            ));
+        }
+        self.ext_mut()
+            .remove()
+            .ok_or_else(|| Error::from_str(StatusCode::BadGateway, "connection can't be upgraded"))
+    }
+
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    fn timings(&self) -> Option<crate::h1::Timings> {
+        let timings = self.ext().get::<crate::h1::RecordedTimings>()?;
+        Some(timings.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn fails_for_error_statuses() -> Result<(), Error> {
+        let res = Response::new(StatusCode::NotModified);
+        assert_eq!(
+            res.error_for_status().await?.status(),
+            StatusCode::NotModified
+        );
+
+        let mut res = Response::new(StatusCode::NotFound);
+        res.set_body("x".repeat(1000));
+        let url = Url::parse("https://example.test/missing").unwrap();
+        res.ext_mut().insert(ResponseUrl(url.clone()));
+        let err = res.error_for_status().await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotFound);
+        let status = err.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(status.url, Some(url));
+        assert_eq!(status.body_preview, "x".repeat(512));
+        assert_eq!(
+            status.to_string(),
+            "404 Not Found from https://example.test/missing"
+        );
+        Ok(())
+    }
+
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    #[async_std::test]
+    async fn reads_bytes() -> Result<(), Error> {
+        use crate::h1::{bytes_body, Bytes};
+        use crate::Body;
+        use futures_lite::io::Cursor;
+
+        let bytes = Bytes::from_static(b"hello world");
+        let mut res = Response::new(StatusCode::Ok);
+        res.set_body(bytes_body(bytes.slice(6..)));
+        assert_eq!(res.len(), Some(5));
+        assert_eq!(res.read_bytes().await?, "world");
+        let mut res = Response::new(StatusCode::Ok);
+        res.set_body(Body::from_reader(Cursor::new(b"hello".to_vec()), None));
+        assert_eq!(res.read_bytes().await?, "hello");
+        Ok(())
+    }
+
+    #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))]
+    #[async_std::test]
+    async fn takes_upgraded_connections() -> Result<(), Error> {
+        use crate::h1::Upgraded;
+        use futures_lite::io::Cursor;
+
+        let err = Response::new(StatusCode::Ok).into_upgraded().unwrap_err();
+        assert_eq!(err.status(), StatusCode::BadGateway);
+        let res = Response::new(StatusCode::SwitchingProtocols);
+        assert!(res.into_upgraded().is_err());
+
+        let mut res = Response::new(StatusCode::SwitchingProtocols);
+        let stream = Cursor::new(b"rest".to_vec());
+        res.ext_mut()
+            .insert(Upgraded::new(b"head ".to_vec(), Box::new(stream)));
+        let mut read = String::new();
+        res.into_upgraded()?.read_to_string(&mut read).await?;
+        assert_eq!(read, "head rest");
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[async_std::test]
+    async fn decodes_json() -> Result<(), Error> {
+        let mut res = Response::new(StatusCode::Ok);
+        res.set_body(r#"{"id": 1, "name": "Jane"}"#);
+        let user: serde_json::Value = res.json().await?;
+        assert_eq!(user["name"], "Jane");
+
+        let mut res = Response::new(StatusCode::Ok);
+        res.set_body("not json");
+        let err = res.json::<serde_json::Value>().await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::UnprocessableEntity);
+
+        let mut res = Response::new(StatusCode::Ok);
+        let endless = futures_lite::io::BufReader::new(futures_lite::io::repeat(b' '));
+        res.set_body(crate::Body::from_reader(endless, None));
+        let err = res.json::<serde_json::Value>().await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::PayloadTooLarge);
+        Ok(())
+    }
+}
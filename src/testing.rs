@@ -0,0 +1,260 @@
+//! A mock `HttpClient`, answering requests with canned responses and errors, to test the code
+//! sending them without a network, down to its error paths.
+//!
+//! ```
+//! # async_std::task::block_on(async {
+//! use http_client::testing::{Mock, MockClient};
+//! use http_client::{Client, Error, HttpClient, Request};
+//! use http_types::{Method, StatusCode};
+//!
+//! let mock = MockClient::new();
+//! mock.mock(
+//!     Mock::new(Method::Get, "/users/1")
+//!         .header("Accept", "application/json")
+//!         .respond(StatusCode::Ok, r#"{"id":1}"#),
+//! );
+//! mock.mock(
+//!     Mock::new(Method::Get, "/users/2")
+//!         .fail(|| Error::from_str(StatusCode::BadGateway, "connection reset")),
+//! );
+//!
+//! let client = Client::with_http_client(mock.clone());
+//! let mut req = Request::get("https://api.example.com/users/1");
+//! req.insert_header("Accept", "application/json");
+//! let mut res = client.send(req).await?;
+//! assert_eq!(res.body_string().await?, r#"{"id":1}"#);
+//! assert!(client.send(Request::get("https://api.example.com/users/2")).await.is_err());
+//!
+//! mock.verify();
+//! # http_types::Result::Ok(())
+//! # }).unwrap();
+//! ```
+
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+
+use http_types::headers::HeaderName;
+use http_types::{Body, Method, StatusCode};
+
+use crate::{async_trait, Error, HttpClient, Request, Response};
+
+type Reply = dyn Fn(&Request) -> Result<Response, Error> + Send + Sync;
+
+/// An `HttpClient` answering each request with the first of its mocks which matches it, or
+/// failing with a `501 Not Implemented` error if none does.
+///
+/// Clones share their mocks, so one can be handed to a `Client` and the other kept to `verify`.
+#[derive(Clone, Debug, Default)]
+pub struct MockClient {
+    mocks: Arc<Mutex<Vec<Mock>>>,
+}
+
+impl MockClient {
+    /// Create a client without any mocks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the requests `mock` matches. Mocks added earlier are tried first.
+    pub fn mock(&self, mock: Mock) -> &Self {
+        self.mocks.lock().unwrap().push(mock);
+        self
+    }
+
+    /// Check each mock was matched as often as expected: exactly as set with `Mock::times`, or
+    /// else at least once.
+    ///
+    /// # Panics
+    ///
+    /// If any wasn't, listing them.
+    pub fn verify(&self) {
+        let unmet = self
+            .mocks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|mock| match mock.times {
+                Some(times) => mock.hits != times,
+                None => mock.hits == 0,
+            })
+            .map(|mock| format!("{:?}", mock))
+            .collect::<Vec<_>>();
+        assert!(unmet.is_empty(), "unmet mocks: {}", unmet.join(", "));
+    }
+}
+
+#[async_trait]
+impl HttpClient for MockClient {
+    async fn send(&self, req: Request) -> Result<Response, Error> {
+        let mut mocks = self.mocks.lock().unwrap();
+        let mock = mocks.iter_mut().find(|mock| mock.matches(&req));
+        match mock {
+            Some(mock) => {
+                mock.hits += 1;
+                (mock.reply)(&req)
+            }
+            None => Err(Error::from_str(
+                StatusCode::NotImplemented,
+                format!("no mock matches {} {}", req.method(), req.url()),
+            )),
+        }
+    }
+}
+
+/// What requests to match, and how to answer them: with a `200 OK` and an empty body, unless
+/// told otherwise.
+pub struct Mock {
+    method: Method,
+    path: String,
+    headers: Vec<(HeaderName, String)>,
+    reply: Box<Reply>,
+    times: Option<usize>,
+    hits: usize,
+}
+
+impl Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mock")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .field("headers", &self.headers)
+            .field("times", &self.times)
+            .field("hits", &self.hits)
+            .finish()
+    }
+}
+
+impl Mock {
+    /// Match `method` requests to `path`, whatever their host. A `path` with a `?` matches the
+    /// query too.
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            headers: Vec::new(),
+            reply: Box::new(|_| Ok(Response::new(StatusCode::Ok))),
+            times: None,
+            hits: 0,
+        }
+    }
+
+    /// Only match requests with `value` as one of their `name` headers.
+    pub fn header(mut self, name: impl Into<HeaderName>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Match requests `times` times at most, and expect that many of them when verifying.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// Answer with `status`, and `body` as its body.
+    pub fn respond(self, status: StatusCode, body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+        self.respond_with(move |_| {
+            let mut res = Response::new(status);
+            res.set_body(Body::from(body.clone()));
+            res
+        })
+    }
+
+    /// Answer with the response `reply` makes of each request.
+    pub fn respond_with<F>(mut self, reply: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.reply = Box::new(move |req| Ok(reply(req)));
+        self
+    }
+
+    /// Fail each request, with the error `error` makes, as if sending it had failed.
+    pub fn fail<F>(mut self, error: F) -> Self
+    where
+        F: Fn() -> Error + Send + Sync + 'static,
+    {
+        self.reply = Box::new(move |_| Err(error()));
+        self
+    }
+
+    fn matches(&self, req: &Request) -> bool {
+        let url = req.url();
+        let path_matches = match self.path.split_once('?') {
+            Some((path, query)) => url.path() == path && url.query() == Some(query),
+            None => url.path() == self.path,
+        };
+        req.method() == self.method
+            && path_matches
+            && self.times.is_none_or(|times| self.hits < times)
+            && self.headers.iter().all(|(name, value)| {
+                req.header(name)
+                    .is_some_and(|values| values.iter().any(|v| v.as_str() == value))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn matches_requests() -> Result<(), Error> {
+        let mock = MockClient::new();
+        mock.mock(
+            Mock::new(Method::Post, "/items?draft=true")
+                .header("Content-Type", "application/json")
+                .times(1)
+                .respond(StatusCode::Created, "draft"),
+        )
+        .mock(Mock::new(Method::Post, "/items").respond_with(|req| {
+            let mut res = Response::new(StatusCode::Accepted);
+            res.set_body(req.url().as_str());
+            res
+        }));
+
+        let mut req = Request::post("http://example.test/items?draft=true");
+        req.insert_header("Content-Type", "application/json");
+        let mut res = mock.send(req).await?;
+        assert_eq!(res.status(), StatusCode::Created);
+        assert_eq!(res.body_string().await?, "draft");
+
+        // The first mock is used up, and the second doesn't look at the query.
+        let mut req = Request::post("http://example.test/items?draft=true");
+        req.insert_header("Content-Type", "application/json");
+        let mut res = mock.send(req).await?;
+        assert_eq!(res.status(), StatusCode::Accepted);
+        assert_eq!(
+            res.body_string().await?,
+            "http://example.test/items?draft=true"
+        );
+
+        let err = mock
+            .send(Request::get("http://example.test/items"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+        mock.verify();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn fails_and_verifies() {
+        let mock = MockClient::new();
+        mock.mock(
+            Mock::new(Method::Get, "/")
+                .fail(|| Error::from_str(StatusCode::GatewayTimeout, "timed out")),
+        )
+        .mock(Mock::new(Method::Delete, "/"));
+
+        let err = mock
+            .send(Request::get("http://example.test/"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::GatewayTimeout);
+
+        let unmet = std::panic::catch_unwind(|| mock.verify()).unwrap_err();
+        let message = unmet.downcast_ref::<String>().unwrap();
+        assert!(message.contains("Delete"), "{}", message);
+    }
+}
@@ -0,0 +1,65 @@
+//! `Client` as a `tower::Service`, to wrap in its layers and hand to frameworks expecting one.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tower_service::Service;
+
+use crate::{Body, Client, Error, HttpClientExt};
+
+/// Sends `http` crate requests, like `HttpClientExt::send_http`. The client is always ready,
+/// as requests wait for a connection of their own once called, and each call sends its request
+/// with a clone of the client, so the future it returns doesn't borrow it.
+impl<B> Service<http::Request<B>> for Client
+where
+    B: Into<Body> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<http::Response<Body>, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let client = self.clone();
+        Box::pin(async move { client.send_http(req).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_trait, HttpClient, Request, Response};
+    use futures_lite::future::poll_fn;
+    use http_types::StatusCode;
+
+    /// Answers every request with its URL.
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl HttpClient for Echo {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(req.url().as_str());
+            Ok(res)
+        }
+    }
+
+    #[async_std::test]
+    async fn serves_http_requests() -> Result<(), Error> {
+        let mut service = Client::with_http_client(Echo);
+        poll_fn(|cx| Service::<http::Request<Body>>::poll_ready(&mut service, cx)).await?;
+        let req = http::Request::get("http://example.test/a").body(Body::empty())?;
+        let res = service.call(req).await?;
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(
+            res.into_body().into_string().await?,
+            "http://example.test/a"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! Recording exchanges with real servers to cassette files, and replaying them in tests, so they
+//! run without a network, and the same every time.
+//!
+//! ```no_run
+//! # async fn fetch() -> http_types::Result<()> {
+//! use http_client::vcr::{Mode, Vcr};
+//! use http_client::{Client, HttpClient, Request};
+//!
+//! // Recorded the first time it's run, and replayed from then on.
+//! let vcr = Vcr::new("tests/cassettes/users.json", Mode::Auto)?.redact_header("Authorization");
+//! let client = Client::builder().with(vcr).build();
+//! let res = client.send(Request::get("https://api.example.com/users")).await?;
+//! # Ok(()) }
+//! ```
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use http_types::headers::{HeaderName, Headers};
+use http_types::{Method, StatusCode, Url};
+use serde_json::{json, Map, Value};
+
+use crate::middleware::{Middleware, Next};
+use crate::{async_trait, Body, Error, Request, Response};
+
+/// What the recorded value of redacted headers is replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether a `Vcr` answers requests from its cassette, or sends them and records the exchange.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Send every request, recording each exchange in a cassette started afresh.
+    Record,
+    /// Answer requests from the cassette only, failing those it has no exchange for.
+    Replay,
+    /// Answer requests from the cassette, sending and recording those it has no exchange for.
+    Auto,
+}
+
+/// Middleware recording exchanges to a cassette file, a JSON array of them, and replaying them.
+///
+/// A request is answered by the first exchange in the cassette with its method and URL which
+/// hasn't answered one yet, so a request made twice gets the two responses recorded for it, in
+/// order. Headers and bodies aren't compared. Bodies are read whole, to record them.
+///
+/// As middleware, it sits in front of the client sending requests, and its connection pools:
+/// replayed requests never reach them.
+#[derive(Debug)]
+pub struct Vcr {
+    path: PathBuf,
+    mode: Mode,
+    redacted: Vec<HeaderName>,
+    cassette: Mutex<Cassette>,
+}
+
+#[derive(Debug, Default)]
+struct Cassette {
+    exchanges: Vec<Exchange>,
+    played: HashSet<usize>,
+}
+
+#[derive(Debug)]
+struct Exchange {
+    method: Method,
+    url: Url,
+    request_headers: Vec<(String, String)>,
+    request_body: Vec<u8>,
+    status: StatusCode,
+    response_headers: Vec<(String, String)>,
+    response_body: Vec<u8>,
+}
+
+impl Vcr {
+    /// Record to and replay from the cassette at `path`, loading it if it exists, unless
+    /// recording afresh with `Mode::Record`. Fails if it can't be read or isn't a cassette.
+    pub fn new(path: impl AsRef<Path>, mode: Mode) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let exchanges = match (mode, fs::read(&path)) {
+            (Mode::Record, _) => Vec::new(),
+            (_, Ok(cassette)) => parse(&cassette)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid cassette"))?,
+            (Mode::Auto, Err(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            (_, Err(e)) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            mode,
+            redacted: Vec::new(),
+            cassette: Mutex::new(Cassette {
+                exchanges,
+                played: HashSet::new(),
+            }),
+        })
+    }
+
+    /// Record the values of `name` headers, on requests and responses, as `[REDACTED]`, e.g. to
+    /// keep credentials out of cassettes.
+    pub fn redact_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.redacted.push(name.into());
+        self
+    }
+
+    /// Take the response of the first exchange for `req` not played yet.
+    fn replay(&self, req: &Request) -> Option<Response> {
+        let mut cassette = self.cassette.lock().unwrap();
+        let Cassette { exchanges, played } = &mut *cassette;
+        let (idx, exchange) = exchanges.iter().enumerate().find(|(idx, exchange)| {
+            !played.contains(idx) && exchange.method == req.method() && exchange.url == *req.url()
+        })?;
+        played.insert(idx);
+        let mut res = Response::new(exchange.status);
+        for (name, value) in &exchange.response_headers {
+            res.append_header(name.as_str(), value.as_str());
+        }
+        res.set_body(Body::from(exchange.response_body.clone()));
+        Some(res)
+    }
+
+    /// Add `exchange` to the cassette, and write it out.
+    fn record(&self, exchange: Exchange) -> io::Result<()> {
+        let mut cassette = self.cassette.lock().unwrap();
+        let recorded = cassette.exchanges.len();
+        cassette.played.insert(recorded);
+        cassette.exchanges.push(exchange);
+        let exchanges = cassette.exchanges.iter().map(to_json).collect();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_vec_pretty(&Value::Array(exchanges))?;
+        fs::write(&self.path, json)
+    }
+
+    fn headers(&self, headers: &Headers) -> Vec<(String, String)> {
+        let mut recorded = Vec::new();
+        for (name, values) in headers.iter() {
+            let redacted = self.redacted.contains(name);
+            for value in values {
+                let value = if redacted { REDACTED } else { value.as_str() };
+                recorded.push((name.to_string(), value.to_string()));
+            }
+        }
+        recorded
+    }
+}
+
+#[async_trait]
+impl Middleware for Vcr {
+    async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+        if self.mode != Mode::Record {
+            if let Some(res) = self.replay(&req) {
+                return Ok(res);
+            }
+        }
+        if self.mode == Mode::Replay {
+            return Err(Error::from_str(
+                StatusCode::NotImplemented,
+                format!("no recorded exchange for {} {}", req.method(), req.url()),
+            ));
+        }
+
+        let request_body = req.take_body().into_bytes().await?;
+        req.set_body(Body::from(request_body.clone()));
+        let method = req.method();
+        let url = req.url().clone();
+        let request_headers = self.headers(req.as_ref());
+
+        let mut res = next.run(req).await?;
+        let response_body = res.take_body().into_bytes().await?;
+        res.set_body(Body::from(response_body.clone()));
+        self.record(Exchange {
+            method,
+            url,
+            request_headers,
+            request_body,
+            status: res.status(),
+            response_headers: self.headers(res.as_ref()),
+            response_body,
+        })?;
+        Ok(res)
+    }
+}
+
+fn to_json(exchange: &Exchange) -> Value {
+    json!({
+        "request": {
+            "method": exchange.method.to_string(),
+            "url": exchange.url.as_str(),
+            "headers": headers_to_json(&exchange.request_headers),
+            "body": body_to_json(&exchange.request_body),
+        },
+        "response": {
+            "status": u16::from(exchange.status),
+            "headers": headers_to_json(&exchange.response_headers),
+            "body": body_to_json(&exchange.response_body),
+        },
+    })
+}
+
+fn headers_to_json(headers: &[(String, String)]) -> Value {
+    headers
+        .iter()
+        .map(|(name, value)| json!([name, value]))
+        .collect()
+}
+
+/// Bodies are recorded as a string if they're UTF-8, and as an array of bytes otherwise.
+fn body_to_json(body: &[u8]) -> Value {
+    match std::str::from_utf8(body) {
+        Ok(text) => Value::from(text),
+        Err(_) => Value::from(body.to_vec()),
+    }
+}
+
+fn parse(cassette: &[u8]) -> Option<Vec<Exchange>> {
+    let cassette: Value = serde_json::from_slice(cassette).ok()?;
+    cassette.as_array()?.iter().map(parse_exchange).collect()
+}
+
+fn parse_exchange(exchange: &Value) -> Option<Exchange> {
+    let req = exchange.get("request")?.as_object()?;
+    let res = exchange.get("response")?.as_object()?;
+    let status = res.get("status")?.as_u64()?;
+    Some(Exchange {
+        method: req.get("method")?.as_str()?.parse().ok()?,
+        url: Url::parse(req.get("url")?.as_str()?).ok()?,
+        request_headers: parse_headers(req)?,
+        request_body: parse_body(req)?,
+        status: StatusCode::try_from(u16::try_from(status).ok()?).ok()?,
+        response_headers: parse_headers(res)?,
+        response_body: parse_body(res)?,
+    })
+}
+
+fn parse_headers(message: &Map<String, Value>) -> Option<Vec<(String, String)>> {
+    let parse_header = |header: &Value| {
+        let header = header.as_array()?;
+        match header.as_slice() {
+            [name, value] => Some((name.as_str()?.to_string(), value.as_str()?.to_string())),
+            _ => None,
+        }
+    };
+    message
+        .get("headers")?
+        .as_array()?
+        .iter()
+        .map(parse_header)
+        .collect()
+}
+
+fn parse_body(message: &Map<String, Value>) -> Option<Vec<u8>> {
+    match message.get("body")? {
+        Value::String(text) => Some(text.clone().into_bytes()),
+        Value::Array(bytes) => bytes
+            .iter()
+            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Client, HttpClient};
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Answers every request with its URL and how many it answered before, and a token.
+    #[derive(Debug, Default)]
+    struct Counter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl HttpClient for Counter {
+        async fn send(&self, req: Request) -> Result<Response, Error> {
+            let count = self.0.fetch_add(1, Ordering::SeqCst);
+            let mut res = Response::new(StatusCode::Ok);
+            res.insert_header("Set-Cookie", "session=secret");
+            res.set_body(format!("{} {}", req.url(), count));
+            Ok(res)
+        }
+    }
+
+    fn cassette_path() -> PathBuf {
+        let id = RandomState::new().build_hasher().finish();
+        std::env::temp_dir().join(format!("http-client-vcr-{:x}.json", id))
+    }
+
+    async fn get(client: &Client, url: &str) -> Result<String, Error> {
+        let mut req = Request::get(url);
+        req.insert_header("Authorization", "Bearer secret");
+        client.send(req).await?.body_string().await
+    }
+
+    #[async_std::test]
+    async fn records_and_replays() -> Result<(), Error> {
+        let path = cassette_path();
+        let sent = Arc::new(AtomicUsize::new(0));
+        let vcr = Vcr::new(&path, Mode::Record)?
+            .redact_header("Authorization")
+            .redact_header("Set-Cookie");
+        let client = Client::builder()
+            .with(vcr)
+            .build_with(Counter(sent.clone()));
+        assert_eq!(
+            get(&client, "http://example.test/a").await?,
+            "http://example.test/a 0"
+        );
+        assert_eq!(
+            get(&client, "http://example.test/a").await?,
+            "http://example.test/a 1"
+        );
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+
+        let cassette = fs::read_to_string(&path)?;
+        assert!(!cassette.contains("secret"), "{}", cassette);
+
+        // Replayed in order, without sending anything.
+        let client = Client::builder()
+            .with(Vcr::new(&path, Mode::Replay)?)
+            .build_with(Counter(sent.clone()));
+        assert_eq!(
+            get(&client, "http://example.test/a").await?,
+            "http://example.test/a 0"
+        );
+        let mut res = client.send(Request::get("http://example.test/a")).await?;
+        assert_eq!(res["set-cookie"], REDACTED);
+        assert_eq!(res.body_string().await?, "http://example.test/a 1");
+        let err = client
+            .send(Request::get("http://example.test/b"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+
+        // New requests are recorded on top of the others.
+        let client = Client::builder()
+            .with(Vcr::new(&path, Mode::Auto)?)
+            .build_with(Counter(sent.clone()));
+        assert_eq!(
+            get(&client, "http://example.test/a").await?,
+            "http://example.test/a 0"
+        );
+        assert_eq!(
+            get(&client, "http://example.test/b").await?,
+            "http://example.test/b 2"
+        );
+        assert_eq!(parse(&fs::read(&path)?).unwrap().len(), 3);
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_binary_bodies() {
+        let exchange = Exchange {
+            method: Method::Post,
+            url: Url::parse("http://example.test/").unwrap(),
+            request_headers: vec![("content-type".into(), "image/png".into())],
+            request_body: vec![0x89, b'P', b'N', b'G'],
+            status: StatusCode::Created,
+            response_headers: Vec::new(),
+            response_body: b"created".to_vec(),
+        };
+        let cassette = serde_json::to_vec(&Value::Array(vec![to_json(&exchange)])).unwrap();
+        let parsed = parse(&cassette).unwrap();
+        assert_eq!(parsed[0].request_body, exchange.request_body);
+        assert_eq!(parsed[0].request_headers, exchange.request_headers);
+        assert_eq!(parsed[0].response_body, exchange.response_body);
+        assert_eq!(parsed[0].status, StatusCode::Created);
+    }
+}
@@ -8,7 +8,10 @@ use std::convert::TryFrom;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-/// WebAssembly HTTP Client.
+/// WebAssembly HTTP Client, sending requests with the Fetch API of the browser or web worker it
+/// runs in.
+///
+/// It's what a `Client` sends requests with on `wasm32`, given the `wasm` feature.
 #[derive(Debug)]
 pub struct WasmClient {
     _priv: (),
@@ -21,6 +24,12 @@ impl WasmClient {
     }
 }
 
+impl Default for WasmClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HttpClient for WasmClient {
     fn send<'a, 'async_trait>(
         &'a self,
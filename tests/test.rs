@@ -8,7 +8,7 @@ use cfg_if::cfg_if;
 cfg_if! {
     if #[cfg(feature = "curl_client")] {
         use http_client::isahc::IsahcClient as DefaultClient;
-    } else if #[cfg(feature = "wasm_client")] {
+    } else if #[cfg(all(feature = "wasm_client", target_arch = "wasm32"))] {
         use http_client::wasm::WasmClient as DefaultClient;
     } else if #[cfg(any(feature = "h1_client", feature = "h1_client_rustls"))] {
         use http_client::h1::H1Client as DefaultClient;